@@ -0,0 +1,43 @@
+-- Citizen resolution review
+-- A ticket is never closed by whoever reports progress. Once work is reported
+-- as done the ticket waits in 'pending_review' until linked citizens accept it.
+
+ALTER TABLE tickets DROP CONSTRAINT IF EXISTS tickets_status_check;
+ALTER TABLE tickets ADD CONSTRAINT tickets_status_check CHECK (status IN (
+    'open', 'assigned', 'in_progress', 'pending', 'pending_review',
+    'resolved', 'closed', 'reopened'
+));
+
+-- One row per review round of a ticket
+CREATE TABLE ticket_resolution_reviews (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticket_id UUID NOT NULL REFERENCES tickets(id) ON DELETE CASCADE,
+    round INTEGER NOT NULL DEFAULT 1,
+    summary TEXT,
+    requested_by UUID REFERENCES users(id),
+
+    -- Fraction of eligible citizens that must accept, frozen when the round opens
+    quorum DECIMAL(3, 2) NOT NULL DEFAULT 0.5,
+
+    status VARCHAR(20) DEFAULT 'open' CHECK (status IN ('open', 'accepted', 'rejected')),
+    opened_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    closed_at TIMESTAMP WITH TIME ZONE,
+
+    UNIQUE (ticket_id, round)
+);
+
+-- Citizen votes on a review round
+CREATE TABLE ticket_resolution_votes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    review_id UUID NOT NULL REFERENCES ticket_resolution_reviews(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),
+    vote VARCHAR(10) NOT NULL CHECK (vote IN ('accept', 'reject')),
+    reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+
+    UNIQUE (review_id, user_id)
+);
+
+-- Only one open round per ticket
+CREATE UNIQUE INDEX idx_resolution_reviews_open ON ticket_resolution_reviews(ticket_id) WHERE status = 'open';
+CREATE INDEX idx_resolution_votes_review_id ON ticket_resolution_votes(review_id);
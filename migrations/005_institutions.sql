@@ -0,0 +1,47 @@
+-- Government institutions (national down to district level)
+CREATE TABLE institutions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    level VARCHAR(20) NOT NULL CHECK (level IN ('national', 'provincial', 'regency', 'district')),
+    parent_id UUID REFERENCES institutions(id),
+    description TEXT,
+
+    -- Jurisdiction; a NULL bounding box means no geographic restriction
+    min_latitude DECIMAL(10, 8),
+    max_latitude DECIMAL(10, 8),
+    min_longitude DECIMAL(11, 8),
+    max_longitude DECIMAL(11, 8),
+
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    is_active BOOLEAN DEFAULT true
+);
+
+-- Officials acting on behalf of an institution
+CREATE TABLE institution_members (
+    institution_id UUID NOT NULL REFERENCES institutions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    member_role VARCHAR(20) DEFAULT 'member' CHECK (member_role IN ('member', 'admin')),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (institution_id, user_id)
+);
+
+-- Category mandate; an institution without rows here may pick any category
+CREATE TABLE institution_categories (
+    institution_id UUID NOT NULL REFERENCES institutions(id) ON DELETE CASCADE,
+    category_id UUID NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    PRIMARY KEY (institution_id, category_id)
+);
+
+-- Adoption of clusters from the problem shop
+ALTER TABLE report_clusters ADD COLUMN adopted_by UUID REFERENCES institutions(id);
+ALTER TABLE report_clusters ADD COLUMN adopted_at TIMESTAMP WITH TIME ZONE;
+
+ALTER TABLE tickets ADD COLUMN assigned_institution_id UUID REFERENCES institutions(id);
+
+CREATE INDEX idx_institution_members_user_id ON institution_members(user_id);
+CREATE INDEX idx_report_clusters_adopted_by ON report_clusters(adopted_by);
+CREATE INDEX idx_tickets_assigned_institution_id ON tickets(assigned_institution_id);
+
+CREATE TRIGGER update_institutions_updated_at BEFORE UPDATE ON institutions FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,33 @@
+-- Progress reported by institutions on adopted tickets
+CREATE TABLE ticket_progress_updates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticket_id UUID NOT NULL REFERENCES tickets(id),
+    institution_id UUID NOT NULL REFERENCES institutions(id),
+    posted_by UUID NOT NULL REFERENCES users(id),
+
+    stage VARCHAR(20) NOT NULL CHECK (stage IN (
+        'surveyed', 'budgeted', 'work_started', 'work_finished'
+    )),
+    percentage SMALLINT NOT NULL DEFAULT 0 CHECK (percentage BETWEEN 0 AND 100),
+    expected_completion_date DATE,
+    note TEXT,
+
+    -- Evidence (photo / document references)
+    attachments JSONB DEFAULT '[]',
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_ticket_progress_updates_ticket_id ON ticket_progress_updates(ticket_id, created_at);
+
+-- Progress is a public record; entries can only be appended
+CREATE OR REPLACE FUNCTION reject_progress_update_mutation()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'ticket_progress_updates is append-only';
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER ticket_progress_updates_append_only
+    BEFORE UPDATE OR DELETE ON ticket_progress_updates
+    FOR EACH ROW EXECUTE FUNCTION reject_progress_update_mutation();
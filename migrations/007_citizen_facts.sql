@@ -0,0 +1,40 @@
+-- Facts and evidence contributed by any citizen on a report or cluster
+CREATE TABLE citizen_facts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID REFERENCES reports(id) ON DELETE CASCADE,
+    cluster_id UUID REFERENCES report_clusters(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),
+
+    content TEXT NOT NULL,
+    photo_url TEXT,
+    latitude DECIMAL(10, 8),
+    longitude DECIMAL(11, 8),
+    observed_date TIMESTAMP WITH TIME ZONE,
+
+    -- Moderation
+    status VARCHAR(20) DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    moderation_note TEXT,
+    moderated_by UUID REFERENCES users(id),
+    moderated_at TIMESTAMP WITH TIME ZONE,
+
+    -- "I confirm this too"
+    confirmation_count INTEGER DEFAULT 0,
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+
+    CHECK ((report_id IS NULL) <> (cluster_id IS NULL))
+);
+
+CREATE TABLE citizen_fact_confirmations (
+    fact_id UUID NOT NULL REFERENCES citizen_facts(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (fact_id, user_id)
+);
+
+CREATE INDEX idx_citizen_facts_report_id ON citizen_facts(report_id) WHERE report_id IS NOT NULL;
+CREATE INDEX idx_citizen_facts_cluster_id ON citizen_facts(cluster_id) WHERE cluster_id IS NOT NULL;
+CREATE INDEX idx_citizen_facts_status ON citizen_facts(status);
+
+CREATE TRIGGER update_citizen_facts_updated_at BEFORE UPDATE ON citizen_facts FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,39 @@
+-- Indonesian administrative regions (Kemendagri codes)
+-- provinsi "33", kabupaten/kota "33.74", kecamatan "33.74.01", kelurahan/desa "33.74.01.1001"
+CREATE TABLE regions (
+    code VARCHAR(13) PRIMARY KEY,
+    parent_code VARCHAR(13) REFERENCES regions(code),
+    name VARCHAR(255) NOT NULL,
+    level VARCHAR(20) NOT NULL CHECK (level IN ('province', 'regency', 'district', 'village')),
+    centroid_latitude DECIMAL(10, 8),
+    centroid_longitude DECIMAL(11, 8),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_regions_parent_code ON regions(parent_code);
+CREATE INDEX idx_regions_level ON regions(level);
+CREATE INDEX idx_regions_name_trgm ON regions USING gin(name gin_trgm_ops);
+CREATE INDEX idx_regions_centroid ON regions(centroid_latitude, centroid_longitude) WHERE centroid_latitude IS NOT NULL;
+
+CREATE TRIGGER update_regions_updated_at BEFORE UPDATE ON regions FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Village boundaries for point-in-polygon lookup, only when PostGIS
+-- from 002_optional_postgis.sql is available
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis') THEN
+        ALTER TABLE regions ADD COLUMN IF NOT EXISTS boundary geometry(MultiPolygon, 4326);
+        CREATE INDEX IF NOT EXISTS idx_regions_boundary ON regions USING GIST(boundary);
+    END IF;
+END
+$$;
+
+ALTER TABLE reports ADD COLUMN region_code VARCHAR(13) REFERENCES regions(code);
+ALTER TABLE report_clusters ADD COLUMN region_code VARCHAR(13) REFERENCES regions(code);
+
+-- Jurisdiction by region; the code covers every region beneath it
+ALTER TABLE institutions ADD COLUMN region_code VARCHAR(13) REFERENCES regions(code);
+
+CREATE INDEX idx_reports_region_code ON reports(region_code varchar_pattern_ops);
+CREATE INDEX idx_report_clusters_region_code ON report_clusters(region_code varchar_pattern_ops);
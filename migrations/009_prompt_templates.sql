@@ -0,0 +1,48 @@
+-- Bring the seeded prompts in line with the stricter wording the service
+-- used inline, unless an admin has already edited them
+UPDATE system_prompts SET version = version + 1, updated_at = NOW(), prompt_text =
+'Ekstrak informasi laporan dari percakapan berikut. Return ONLY valid JSON dengan struktur:
+{
+  "title": "judul singkat laporan (max 100 char)",
+  "description": "deskripsi lengkap",
+  "location_text": "lokasi yang disebutkan",
+  "category": "kategori yang sesuai",
+  "incident_date": "tanggal kejadian ISO format jika disebutkan, null jika tidak",
+  "urgency": "low|medium|high"
+}
+
+Percakapan:
+{{conversation}}'
+WHERE prompt_type = 'report_extraction' AND version = 1;
+
+UPDATE system_prompts SET version = version + 1, updated_at = NOW(), prompt_text =
+'Periksa kelengkapan laporan berikut dan beri skor 0-1. Return ONLY valid JSON:
+{
+  "is_complete": true/false,
+  "completeness_score": 0.0-1.0,
+  "missing_fields": ["field1", "field2"],
+  "suggestions": ["saran perbaikan"]
+}
+
+Required fields untuk laporan lengkap:
+- Deskripsi jelas masalah (minimal 20 karakter)
+- Lokasi spesifik (nama jalan, kelurahan, atau koordinat)
+- Waktu/tanggal kejadian (minimal perkiraan)
+- Kategori masalah
+
+Laporan:
+{{report}}'
+WHERE prompt_type = 'completeness_check' AND version = 1;
+
+UPDATE system_prompts SET version = version + 1, updated_at = NOW(), prompt_text =
+'Ekstrak entitas dari teks laporan berikut. Return ONLY valid JSON:
+{
+  "locations": ["lokasi1", "lokasi2"],
+  "dates": ["tanggal1"],
+  "organizations": ["instansi terkait"],
+  "persons": ["nama orang jika ada"],
+  "facilities": ["fasilitas yang disebutkan"]
+}
+
+Teks: {{text}}'
+WHERE prompt_type = 'ner_extraction' AND version = 1;
@@ -0,0 +1,51 @@
+-- Every text a system prompt has ever had. system_prompts keeps the active
+-- text and its version number; drafts live only here until promoted.
+CREATE TABLE system_prompt_versions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    prompt_id UUID NOT NULL REFERENCES system_prompts(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    prompt_text TEXT NOT NULL,
+    variables JSONB DEFAULT '{}',
+    status VARCHAR(20) NOT NULL DEFAULT 'published' CHECK (status IN ('draft', 'published')),
+    note TEXT,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    published_at TIMESTAMP WITH TIME ZONE,
+    UNIQUE (prompt_id, version)
+);
+
+CREATE INDEX idx_system_prompt_versions_prompt_id ON system_prompt_versions(prompt_id, version DESC);
+
+-- Versions are immutable; only a draft may be published
+CREATE OR REPLACE FUNCTION protect_system_prompt_versions()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        -- Allowed only when the prompt itself is being removed
+        IF EXISTS (SELECT 1 FROM system_prompts WHERE id = OLD.prompt_id) THEN
+            RAISE EXCEPTION 'system_prompt_versions is append-only';
+        END IF;
+        RETURN OLD;
+    END IF;
+
+    IF NEW.prompt_id <> OLD.prompt_id
+        OR NEW.version <> OLD.version
+        OR NEW.prompt_text <> OLD.prompt_text
+        OR NEW.variables IS DISTINCT FROM OLD.variables
+        OR NEW.created_at IS DISTINCT FROM OLD.created_at
+        OR NOT (OLD.status = 'draft' AND NEW.status = 'published' OR NEW.status = OLD.status)
+    THEN
+        RAISE EXCEPTION 'system_prompt_versions is append-only';
+    END IF;
+
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER protect_system_prompt_versions BEFORE UPDATE OR DELETE ON system_prompt_versions
+    FOR EACH ROW EXECUTE FUNCTION protect_system_prompt_versions();
+
+-- Seed history with the current texts; earlier ones were overwritten in place
+INSERT INTO system_prompt_versions (prompt_id, version, prompt_text, variables, status, created_by, created_at, published_at)
+SELECT id, version, prompt_text, variables, 'published', created_by, updated_at, updated_at
+FROM system_prompts;
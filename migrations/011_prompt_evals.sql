@@ -0,0 +1,84 @@
+-- Labelled inputs for evaluating prompt versions offline
+CREATE TABLE eval_cases (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    prompt_type VARCHAR(50) NOT NULL CHECK (prompt_type IN ('report_extraction', 'completeness_check', 'ner_extraction')),
+    -- Conversation, report JSON or text, depending on the prompt type
+    input TEXT NOT NULL,
+    -- Expected value per output field; fields left out are not scored
+    expected JSONB NOT NULL,
+    is_active BOOLEAN DEFAULT true,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- One run of a prompt version over the active cases of its type
+CREATE TABLE eval_runs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    prompt_id UUID NOT NULL REFERENCES system_prompts(id) ON DELETE CASCADE,
+    prompt_version INTEGER NOT NULL,
+    -- NULL runs on the fake provider
+    api_key_id UUID REFERENCES api_keys(id) ON DELETE SET NULL,
+    provider VARCHAR(50) NOT NULL,
+    model VARCHAR(255),
+    status VARCHAR(20) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'running', 'completed', 'failed')),
+    case_count INTEGER DEFAULT 0,
+    json_valid_rate DOUBLE PRECISION,
+    -- {"field": {"exact": rate, "fuzzy": mean}}
+    field_scores JSONB DEFAULT '{}',
+    avg_latency_ms DOUBLE PRECISION,
+    prompt_tokens BIGINT DEFAULT 0,
+    completion_tokens BIGINT DEFAULT 0,
+    error TEXT,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE eval_results (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    run_id UUID NOT NULL REFERENCES eval_runs(id) ON DELETE CASCADE,
+    case_id UUID REFERENCES eval_cases(id) ON DELETE SET NULL,
+    output TEXT,
+    json_valid BOOLEAN DEFAULT false,
+    field_scores JSONB DEFAULT '{}',
+    latency_ms INTEGER,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_eval_cases_prompt_type ON eval_cases(prompt_type) WHERE is_active = true;
+CREATE INDEX idx_eval_runs_prompt_id ON eval_runs(prompt_id, created_at DESC);
+CREATE INDEX idx_eval_results_run_id ON eval_results(run_id);
+
+CREATE TRIGGER update_eval_cases_updated_at BEFORE UPDATE ON eval_cases FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Golden set
+INSERT INTO eval_cases (name, prompt_type, input, expected) VALUES
+('Jalan berlubang di Jl. Pemuda', 'report_extraction',
+'user: Selamat pagi, saya mau lapor jalan rusak
+assistant: Selamat pagi. Boleh dijelaskan lokasi dan kondisinya?
+user: Di Jl. Pemuda depan SMA 3 Semarang ada lubang besar, sudah dua motor jatuh
+assistant: Sejak kapan lubang itu ada?
+user: Sejak hujan deras tanggal 3 Maret 2025',
+'{"location_text": "Jl. Pemuda depan SMA 3 Semarang", "category": "Infrastruktur Jalan", "incident_date": "2025-03-03", "urgency": "high"}'),
+('Sampah menumpuk di pasar', 'report_extraction',
+'user: Sampah di Pasar Johar sudah seminggu tidak diangkut
+assistant: Terima kasih. Di bagian mana pasar tepatnya?
+user: Di pintu timur, baunya sampai ke permukiman warga',
+'{"location_text": "Pintu timur Pasar Johar", "category": "Kebersihan", "incident_date": null, "urgency": "medium"}'),
+('Lampu jalan mati', 'report_extraction',
+'user: lampu jalan di gang mawar rt 04 rw 02 kelurahan tembalang mati sejak 10 januari 2025, jadi rawan kalau malam',
+'{"location_text": "Gang Mawar RT 04 RW 02 Kelurahan Tembalang", "category": "Penerangan", "incident_date": "2025-01-10", "urgency": "medium"}'),
+('Laporan lengkap', 'completeness_check',
+'{"title": "Jalan berlubang di Jl. Pemuda", "description": "Lubang besar di depan SMA 3 Semarang menyebabkan dua pengendara motor jatuh", "location_text": "Jl. Pemuda depan SMA 3 Semarang", "category": "Infrastruktur Jalan", "incident_date": "2025-03-03T00:00:00+07:00"}',
+'{"is_complete": true, "missing_fields": []}'),
+('Laporan tanpa lokasi dan waktu', 'completeness_check',
+'{"title": "Air PDAM keruh", "description": "Air keruh dan berbau", "location_text": null, "category": "Air & Sanitasi", "incident_date": null}',
+'{"is_complete": false, "missing_fields": ["location_text", "incident_date"]}'),
+('Entitas banjir', 'ner_extraction',
+'Banjir setinggi 50 cm merendam Jalan Kaligawe dekat Terminal Terboyo sejak 12 Februari 2025. BPBD Kota Semarang belum datang.',
+'{"locations": ["Jalan Kaligawe"], "dates": ["12 Februari 2025"], "organizations": ["BPBD Kota Semarang"], "persons": [], "facilities": ["Terminal Terboyo"]}');
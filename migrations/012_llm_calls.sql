@@ -0,0 +1,41 @@
+-- Price per million tokens, matched against the served model name by prefix
+CREATE TABLE llm_model_prices (
+    model VARCHAR(255) PRIMARY KEY,
+    prompt_usd_per_million DOUBLE PRECISION NOT NULL DEFAULT 0,
+    completion_usd_per_million DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- One row per provider call
+CREATE TABLE llm_calls (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    api_key_id UUID REFERENCES api_keys(id) ON DELETE SET NULL,
+    provider VARCHAR(50) NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    prompt_type VARCHAR(50),
+    prompt_id UUID REFERENCES system_prompts(id) ON DELETE SET NULL,
+    prompt_version INTEGER,
+    session_id UUID REFERENCES chat_sessions(id) ON DELETE SET NULL,
+    report_id UUID REFERENCES reports(id) ON DELETE SET NULL,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    latency_ms INTEGER NOT NULL,
+    success BOOLEAN NOT NULL,
+    error TEXT,
+    -- NULL when the model has no price
+    estimated_cost_usd DOUBLE PRECISION,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_llm_calls_created_at ON llm_calls(created_at DESC);
+CREATE INDEX idx_llm_calls_api_key_id ON llm_calls(api_key_id, created_at DESC);
+CREATE INDEX idx_llm_calls_session_id ON llm_calls(session_id);
+
+CREATE TRIGGER update_llm_model_prices_updated_at BEFORE UPDATE ON llm_model_prices FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Defaults for the models configured out of the box
+INSERT INTO llm_model_prices (model, prompt_usd_per_million, completion_usd_per_million) VALUES
+('anthropic/claude-3.5-sonnet', 3, 15),
+('claude-3-5-sonnet', 3, 15),
+('gpt-4o-mini', 0.15, 0.6),
+('fake', 0, 0);
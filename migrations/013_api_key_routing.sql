@@ -0,0 +1,15 @@
+-- Routing between several active keys: lower priority values are tried
+-- first, keys of equal priority take turns.
+ALTER TABLE api_keys ADD COLUMN priority INTEGER NOT NULL DEFAULT 100;
+
+-- Daily limits (WIB days, counted from llm_calls); NULL means unlimited
+ALTER TABLE api_keys ADD COLUMN daily_token_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN daily_request_quota INTEGER;
+
+-- Health: a key that keeps failing is skipped until its cooldown has passed
+ALTER TABLE api_keys ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN cooldown_until TIMESTAMP WITH TIME ZONE;
+ALTER TABLE api_keys ADD COLUMN last_error TEXT;
+ALTER TABLE api_keys ADD COLUMN last_error_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX idx_api_keys_routing ON api_keys(priority, last_used_at) WHERE is_active = true;
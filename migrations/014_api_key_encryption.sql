@@ -0,0 +1,14 @@
+-- API keys are stored envelope-encrypted: the key is sealed with a per-row
+-- data key, which is sealed with the master key identified by kek_id.
+ALTER TABLE api_keys ADD COLUMN api_key_ciphertext TEXT;
+ALTER TABLE api_keys ADD COLUMN wrapped_data_key TEXT;
+ALTER TABLE api_keys ADD COLUMN kek_id VARCHAR(16);
+
+-- Masked suffix shown in the panel, e.g. "****a1b2"
+ALTER TABLE api_keys ADD COLUMN api_key_hint VARCHAR(20);
+
+-- Existing plaintext keys are encrypted and cleared at startup
+ALTER TABLE api_keys ALTER COLUMN api_key DROP NOT NULL;
+UPDATE api_keys SET api_key_hint = '****' || RIGHT(api_key, 4) WHERE api_key IS NOT NULL;
+
+CREATE INDEX idx_api_keys_kek_id ON api_keys(kek_id);
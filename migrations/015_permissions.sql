@@ -0,0 +1,59 @@
+-- What a user may do. Handlers check permissions instead of comparing roles.
+CREATE TABLE permissions (
+    key VARCHAR(100) PRIMARY KEY,
+    description TEXT NOT NULL
+);
+
+-- Permissions held by everyone with a role. Platform roles ('user',
+-- 'moderator', 'admin') grant them everywhere; institution member roles
+-- ('institution:member', 'institution:admin') only within that institution.
+CREATE TABLE role_permissions (
+    role VARCHAR(50) NOT NULL,
+    permission VARCHAR(100) NOT NULL REFERENCES permissions(key) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (role, permission)
+);
+
+-- Extra permissions for a single user, optionally limited to one
+-- institution or one region (and the regions below it)
+CREATE TABLE user_permissions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    permission VARCHAR(100) NOT NULL REFERENCES permissions(key) ON DELETE CASCADE,
+    institution_id UUID REFERENCES institutions(id) ON DELETE CASCADE,
+    region_code VARCHAR(13) REFERENCES regions(code) ON DELETE CASCADE,
+    granted_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    CHECK (institution_id IS NULL OR region_code IS NULL)
+);
+
+CREATE INDEX idx_user_permissions_user_id ON user_permissions(user_id);
+
+INSERT INTO permissions (key, description) VALUES
+('users.manage', 'Change user roles and grant permissions to users'),
+('permissions.manage', 'Change which permissions each role holds'),
+('categories.manage', 'Create, edit and remove report categories'),
+('prompts.edit', 'Edit system prompts and their versions'),
+('evals.run', 'Manage evaluation cases and run prompt evaluations'),
+('institutions.manage', 'Create and edit institutions and their members'),
+('reports.moderate', 'Moderate facts extracted from reports'),
+('regions.import', 'Import administrative regions'),
+('api_keys.manage', 'Manage LLM API keys and model prices'),
+('llm.usage.view', 'View LLM usage and costs'),
+('jobs.view', 'View background jobs'),
+('institution.problems.view', 'Browse the problem shop of an institution'),
+('tickets.assign', 'Adopt clusters, assigning their tickets to an institution'),
+('institution.progress.post', 'Post progress updates on tickets assigned to an institution');
+
+-- Same access as the previous user < moderator < admin hierarchy
+INSERT INTO role_permissions (role, permission)
+SELECT 'admin', key FROM permissions;
+
+INSERT INTO role_permissions (role, permission) VALUES
+('moderator', 'reports.moderate'),
+('institution:member', 'institution.problems.view'),
+('institution:member', 'tickets.assign'),
+('institution:member', 'institution.progress.post'),
+('institution:admin', 'institution.problems.view'),
+('institution:admin', 'tickets.assign'),
+('institution:admin', 'institution.progress.post');
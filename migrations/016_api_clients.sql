@@ -0,0 +1,32 @@
+-- Service accounts act for an institution on behalf of its own systems
+ALTER TABLE users DROP CONSTRAINT users_role_check;
+ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('user', 'admin', 'moderator', 'service'));
+
+-- Logto machine-to-machine applications of an institution. Each one acts
+-- through its own service account, which holds the client's permissions as
+-- grants limited to the institution.
+CREATE TABLE api_clients (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    logto_client_id VARCHAR(255) UNIQUE NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    institution_id UUID NOT NULL REFERENCES institutions(id) ON DELETE CASCADE,
+    user_id UUID UNIQUE NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    is_active BOOLEAN DEFAULT true,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_api_clients_institution_id ON api_clients(institution_id);
+
+CREATE TRIGGER update_api_clients_updated_at BEFORE UPDATE ON api_clients FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+INSERT INTO permissions (key, description) VALUES
+('institution.tickets.view', 'List tickets assigned to an institution'),
+('api_clients.manage', 'Register API clients for institution systems');
+
+INSERT INTO role_permissions (role, permission) VALUES
+('admin', 'institution.tickets.view'),
+('admin', 'api_clients.manage'),
+('institution:member', 'institution.tickets.view'),
+('institution:admin', 'institution.tickets.view');
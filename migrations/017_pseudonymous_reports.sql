@@ -0,0 +1,47 @@
+-- Citizens may report under a pseudonym: a stand-in user without personal
+-- data that their pseudonymous reports and tickets belong to
+ALTER TABLE users DROP CONSTRAINT users_role_check;
+ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('user', 'admin', 'moderator', 'service', 'pseudonym'));
+
+-- The link from a pseudonym back to its citizen, never stored in the clear.
+-- `owner_hash` is a keyed hash of the citizen's user id, so they can find
+-- their own reports; the escrow holds the user id sealed like `api_keys`
+-- and is only opened for legal requests.
+CREATE TABLE pseudonyms (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    owner_hash VARCHAR(64) UNIQUE NOT NULL,
+    escrow_ciphertext TEXT NOT NULL,
+    wrapped_data_key TEXT NOT NULL,
+    kek_id VARCHAR(16) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+ALTER TABLE reports ADD COLUMN anonymity VARCHAR(20) DEFAULT 'identified' NOT NULL
+    CHECK (anonymity IN ('identified', 'pseudonymous'));
+-- Mode for the report drafted from a chat
+ALTER TABLE chat_sessions ADD COLUMN anonymity VARCHAR(20) DEFAULT 'identified' NOT NULL
+    CHECK (anonymity IN ('identified', 'pseudonymous'));
+
+-- Institutions a citizen chose to reveal themselves to for a pseudonymous report
+CREATE TABLE identity_disclosures (
+    report_id UUID NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+    institution_id UUID NOT NULL REFERENCES institutions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (report_id, institution_id)
+);
+
+-- Every time the escrow was opened, and why
+CREATE TABLE identity_escrow_requests (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+    requested_by UUID NOT NULL REFERENCES users(id),
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+INSERT INTO permissions (key, description) VALUES
+('identity.escrow', 'Reveal who filed a pseudonymous report, for legal requests');
+
+INSERT INTO role_permissions (role, permission) VALUES
+('admin', 'identity.escrow');
@@ -0,0 +1,101 @@
+-- Content moderation: reports, comments and citizen facts are pre-screened
+-- with keyword lists and, optionally, an LLM classifier. Flagged content
+-- waits in a queue until a moderator decides on it.
+
+-- Content waiting for moderation is not published. Everything posted before
+-- moderation existed counts as approved.
+ALTER TABLE reports ADD COLUMN moderation_status VARCHAR(20) NOT NULL DEFAULT 'approved'
+    CHECK (moderation_status IN ('pending', 'approved', 'rejected'));
+ALTER TABLE reports ADD COLUMN rejection_reason TEXT;
+
+ALTER TABLE ticket_comments ADD COLUMN moderation_status VARCHAR(20) NOT NULL DEFAULT 'approved'
+    CHECK (moderation_status IN ('pending', 'approved', 'rejected'));
+
+CREATE INDEX idx_reports_moderation_status ON reports(moderation_status) WHERE moderation_status <> 'approved';
+
+-- Terms that send content to the queue, matched as whole words
+CREATE TABLE moderation_keywords (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    term VARCHAR(100) NOT NULL UNIQUE,
+    category VARCHAR(20) NOT NULL CHECK (category IN ('abuse', 'defamation', 'spam')),
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- One row per piece of content that needs, or needed, a moderator.
+-- 'screening' while the classifier has not answered yet.
+CREATE TABLE moderation_items (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    content_type VARCHAR(20) NOT NULL CHECK (content_type IN ('report', 'comment', 'fact')),
+    content_id UUID NOT NULL,
+
+    -- Where the content was posted, for moderators granted single regions
+    report_id UUID REFERENCES reports(id) ON DELETE CASCADE,
+    cluster_id UUID REFERENCES report_clusters(id) ON DELETE CASCADE,
+
+    -- Why it was queued: [{"source": "keyword" | "classifier", "category": "...", "detail": "..."}]
+    flags JSONB NOT NULL DEFAULT '[]',
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('screening', 'pending', 'approved', 'rejected')),
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    resolved_at TIMESTAMP WITH TIME ZONE,
+
+    UNIQUE (content_type, content_id)
+);
+
+CREATE INDEX idx_moderation_items_status ON moderation_items(status, created_at);
+
+CREATE TRIGGER update_moderation_items_updated_at BEFORE UPDATE ON moderation_items FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Audit trail of moderator decisions, never changed afterwards
+CREATE TABLE moderation_decisions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    item_id UUID NOT NULL REFERENCES moderation_items(id) ON DELETE CASCADE,
+    moderator_id UUID NOT NULL REFERENCES users(id),
+    action VARCHAR(20) NOT NULL CHECK (action IN ('approve', 'reject', 'edit')),
+    note TEXT,
+
+    -- The text before and after an edit
+    previous_content TEXT,
+    new_content TEXT,
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX idx_moderation_decisions_item_id ON moderation_decisions(item_id);
+CREATE INDEX idx_moderation_decisions_moderator_id ON moderation_decisions(moderator_id);
+
+UPDATE permissions SET description = 'Moderate reports, comments and citizen facts'
+WHERE key = 'reports.moderate';
+
+-- Classifier prompt, used when MODERATION_CLASSIFIER_ENABLED is set
+ALTER TABLE system_prompts DROP CONSTRAINT IF EXISTS system_prompts_prompt_type_check;
+ALTER TABLE system_prompts ADD CONSTRAINT system_prompts_prompt_type_check CHECK (prompt_type IN (
+    'chat_assistant', 'report_extraction', 'completeness_check',
+    'ner_extraction', 'clustering', 'summarization',
+    'content_moderation'
+));
+
+INSERT INTO system_prompts (name, prompt_type, prompt_text, variables) VALUES
+('Content Moderation', 'content_moderation', 'Periksa apakah teks warga berikut layak dipublikasikan di platform pelaporan masalah publik. Kritik keras terhadap pemerintah atau instansi tetap layak.
+
+Tandai teks jika berisi:
+- abuse: makian, ujaran kebencian, ancaman, atau pelecehan
+- defamation: tuduhan kejahatan atau aib terhadap orang tertentu yang disebut namanya
+- spam: iklan, promosi, tautan tidak relevan, atau teks yang tidak bermakna
+
+Return ONLY valid JSON:
+{
+  "flagged": true/false,
+  "category": "abuse|defamation|spam, null jika tidak ditandai",
+  "reason": "alasan singkat, null jika tidak ditandai"
+}
+
+Teks:
+{{text}}', '{"text": ""}');
+
+INSERT INTO system_prompt_versions (prompt_id, version, prompt_text, variables, status, created_at, published_at)
+SELECT id, version, prompt_text, variables, 'published', updated_at, updated_at
+FROM system_prompts
+WHERE prompt_type = 'content_moderation';
@@ -0,0 +1,53 @@
+-- Duplicate reports. New reports are compared with recent ones nearby;
+-- citizens are asked whether a candidate is the same problem, and
+-- moderators merge confirmed duplicates into the canonical report.
+
+-- Distance between reports, also used by clustering
+CREATE EXTENSION IF NOT EXISTS cube;
+CREATE EXTENSION IF NOT EXISTS earthdistance;
+
+-- Set on a report with status 'duplicate': the report it was merged into
+ALTER TABLE reports ADD COLUMN duplicate_of UUID REFERENCES reports(id);
+ALTER TABLE reports ADD COLUMN merged_by UUID REFERENCES users(id);
+ALTER TABLE reports ADD COLUMN merged_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX idx_reports_duplicate_of ON reports(duplicate_of) WHERE duplicate_of IS NOT NULL;
+
+-- Citizens backing a report besides its reporter, i.e. the reporters of
+-- duplicates merged into it. They follow its ticket and vote on its resolution.
+CREATE TABLE report_supporters (
+    report_id UUID NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),
+    -- The duplicate they came with
+    merged_from UUID REFERENCES reports(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (report_id, user_id)
+);
+
+CREATE INDEX idx_report_supporters_user_id ON report_supporters(user_id);
+
+-- Possible duplicates of a new report among the existing ones
+CREATE TABLE duplicate_candidates (
+    report_id UUID NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+    candidate_id UUID NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+
+    score DECIMAL(4, 3) NOT NULL,
+    text_similarity DECIMAL(4, 3) NOT NULL,
+    distance_meters DECIMAL(10, 2),
+
+    -- The citizen's answer to "Is this the same as TKT-XXXX?", null until answered
+    citizen_confirmed BOOLEAN,
+    status VARCHAR(20) NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'merged', 'dismissed')),
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+
+    PRIMARY KEY (report_id, candidate_id)
+);
+
+CREATE INDEX idx_duplicate_candidates_status ON duplicate_candidates(status, score DESC);
+
+CREATE TRIGGER update_duplicate_candidates_updated_at BEFORE UPDATE ON duplicate_candidates FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+UPDATE permissions SET description = 'Moderate reports, comments and citizen facts, and merge duplicate reports'
+WHERE key = 'reports.moderate';
@@ -0,0 +1,9 @@
+-- The text an item was screened or decided on. Screening the same text
+-- again leaves a decided item alone.
+ALTER TABLE moderation_items ADD COLUMN screened_content TEXT;
+
+UPDATE moderation_items i SET screened_content = CASE i.content_type
+    WHEN 'report' THEN (SELECT c.title || E'\n\n' || c.description FROM reports c WHERE c.id = i.content_id)
+    WHEN 'comment' THEN (SELECT c.comment FROM ticket_comments c WHERE c.id = i.content_id)
+    WHEN 'fact' THEN (SELECT c.content FROM citizen_facts c WHERE c.id = i.content_id)
+END;
@@ -0,0 +1,3 @@
+-- Comments the platform posts itself, such as a rejected resolution,
+-- have no author
+ALTER TABLE ticket_comments ALTER COLUMN user_id DROP NOT NULL;
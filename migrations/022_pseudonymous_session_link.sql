@@ -0,0 +1,15 @@
+-- The chat a report was drafted in belongs to the citizen, so submitted
+-- pseudonymous reports keep no link to it
+ALTER TABLE reports ALTER COLUMN session_id DROP NOT NULL;
+
+UPDATE chat_sessions SET status = 'completed'
+WHERE id IN (SELECT session_id FROM reports WHERE anonymity = 'pseudonymous' AND status <> 'draft');
+
+UPDATE llm_calls SET session_id = NULL
+WHERE report_id IN (SELECT id FROM reports WHERE anonymity = 'pseudonymous' AND status <> 'draft');
+
+UPDATE reports SET session_id = NULL
+WHERE anonymity = 'pseudonymous' AND status <> 'draft';
+
+-- Disclosures are resolved through the pseudonym's escrow
+ALTER TABLE identity_disclosures DROP COLUMN user_id;
@@ -0,0 +1,5 @@
+-- When a fact was first approved. Its report is credited only then, however
+-- often the fact is approved again.
+ALTER TABLE citizen_facts ADD COLUMN approved_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE citizen_facts SET approved_at = COALESCE(moderated_at, updated_at) WHERE status = 'approved';
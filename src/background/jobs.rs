@@ -2,17 +2,22 @@ use rwf::prelude::*;
 use rwf::job::Error as JobError;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::sync::Arc;
 use uuid::Uuid;
+use crate::services::providers::{self, LlmProvider};
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct ClusteringJob;
 
 #[async_trait]
 impl Job for ClusteringJob {
-    async fn execute(&self, _args: serde_json::Value) -> Result<(), JobError> {
+    async fn execute(&self, args: serde_json::Value) -> Result<(), JobError> {
         let pool = crate::db::get_pool();
         
-        perform_clustering(pool).await.map_err(|e| {
+        // Optionally limited to one region, e.g. {"region_code": "33.74"}
+        let region_code = args.get("region_code").and_then(|v| v.as_str());
+        
+        perform_clustering(pool, region_code).await.map_err(|e| {
             tracing::error!("Clustering job failed: {:?}", e);
             JobError::from(serde_json::from_str::<serde_json::Value>("").unwrap_err())
         })?;
@@ -38,14 +43,106 @@ impl Job for CleanupJob {
     }
 }
 
-async fn perform_clustering(pool: &PgPool) -> Result<(), sqlx::Error> {
-    let reports: Vec<(Uuid, Option<f64>, Option<f64>)> = sqlx::query_as(
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ModerationScreenJob;
+
+#[async_trait]
+impl Job for ModerationScreenJob {
+    async fn execute(&self, args: serde_json::Value) -> Result<(), JobError> {
+        let pool = crate::db::get_pool();
+        
+        // {"item_id": "..."}, queued when content is pre-screened
+        let item_id = args
+            .get("item_id")
+            .and_then(|v| v.as_str())
+            .and_then(|v| Uuid::parse_str(v).ok());
+        
+        let Some(item_id) = item_id else {
+            tracing::error!("Moderation screen job without item_id: {}", args);
+            return Ok(());
+        };
+        
+        crate::services::moderation::classify(pool, item_id).await.map_err(|e| {
+            tracing::error!("Moderation screening of {} failed: {:?}", item_id, e);
+            JobError::from(serde_json::from_str::<serde_json::Value>("").unwrap_err())
+        })?;
+        
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct EvalRunJob;
+
+#[async_trait]
+impl Job for EvalRunJob {
+    async fn execute(&self, args: serde_json::Value) -> Result<(), JobError> {
+        let pool = crate::db::get_pool();
+        
+        // {"run_id": "..."}, queued by the panel
+        let run_id = args
+            .get("run_id")
+            .and_then(|v| v.as_str())
+            .and_then(|v| Uuid::parse_str(v).ok());
+        
+        let Some(run_id) = run_id else {
+            tracing::error!("Eval run job without run_id: {}", args);
+            return Ok(());
+        };
+        
+        if let Err(e) = perform_eval_run(pool, run_id).await {
+            tracing::error!("Eval run {} failed: {:?}", run_id, e);
+            
+            sqlx::query("UPDATE eval_runs SET status = 'failed', error = $2, completed_at = NOW() WHERE id = $1")
+                .bind(run_id)
+                .bind(e.to_string())
+                .execute(pool)
+                .await
+                .map_err(|_| JobError::from(serde_json::from_str::<serde_json::Value>("").unwrap_err()))?;
+        }
+        
+        Ok(())
+    }
+}
+
+async fn perform_eval_run(pool: &PgPool, run_id: Uuid) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let api_key_id: Option<Uuid> = sqlx::query_scalar("SELECT api_key_id FROM eval_runs WHERE id = $1")
+        .bind(run_id)
+        .fetch_one(pool)
+        .await?;
+    
+    let provider: Arc<dyn LlmProvider> = match api_key_id {
+        Some(api_key_id) => {
+            let key = sqlx::query_as::<_, crate::models::ApiKey>("SELECT * FROM api_keys WHERE id = $1")
+                .bind(api_key_id)
+                .fetch_one(pool)
+                .await?;
+            let config = crate::config::Config::load()
+                .map_err(|e| format!("Config error: {}", e))?;
+            providers::from_api_key(&key, &config.llm)?
+        }
+        None => Arc::new(providers::FakeProvider),
+    };
+    
+    crate::services::evals::execute_run(pool, run_id, provider.as_ref()).await?;
+    
+    Ok(())
+}
+
+async fn perform_clustering(pool: &PgPool, region_code: Option<&str>) -> Result<(), sqlx::Error> {
+    resolve_missing_regions(pool).await?;
+    
+    let reports: Vec<(Uuid, Option<f64>, Option<f64>)> = sqlx::query_as(&format!(
         "SELECT id, CAST(latitude AS DOUBLE PRECISION), CAST(longitude AS DOUBLE PRECISION)
          FROM reports 
          WHERE latitude IS NOT NULL AND longitude IS NOT NULL 
          AND cluster_id IS NULL
-         AND created_at >= NOW() - INTERVAL '30 days'"
-    )
+         AND moderation_status = 'approved'
+         AND created_at >= NOW() - INTERVAL '30 days'
+         AND {}",
+        crate::services::regions::sql_filter("region_code", 1)
+    ))
+    .bind(region_code)
     .fetch_all(pool)
     .await?;
     
@@ -122,7 +219,26 @@ async fn perform_clustering(pool: &PgPool) -> Result<(), sqlx::Error> {
     Ok(())
 }
 
-async fn update_cluster_metadata(pool: &PgPool, cluster_id: Uuid) -> Result<(), sqlx::Error> {
+// Retries region resolution for recent reports, e.g. after new regions were imported
+async fn resolve_missing_regions(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let report_ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM reports
+         WHERE region_code IS NULL
+         AND created_at >= NOW() - INTERVAL '30 days'
+         ORDER BY created_at DESC
+         LIMIT 500"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    for report_id in report_ids {
+        crate::services::regions::resolve_report(pool, report_id).await?;
+    }
+    
+    Ok(())
+}
+
+pub async fn update_cluster_metadata(pool: &PgPool, cluster_id: Uuid) -> Result<(), sqlx::Error> {
     sqlx::query(
         "UPDATE report_clusters rc
          SET 
@@ -140,6 +256,13 @@ async fn update_cluster_metadata(pool: &PgPool, cluster_id: Uuid) -> Result<(),
              ),
              report_count = (
                  SELECT COUNT(*) FROM reports WHERE cluster_id = rc.id
+             ),
+             region_code = (
+                 SELECT region_code FROM reports
+                 WHERE cluster_id = rc.id AND region_code IS NOT NULL
+                 GROUP BY region_code
+                 ORDER BY COUNT(*) DESC, region_code ASC
+                 LIMIT 1
              )
          WHERE id = $1"
     )
@@ -147,6 +270,56 @@ async fn update_cluster_metadata(pool: &PgPool, cluster_id: Uuid) -> Result<(),
     .execute(pool)
     .await?;
     
+    regenerate_cluster_description(pool, cluster_id).await?;
+    
+    Ok(())
+}
+
+/// Rebuilds a cluster's description from its reports and the approved
+/// citizen facts attached to the cluster or to any of its reports.
+pub async fn regenerate_cluster_description(pool: &PgPool, cluster_id: Uuid) -> Result<(), sqlx::Error> {
+    let titles: Vec<String> = sqlx::query_scalar(
+        "SELECT title FROM reports WHERE cluster_id = $1 ORDER BY created_at ASC LIMIT 5"
+    )
+    .bind(cluster_id)
+    .fetch_all(pool)
+    .await?;
+    
+    let facts: Vec<(String, i32)> = sqlx::query_as(
+        "SELECT f.content, f.confirmation_count FROM citizen_facts f
+         LEFT JOIN reports r ON r.id = f.report_id
+         WHERE f.status = 'approved' AND (f.cluster_id = $1 OR r.cluster_id = $1)
+         ORDER BY f.confirmation_count DESC, f.created_at ASC
+         LIMIT 5"
+    )
+    .bind(cluster_id)
+    .fetch_all(pool)
+    .await?;
+    
+    let report_count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM reports WHERE cluster_id = $1"
+    )
+    .bind(cluster_id)
+    .fetch_one(pool)
+    .await?;
+    
+    let mut description = format!("{} laporan warga: {}.", report_count, titles.join("; "));
+    
+    if !facts.is_empty() {
+        description.push_str("\nFakta tambahan dari warga:");
+        for (content, confirmations) in facts {
+            description.push_str(&format!("\n- {} (dikonfirmasi {} warga)", content, confirmations));
+        }
+    }
+    
+    sqlx::query(
+        "UPDATE report_clusters SET description = $1, updated_at = NOW() WHERE id = $2"
+    )
+    .bind(description)
+    .bind(cluster_id)
+    .execute(pool)
+    .await?;
+    
     Ok(())
 }
 
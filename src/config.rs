@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use crate::services::redaction::RedactionRules;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -10,10 +11,16 @@ pub struct Config {
     pub host: String,
     pub port: u16,
     pub logto: LogtoConfig,
-    pub openrouter: OpenRouterConfig,
+    pub llm: LlmConfig,
     pub jwt_secret: String,
     pub clustering_interval_hours: u64,
     pub ner_processing_enabled: bool,
+    /// Hold new reports and comments for the LLM classifier before publishing
+    pub moderation_classifier_enabled: bool,
+    pub resolution_quorum: f64,
+    /// Origins allowed to call the API from a browser, none unless configured;
+    /// "*" allows any, without credentials
+    pub cors_allowed_origins: Vec<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -21,16 +28,30 @@ pub struct LogtoConfig {
     pub endpoint: String,
     pub app_id: String,
     pub app_secret: String,
+    /// API resource indicator that machine-to-machine tokens are issued for
+    pub api_resource: Option<String>,
 }
 
+/// Model used for each `api_keys.provider`
 #[derive(Clone, Debug, Deserialize)]
-pub struct OpenRouterConfig {
-    pub api_key: String,
-    pub base_url: String,
+pub struct LlmConfig {
+    pub openrouter_model: String,
+    pub openai_model: String,
+    pub anthropic_model: String,
+    pub custom_model: String,
+    /// Answer with the local fake provider instead of calling any API
+    pub fake_provider: bool,
+    /// Corrective follow-ups when a JSON answer cannot be read
+    pub structured_retries: u32,
+    /// Base64 master key sealing the stored API keys
+    pub api_key_encryption_key: String,
+    /// Personal data replaced with placeholders before text leaves for a provider
+    #[serde(skip)]
+    pub redaction: RedactionRules,
 }
 
 #[derive(Debug)]
-pub struct ConfigError(String);
+pub struct ConfigError(pub String);
 
 impl std::fmt::Display for ConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -62,12 +83,32 @@ impl Config {
                     .map_err(|_| ConfigError("LOGTO_APP_ID not set".to_string()))?,
                 app_secret: std::env::var("LOGTO_APP_SECRET")
                     .map_err(|_| ConfigError("LOGTO_APP_SECRET not set".to_string()))?,
+                api_resource: std::env::var("LOGTO_API_RESOURCE").ok(),
             },
-            openrouter: OpenRouterConfig {
-                api_key: std::env::var("OPENROUTER_API_KEY")
-                    .map_err(|_| ConfigError("OPENROUTER_API_KEY not set".to_string()))?,
-                base_url: std::env::var("OPENROUTER_BASE_URL")
-                    .unwrap_or_else(|_| "https://openrouter.ai/api/v1".to_string()),
+            llm: LlmConfig {
+                openrouter_model: std::env::var("LLM_OPENROUTER_MODEL")
+                    .unwrap_or_else(|_| "anthropic/claude-3.5-sonnet".to_string()),
+                openai_model: std::env::var("LLM_OPENAI_MODEL")
+                    .unwrap_or_else(|_| "gpt-4o-mini".to_string()),
+                anthropic_model: std::env::var("LLM_ANTHROPIC_MODEL")
+                    .unwrap_or_else(|_| "claude-3-5-sonnet-latest".to_string()),
+                custom_model: std::env::var("LLM_CUSTOM_MODEL")
+                    .unwrap_or_else(|_| "gpt-3.5-turbo".to_string()),
+                fake_provider: std::env::var("LLM_FAKE_PROVIDER")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .map_err(|_| ConfigError("Invalid LLM_FAKE_PROVIDER value".to_string()))?,
+                structured_retries: std::env::var("LLM_STRUCTURED_RETRIES")
+                    .unwrap_or_else(|_| "2".to_string())
+                    .parse()
+                    .map_err(|_| ConfigError("Invalid LLM_STRUCTURED_RETRIES value".to_string()))?,
+                api_key_encryption_key: std::env::var("API_KEY_ENCRYPTION_KEY")
+                    .map_err(|_| ConfigError("API_KEY_ENCRYPTION_KEY not set".to_string()))?,
+                redaction: RedactionRules::parse(
+                    &std::env::var("PII_REDACT").unwrap_or_else(|_| "nik,npwp,phone,email,plate,person".to_string()),
+                    &std::env::var("PII_KEEP_REDACTED").unwrap_or_else(|_| "nik,npwp".to_string()),
+                )
+                .map_err(|e| ConfigError(format!("Invalid PII_REDACT or PII_KEEP_REDACTED value: {}", e)))?,
             },
             jwt_secret: std::env::var("JWT_SECRET")
                 .map_err(|_| ConfigError("JWT_SECRET not set".to_string()))?,
@@ -79,6 +120,20 @@ impl Config {
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
                 .map_err(|_| ConfigError("Invalid NER_PROCESSING_ENABLED value".to_string()))?,
+            moderation_classifier_enabled: std::env::var("MODERATION_CLASSIFIER_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|_| ConfigError("Invalid MODERATION_CLASSIFIER_ENABLED value".to_string()))?,
+            resolution_quorum: std::env::var("RESOLUTION_QUORUM")
+                .unwrap_or_else(|_| "0.5".to_string())
+                .parse()
+                .map_err(|_| ConfigError("Invalid RESOLUTION_QUORUM value".to_string()))?,
+            cors_allowed_origins: std::env::var("CORS_ALLOWED_ORIGINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
         })
     }
 
@@ -2,6 +2,7 @@ use rwf::prelude::*;
 use uuid::Uuid;
 use crate::models::*;
 use crate::middleware::auth::RequestUserExt;
+use crate::services::regions;
 
 #[derive(Default)]
 pub struct DashboardStatsController;
@@ -13,65 +14,82 @@ impl Controller for DashboardStatsController {
         let _user_id = RequestUserExt::user_id(request)?;
         
         let pool = crate::db::get_pool();
+        let region_code = request.query().get::<String>("region_code");
+        let region = regions::sql_filter("region_code", 1);
         
-        let total_reports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reports")
+        let total_reports: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM reports WHERE {}", region))
+            .bind(&region_code)
             .fetch_one(pool)
             .await
             .map_err(Error::new)?;
         
-        let active_reports: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM reports WHERE status IN ('submitted', 'verified', 'in_progress')"
-        )
+        let active_reports: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM reports WHERE status IN ('submitted', 'verified', 'in_progress') AND {}",
+            region
+        ))
+        .bind(&region_code)
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
-        let resolved_reports: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM reports WHERE status = 'resolved'"
-        )
+        let resolved_reports: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM reports WHERE status = 'resolved' AND {}",
+            region
+        ))
+        .bind(&region_code)
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
-        let total_users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        // Pseudonyms and service accounts are not people of their own
+        let total_users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role NOT IN ('pseudonym', 'service')")
             .fetch_one(pool)
             .await
             .map_err(Error::new)?;
         
-        let reports_this_week: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM reports WHERE created_at >= NOW() - INTERVAL '7 days'"
-        )
+        let reports_this_week: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM reports WHERE created_at >= NOW() - INTERVAL '7 days' AND {}",
+            region
+        ))
+        .bind(&region_code)
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
-        let reports_this_month: i64 = sqlx::query_scalar(
-            "SELECT COUNT(*) FROM reports WHERE created_at >= NOW() - INTERVAL '30 days'"
-        )
+        let reports_this_month: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM reports WHERE created_at >= NOW() - INTERVAL '30 days' AND {}",
+            region
+        ))
+        .bind(&region_code)
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
-        let average_resolution_time_hours: Option<f64> = sqlx::query_scalar(
-            "SELECT AVG(EXTRACT(EPOCH FROM (resolved_at - created_at)) / 3600)
-             FROM tickets WHERE resolved_at IS NOT NULL"
-        )
+        let average_resolution_time_hours: Option<f64> = sqlx::query_scalar(&format!(
+            "SELECT AVG(EXTRACT(EPOCH FROM (t.resolved_at - t.created_at)) / 3600)
+             FROM tickets t JOIN reports r ON r.id = t.report_id
+             WHERE t.resolved_at IS NOT NULL AND {}",
+            regions::sql_filter("r.region_code", 1)
+        ))
+        .bind(&region_code)
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
-        let top_categories: Vec<CategoryStats> = sqlx::query_as(
+        let top_categories: Vec<CategoryStats> = sqlx::query_as(&format!(
             "SELECT 
                 c.id as category_id,
                 c.name as category_name,
                 COUNT(r.id)::bigint as report_count,
-                (COUNT(r.id)::float / NULLIF($1::float, 0) * 100) as percentage
+                (COUNT(r.id)::float / NULLIF($2::float, 0) * 100) as percentage
              FROM categories c
-             LEFT JOIN reports r ON c.id = r.category_id
+             LEFT JOIN reports r ON c.id = r.category_id AND {}
              GROUP BY c.id, c.name
              ORDER BY report_count DESC
-             LIMIT 5"
-        )
+             LIMIT 5",
+            regions::sql_filter("r.region_code", 1)
+        ))
+        .bind(&region_code)
         .bind(total_reports)
         .fetch_all(pool)
         .await
@@ -107,25 +125,28 @@ impl Controller for DashboardTrendsController {
         let days: i32 = query.get::<i32>("days").unwrap_or(30);
         let category_id = query.get::<String>("category_id")
             .and_then(|s| Uuid::parse_str(&s).ok());
+        let region_code = query.get::<String>("region_code");
         
-        let mut sql = String::from(
+        let mut sql = format!(
             "SELECT 
                 TO_CHAR(DATE(created_at), 'YYYY-MM-DD') as date,
                 COUNT(*)::bigint as count,
                 c.name as category
              FROM reports r
              LEFT JOIN categories c ON r.category_id = c.id
-             WHERE created_at >= NOW() - INTERVAL '1 day' * $1"
+             WHERE created_at >= NOW() - INTERVAL '1 day' * $1 AND {}",
+            regions::sql_filter("r.region_code", 2)
         );
         
         if category_id.is_some() {
-            sql.push_str(" AND r.category_id = $2");
+            sql.push_str(" AND r.category_id = $3");
         }
         
         sql.push_str(" GROUP BY DATE(created_at), c.name ORDER BY DATE(created_at) ASC");
         
         let mut query_builder = sqlx::query_as::<_, TrendData>(&sql)
-            .bind(days);
+            .bind(days)
+            .bind(&region_code);
         
         if let Some(cat_id) = category_id {
             query_builder = query_builder.bind(cat_id);
@@ -150,10 +171,13 @@ impl Controller for DashboardClustersController {
         let _user_id = RequestUserExt::user_id(request)?;
         
         let pool = crate::db::get_pool();
+        let region_code = request.query().get::<String>("region_code");
         
-        let clusters = sqlx::query_as::<_, ReportCluster>(
-            "SELECT * FROM report_clusters ORDER BY report_count DESC LIMIT 10"
-        )
+        let clusters = sqlx::query_as::<_, ReportCluster>(&format!(
+            "SELECT * FROM report_clusters WHERE {} ORDER BY report_count DESC LIMIT 10",
+            regions::sql_filter("region_code", 1)
+        ))
+        .bind(&region_code)
         .fetch_all(pool)
         .await
         .map_err(Error::new)?;
@@ -176,24 +200,35 @@ impl Controller for DashboardHeatmapController {
         
         let category_id = query.get::<String>("category_id")
             .and_then(|s| Uuid::parse_str(&s).ok());
+        let region_code = query.get::<String>("region_code");
         
-        let mut sql = String::from(
+        // Pseudonymous reports are blurred to about a kilometre and not listed,
+        // so a point cannot be traced back to one report
+        let mut sql = format!(
             "SELECT 
                 CAST(latitude AS DOUBLE PRECISION) as latitude,
                 CAST(longitude AS DOUBLE PRECISION) as longitude,
                 COUNT(*)::int as intensity,
-                ARRAY_AGG(id) as reports
-             FROM reports
-             WHERE latitude IS NOT NULL AND longitude IS NOT NULL"
+                ARRAY_REMOVE(ARRAY_AGG(CASE WHEN anonymity = 'identified' THEN id END), NULL) as reports
+             FROM (
+                SELECT id, category_id, region_code, anonymity,
+                    CASE WHEN anonymity = 'identified' THEN latitude ELSE ROUND(latitude, 2) END AS latitude,
+                    CASE WHEN anonymity = 'identified' THEN longitude ELSE ROUND(longitude, 2) END AS longitude
+                FROM reports
+                WHERE moderation_status = 'approved'
+             ) reports
+             WHERE latitude IS NOT NULL AND longitude IS NOT NULL AND {}",
+            regions::sql_filter("region_code", 1)
         );
         
         if category_id.is_some() {
-            sql.push_str(" AND category_id = $1");
+            sql.push_str(" AND category_id = $2");
         }
         
         sql.push_str(" GROUP BY latitude, longitude");
         
-        let mut query_builder = sqlx::query_as::<_, HeatmapPoint>(&sql);
+        let mut query_builder = sqlx::query_as::<_, HeatmapPoint>(&sql)
+            .bind(&region_code);
         
         if let Some(cat_id) = category_id {
             query_builder = query_builder.bind(cat_id);
@@ -206,4 +241,42 @@ impl Controller for DashboardHeatmapController {
         
         Response::new().json(&points).map_err(Error::new)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default)]
+pub struct DashboardRegionsController;
+
+#[async_trait]
+impl Controller for DashboardRegionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify user is authenticated
+        let _user_id = RequestUserExt::user_id(request)?;
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        
+        // Children of the given region, or provinces when none is given
+        let region_code = query.get::<String>("region_code");
+        
+        let stats = sqlx::query_as::<_, RegionStats>(
+            "SELECT 
+                g.code as region_code,
+                g.name as region_name,
+                g.level,
+                COUNT(r.id)::bigint as report_count,
+                COUNT(r.id) FILTER (WHERE r.status IN ('submitted', 'verified', 'in_progress'))::bigint as active_reports,
+                COUNT(r.id) FILTER (WHERE r.status = 'resolved')::bigint as resolved_reports
+             FROM regions g
+             LEFT JOIN reports r ON r.region_code = g.code OR r.region_code LIKE g.code || '.%'
+             WHERE g.parent_code IS NOT DISTINCT FROM $1
+             GROUP BY g.code, g.name, g.level
+             ORDER BY report_count DESC, g.name ASC"
+        )
+        .bind(&region_code)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&stats).map_err(Error::new)
+    }
+}
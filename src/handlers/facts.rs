@@ -0,0 +1,166 @@
+use rwf::prelude::*;
+use uuid::Uuid;
+use crate::models::*;
+use crate::middleware::auth::RequestUserExt;
+use crate::services::facts;
+use crate::services::moderation::{self, ContentType};
+
+enum FactTarget {
+    Report(Uuid),
+    Cluster(Uuid),
+}
+
+async fn list_facts(request: &Request, target: FactTarget) -> Result<Response, Error> {
+    let user_id: Uuid = RequestUserExt::user_id(request)?;
+    let pool = crate::db::get_pool();
+
+    let (column, id) = match target {
+        FactTarget::Report(id) => ("report_id", id),
+        FactTarget::Cluster(id) => ("cluster_id", id),
+    };
+
+    // Approved facts are public; contributors also see their own pending ones.
+    let facts = sqlx::query_as::<_, CitizenFact>(&format!(
+        "SELECT * FROM citizen_facts
+         WHERE {} = $1 AND (status = 'approved' OR user_id = $2)
+         ORDER BY confirmation_count DESC, created_at ASC",
+        column
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(Error::new)?;
+
+    Response::new().json(&facts).map_err(Error::new)
+}
+
+async fn add_fact(request: &Request, target: FactTarget) -> Result<Response, Error> {
+    let user_id: Uuid = RequestUserExt::user_id(request)?;
+    let pool = crate::db::get_pool();
+    let req: CreateFactRequest = request.json().map_err(Error::new)?;
+
+    if req.content.trim().is_empty() {
+        return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Fact content is required")));
+    }
+
+    let (report_id, cluster_id, exists_sql) = match target {
+        FactTarget::Report(id) => (Some(id), None, "SELECT EXISTS (SELECT 1 FROM reports WHERE id = $1)"),
+        FactTarget::Cluster(id) => (None, Some(id), "SELECT EXISTS (SELECT 1 FROM report_clusters WHERE id = $1)"),
+    };
+
+    let exists: bool = sqlx::query_scalar(exists_sql)
+        .bind(report_id.or(cluster_id))
+        .fetch_one(pool)
+        .await
+        .map_err(Error::new)?;
+
+    if !exists {
+        return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report or cluster not found")));
+    }
+
+    let fact = sqlx::query_as::<_, CitizenFact>(
+        "INSERT INTO citizen_facts (report_id, cluster_id, user_id, content, photo_url, latitude, longitude, observed_date)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         RETURNING *"
+    )
+    .bind(report_id)
+    .bind(cluster_id)
+    .bind(user_id)
+    .bind(req.content.trim())
+    .bind(req.photo_url)
+    .bind(req.latitude)
+    .bind(req.longitude)
+    .bind(req.observed_date)
+    .fetch_one(pool)
+    .await
+    .map_err(Error::new)?;
+
+    // Queued for the moderators, with any keyword flags
+    moderation::screen(pool, ContentType::Fact, fact.id).await.map_err(Error::new)?;
+
+    Response::new().json(&fact).map_err(Error::new)
+}
+
+#[derive(Default)]
+pub struct ReportFactsController;
+
+#[async_trait]
+impl Controller for ReportFactsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let target = FactTarget::Report(Uuid::parse_str(&id_str).map_err(Error::new)?);
+
+        if request.method() == &Method::Post {
+            add_fact(request, target).await
+        } else {
+            list_facts(request, target).await
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ClusterFactsController;
+
+#[async_trait]
+impl Controller for ClusterFactsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let target = FactTarget::Cluster(Uuid::parse_str(&id_str).map_err(Error::new)?);
+
+        if request.method() == &Method::Post {
+            add_fact(request, target).await
+        } else {
+            list_facts(request, target).await
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FactConfirmController;
+
+#[async_trait]
+impl Controller for FactConfirmController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let fact_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+
+        let fact = sqlx::query_as::<_, CitizenFact>(
+            "SELECT * FROM citizen_facts WHERE id = $1 AND status = 'approved'"
+        )
+        .bind(fact_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Fact not found")))?;
+
+        if fact.user_id == user_id {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "You cannot confirm your own fact")));
+        }
+
+        let fact = match facts::confirm(pool, fact.id, user_id).await.map_err(Error::new)? {
+            Some(fact) => fact,
+            None => return Response::new().json(&fact).map_err(Error::new),
+        };
+
+        let cluster_id = match fact.cluster_id {
+            Some(id) => Some(id),
+            None => sqlx::query_scalar::<_, Option<Uuid>>("SELECT cluster_id FROM reports WHERE id = $1")
+                .bind(fact.report_id)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::new)?
+                .flatten(),
+        };
+
+        if let Some(cluster_id) = cluster_id {
+            crate::background::jobs::regenerate_cluster_description(pool, cluster_id)
+                .await
+                .map_err(Error::new)?;
+        }
+
+        Response::new().json(&fact).map_err(Error::new)
+    }
+}
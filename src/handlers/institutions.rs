@@ -0,0 +1,246 @@
+use rwf::prelude::*;
+use uuid::Uuid;
+use crate::models::*;
+use crate::middleware::auth::RequestUserExt;
+use crate::middleware::principal::Scope;
+use crate::services::pseudonyms;
+
+// Clusters still waiting for an institution, restricted to the institution's
+// jurisdiction and category mandate.
+const PROBLEM_SHOP_SQL: &str = "
+    SELECT rc.*,
+           COUNT(DISTINCT t.id)::bigint AS open_tickets,
+           ARRAY_REMOVE(ARRAY_AGG(DISTINCT COALESCE(rc.category_id, r.category_id)), NULL) AS category_ids
+    FROM report_clusters rc
+    JOIN institutions i ON i.id = $1
+    JOIN reports r ON r.cluster_id = rc.id
+    JOIN tickets t ON t.report_id = r.id AND t.status IN ('open', 'reopened')
+    WHERE rc.adopted_by IS NULL
+    AND (i.min_latitude IS NULL OR rc.center_latitude BETWEEN i.min_latitude AND i.max_latitude)
+    AND (i.min_longitude IS NULL OR rc.center_longitude BETWEEN i.min_longitude AND i.max_longitude)
+    AND (i.region_code IS NULL OR rc.region_code = i.region_code OR rc.region_code LIKE i.region_code || '.%')
+    AND (
+        NOT EXISTS (SELECT 1 FROM institution_categories ic WHERE ic.institution_id = i.id)
+        OR COALESCE(rc.category_id, r.category_id) IN (
+            SELECT ic.category_id FROM institution_categories ic WHERE ic.institution_id = i.id
+        )
+    )";
+
+#[derive(Default)]
+pub struct MyInstitutionsController;
+
+#[async_trait]
+impl Controller for MyInstitutionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let pool = crate::db::get_pool();
+
+        let institutions = sqlx::query_as::<_, Institution>(
+            "SELECT i.* FROM institutions i
+             JOIN institution_members m ON m.institution_id = i.id
+             WHERE m.user_id = $1 AND i.is_active = true
+             ORDER BY i.name ASC"
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+
+        Response::new().json(&institutions).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ProblemShopController;
+
+#[async_trait]
+impl Controller for ProblemShopController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let institution_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        request.require_permission("institution.problems.view", Scope::Institution(institution_id))?;
+
+        let query = request.query();
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+
+        let sql = format!(
+            "{} GROUP BY rc.id ORDER BY open_tickets DESC, rc.report_count DESC LIMIT $2 OFFSET $3",
+            PROBLEM_SHOP_SQL
+        );
+
+        let entries = sqlx::query_as::<_, ProblemShopEntry>(&sql)
+            .bind(institution_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(pool)
+            .await
+            .map_err(Error::new)?;
+
+        Response::new().json(&entries).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct AdoptClusterController;
+
+#[async_trait]
+impl Controller for AdoptClusterController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let pool = crate::db::get_pool();
+
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let institution_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let cluster_str = request.parameter::<String>("cluster_id")?.unwrap_or_default();
+        let cluster_id = Uuid::parse_str(&cluster_str).map_err(Error::new)?;
+
+        request.require_permission("tickets.assign", Scope::Institution(institution_id))?;
+
+        let sql = format!("{} AND rc.id = $2 GROUP BY rc.id", PROBLEM_SHOP_SQL);
+        sqlx::query_as::<_, ProblemShopEntry>(&sql)
+            .bind(institution_id)
+            .bind(cluster_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Cluster is not available to this institution")))?;
+
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+
+        // Guard against two institutions adopting the same cluster concurrently.
+        let adopted = sqlx::query(
+            "UPDATE report_clusters SET adopted_by = $1, adopted_at = NOW(), updated_at = NOW()
+             WHERE id = $2 AND adopted_by IS NULL"
+        )
+        .bind(institution_id)
+        .bind(cluster_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+
+        if adopted.rows_affected() == 0 {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "Cluster already adopted")));
+        }
+
+        let tickets = sqlx::query_as::<_, Ticket>(
+            "UPDATE tickets t SET
+                status = 'assigned',
+                assigned_institution_id = $1,
+                assigned_to = $2,
+                assigned_at = NOW(),
+                updated_at = NOW()
+             FROM reports r
+             WHERE r.id = t.report_id AND r.cluster_id = $3
+             AND t.status IN ('open', 'reopened')
+             RETURNING t.*"
+        )
+        .bind(institution_id)
+        .bind(user_id)
+        .bind(cluster_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+
+        tx.commit().await.map_err(Error::new)?;
+
+        Response::new().json(&tickets).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct InstitutionTicketsController;
+
+#[async_trait]
+impl Controller for InstitutionTicketsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let institution_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        request.require_permission("institution.tickets.view", Scope::Institution(institution_id))?;
+
+        let query = request.query();
+        let status = query.get::<String>("status");
+        // Lets case-management systems pull only what changed since their last sync
+        let updated_since = match query.get::<String>("updated_since") {
+            Some(since) => Some(
+                chrono::DateTime::parse_from_rfc3339(&since)
+                    .map_err(|e| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?
+                    .with_timezone(&chrono::Utc),
+            ),
+            None => None,
+        };
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+
+        let tickets = sqlx::query_as::<_, Ticket>(
+            "SELECT * FROM tickets
+             WHERE assigned_institution_id = $1
+             AND ($2::varchar IS NULL OR status = $2)
+             AND ($3::timestamptz IS NULL OR updated_at > $3)
+             ORDER BY updated_at ASC LIMIT $4 OFFSET $5"
+        )
+        .bind(institution_id)
+        .bind(status)
+        .bind(updated_since)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+
+        Response::new().json(&tickets).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct TicketReporterController;
+
+#[async_trait]
+impl Controller for TicketReporterController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let institution_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let ticket_str = request.parameter::<String>("ticket_id")?.unwrap_or_default();
+        let ticket_id = Uuid::parse_str(&ticket_str).map_err(Error::new)?;
+
+        request.require_permission("institution.tickets.view", Scope::Institution(institution_id))?;
+
+        let (reporter_id, disclosed): (Uuid, bool) = sqlx::query_as(
+            "SELECT r.user_id, EXISTS (
+                 SELECT 1 FROM identity_disclosures d WHERE d.report_id = r.id AND d.institution_id = $2
+             )
+             FROM tickets t
+             JOIN reports r ON r.id = t.report_id
+             WHERE t.id = $1 AND t.assigned_institution_id = $2"
+        )
+        .bind(ticket_id)
+        .bind(institution_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found")))?;
+
+        // Pseudonymous reporters stay their pseudonym unless they revealed
+        // themselves to this institution, which opens the escrow
+        let user_id = if disclosed {
+            let master = pseudonyms::master_key().map_err(Error::new)?;
+            pseudonyms::reveal(pool, &master, reporter_id).await.map_err(Error::new)?.unwrap_or(reporter_id)
+        } else {
+            reporter_id
+        };
+
+        let reporter = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+
+        Response::new().json(&reporter).map_err(Error::new)
+    }
+}
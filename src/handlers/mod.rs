@@ -4,4 +4,7 @@ pub mod chat;
 pub mod reports;
 pub mod tickets;
 pub mod dashboard;
-pub mod panel;
\ No newline at end of file
+pub mod panel;
+pub mod institutions;
+pub mod facts;
+pub mod regions;
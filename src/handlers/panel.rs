@@ -3,6 +3,9 @@ use uuid::Uuid;
 use serde::Deserialize;
 use crate::models::*;
 use crate::middleware::auth::RequestUserExt;
+use crate::middleware::principal::{self, Scope};
+use crate::services::{duplicates, evals, moderation, permissions, prompts, pseudonyms, usage};
+use crate::services::providers::crypto;
 
 #[derive(Default)]
 pub struct AdminUsersController;
@@ -10,8 +13,8 @@ pub struct AdminUsersController;
 #[async_trait]
 impl Controller for AdminUsersController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("users.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let query = request.query();
@@ -61,8 +64,8 @@ pub struct AdminUserRoleController;
 #[async_trait]
 impl Controller for AdminUserRoleController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("users.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         
@@ -80,6 +83,9 @@ impl Controller for AdminUserRoleController {
         .map_err(Error::new)?
         .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "User not found")))?;
         
+        // Takes effect from the user's next request
+        principal::invalidate_user(user.id);
+        
         Response::new().json(&user).map_err(Error::new)
     }
 }
@@ -89,6 +95,233 @@ pub struct UpdateUserRoleRequest {
     pub role: String,
 }
 
+#[derive(Default)]
+pub struct PermissionsController;
+
+#[async_trait]
+impl Controller for PermissionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("permissions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let permissions = permissions::list(pool).await.map_err(Error::new)?;
+        
+        Response::new().json(&permissions).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct RolePermissionsController;
+
+#[async_trait]
+impl Controller for RolePermissionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("permissions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let role = request.parameter::<String>("role")?.unwrap_or_default();
+        
+        if !permissions::ROLES.contains(&role.as_str()) {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Unknown role")));
+        }
+        
+        if request.method() == &Method::Post {
+            let req: RolePermissionRequest = request.json().map_err(Error::new)?;
+            
+            sqlx::query(
+                "INSERT INTO role_permissions (role, permission) VALUES ($1, $2)
+                 ON CONFLICT DO NOTHING"
+            )
+            .bind(&role)
+            .bind(&req.permission)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            // Every cached user may hold this role
+            principal::invalidate_all();
+        }
+        
+        if request.method() == &Method::Delete {
+            let permission = request.query().get::<String>("permission")
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Missing permission")))?;
+            
+            let result = sqlx::query(
+                "DELETE FROM role_permissions WHERE role = $1 AND permission = $2"
+            )
+            .bind(&role)
+            .bind(&permission)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            if result.rows_affected() == 0 {
+                return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Role does not hold this permission")));
+            }
+            
+            principal::invalidate_all();
+        }
+        
+        let granted: Vec<String> = sqlx::query_scalar(
+            "SELECT permission FROM role_permissions WHERE role = $1 ORDER BY permission"
+        )
+        .bind(&role)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(serde_json::json!({
+            "role": role,
+            "permissions": granted,
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct UserPermissionsController;
+
+#[async_trait]
+impl Controller for UserPermissionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("users.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let user_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        if request.method() == &Method::Post {
+            let granted_by: Uuid = RequestUserExt::user_id(request)?;
+            let req: GrantPermissionRequest = request.json().map_err(Error::new)?;
+            
+            if req.institution_id.is_some() && req.region_code.is_some() {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "A grant is scoped to an institution or a region, not both",
+                )));
+            }
+            
+            let grant = sqlx::query_as::<_, UserPermission>(
+                "INSERT INTO user_permissions (user_id, permission, institution_id, region_code, granted_by)
+                 VALUES ($1, $2, $3, $4, $5)
+                 RETURNING *"
+            )
+            .bind(user_id)
+            .bind(&req.permission)
+            .bind(req.institution_id)
+            .bind(&req.region_code)
+            .bind(granted_by)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            principal::invalidate_user(user_id);
+            
+            return Response::new().json(&grant).map_err(Error::new);
+        }
+        
+        if request.method() == &Method::Delete {
+            let grant_id = request.query().get::<String>("grant_id")
+                .and_then(|s| Uuid::parse_str(&s).ok())
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Missing grant_id")))?;
+            
+            let result = sqlx::query(
+                "DELETE FROM user_permissions WHERE id = $1 AND user_id = $2"
+            )
+            .bind(grant_id)
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            if result.rows_affected() == 0 {
+                return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Grant not found")));
+            }
+            
+            principal::invalidate_user(user_id);
+            
+            return Ok(Response::new());
+        }
+        
+        let grants = sqlx::query_as::<_, UserPermission>(
+            "SELECT * FROM user_permissions WHERE user_id = $1 ORDER BY created_at ASC"
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&grants).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ReportIdentityController;
+
+#[async_trait]
+impl Controller for ReportIdentityController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("identity.escrow", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let report_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let req: EscrowRequest = request.json().map_err(Error::new)?;
+        
+        if req.reason.trim().is_empty() {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "A reason is required")));
+        }
+        
+        let (pseudonym_id, anonymity): (Uuid, String) = sqlx::query_as(
+            "SELECT user_id, anonymity FROM reports WHERE id = $1"
+        )
+        .bind(report_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        
+        if anonymity != "pseudonymous" {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Report is not pseudonymous")));
+        }
+        
+        // Recorded before the escrow is opened, so every attempt is on file
+        sqlx::query(
+            "INSERT INTO identity_escrow_requests (report_id, requested_by, reason) VALUES ($1, $2, $3)"
+        )
+        .bind(report_id)
+        .bind(user_id)
+        .bind(req.reason.trim())
+        .execute(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        let master = pseudonyms::master_key().map_err(Error::new)?;
+        let citizen_id = pseudonyms::reveal(pool, &master, pseudonym_id)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "No escrow for this report")))?;
+        
+        tracing::warn!("Identity behind report {} revealed to {}", report_id, user_id);
+        
+        let citizen = sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE id = $1"
+        )
+        .bind(citizen_id)
+        .fetch_one(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&citizen).map_err(Error::new)
+    }
+}
+
 #[derive(Default, macros::RestController)]
 pub struct CategoriesController;
 
@@ -126,8 +359,8 @@ impl RestController for CategoriesController {
     }
     
     async fn create(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("categories.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let user_id: Uuid = RequestUserExt::user_id(request)?;
@@ -151,8 +384,8 @@ impl RestController for CategoriesController {
     }
     
     async fn update(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("categories.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let req: UpdateCategoryRequest = request.json().map_err(Error::new)?;
@@ -184,8 +417,8 @@ impl RestController for CategoriesController {
     }
     
     async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("categories.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let category_id = Uuid::parse_str(id).map_err(Error::new)?;
@@ -223,8 +456,8 @@ impl RestController for PromptsController {
     type Resource = String;
     
     async fn list(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         
@@ -239,8 +472,8 @@ impl RestController for PromptsController {
     }
     
     async fn get(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let prompt_id = Uuid::parse_str(id).map_err(Error::new)?;
@@ -258,8 +491,8 @@ impl RestController for PromptsController {
     }
     
     async fn create(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let user_id: Uuid = RequestUserExt::user_id(request)?;
@@ -267,6 +500,8 @@ impl RestController for PromptsController {
         
         let variables = req.variables.unwrap_or(serde_json::json!({}));
         
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
         let prompt = sqlx::query_as::<_, SystemPrompt>(
             "INSERT INTO system_prompts (name, prompt_type, prompt_text, variables, created_by)
              VALUES ($1, $2, $3, $4, $5)
@@ -274,19 +509,25 @@ impl RestController for PromptsController {
         )
         .bind(req.name)
         .bind(req.prompt_type)
-        .bind(req.prompt_text)
-        .bind(variables)
+        .bind(&req.prompt_text)
+        .bind(&variables)
         .bind(user_id)
-        .fetch_one(pool)
+        .fetch_one(&mut *tx)
         .await
         .map_err(Error::new)?;
         
+        prompts::record_version(&mut tx, prompt.id, &req.prompt_text, &variables, "published", None, user_id)
+            .await
+            .map_err(Error::new)?;
+        
+        tx.commit().await.map_err(Error::new)?;
+        
         Response::new().json(&prompt).map_err(Error::new)
     }
     
     async fn update(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let req: UpdatePromptRequest = request.json().map_err(Error::new)?;
@@ -301,10 +542,27 @@ impl RestController for PromptsController {
         .map_err(Error::new)?
         .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt not found")))?;
         
-        let new_version = if req.prompt_text.is_some() {
-            existing.version + 1
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        // A text or variable change becomes a new version; drafts leave the
+        // active text alone until promoted.
+        let version = if req.prompt_text.is_some() || req.variables.is_some() {
+            let status = if req.draft.unwrap_or(false) { "draft" } else { "published" };
+            let version = prompts::record_version(
+                &mut tx,
+                prompt_id,
+                req.prompt_text.as_deref().unwrap_or(&existing.prompt_text),
+                req.variables.as_ref().unwrap_or(&existing.variables),
+                status,
+                req.note,
+                user_id,
+            )
+            .await
+            .map_err(Error::new)?;
+            Some(version).filter(|v| v.status == "published")
         } else {
-            existing.version
+            None
         };
         
         let prompt = sqlx::query_as::<_, SystemPrompt>(
@@ -313,27 +571,29 @@ impl RestController for PromptsController {
                 prompt_text = COALESCE($2, prompt_text),
                 variables = COALESCE($3, variables),
                 is_active = COALESCE($4, is_active),
-                version = $5,
+                version = COALESCE($5, version),
                 updated_at = NOW()
              WHERE id = $6
              RETURNING *"
         )
         .bind(req.name)
-        .bind(req.prompt_text)
-        .bind(req.variables)
+        .bind(version.as_ref().map(|v| &v.prompt_text))
+        .bind(version.as_ref().map(|v| &v.variables))
         .bind(req.is_active)
-        .bind(new_version)
+        .bind(version.as_ref().map(|v| v.version))
         .bind(prompt_id)
-        .fetch_one(pool)
+        .fetch_one(&mut *tx)
         .await
         .map_err(Error::new)?;
         
+        tx.commit().await.map_err(Error::new)?;
+        
         Response::new().json(&prompt).map_err(Error::new)
     }
     
     async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let prompt_id = Uuid::parse_str(id).map_err(Error::new)?;
@@ -360,6 +620,9 @@ pub struct UpdatePromptRequest {
     pub prompt_text: Option<String>,
     pub variables: Option<serde_json::Value>,
     pub is_active: Option<bool>,
+    /// Store the change as a draft version instead of activating it
+    pub draft: Option<bool>,
+    pub note: Option<String>,
 }
 
 #[derive(Default, macros::RestController)]
@@ -370,8 +633,8 @@ impl RestController for ApiKeysController {
     type Resource = String;
     
     async fn list(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("api_keys.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         
@@ -386,8 +649,8 @@ impl RestController for ApiKeysController {
     }
     
     async fn get(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("api_keys.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let key_id = Uuid::parse_str(id).map_err(Error::new)?;
@@ -405,22 +668,47 @@ impl RestController for ApiKeysController {
     }
     
     async fn create(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("api_keys.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let user_id: Uuid = RequestUserExt::user_id(request)?;
         let req: CreateApiKeyRequest = request.json().map_err(Error::new)?;
         
+        if req.daily_token_quota.is_some_and(|q| q < 0) || req.daily_request_quota.is_some_and(|q| q < 0) {
+            return Err(Error::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Quotas cannot be negative",
+            )));
+        }
+        
+        let config = crate::config::Config::load()
+            .map_err(|e| Error::new(std::io::Error::other(format!("Config error: {}", e))))?;
+        let master = crypto::MasterKey::parse(&config.llm.api_key_encryption_key).map_err(Error::new)?;
+        
+        // The id is bound into the ciphertext, so it is chosen up front
+        let key_id = Uuid::new_v4();
+        let sealed = crypto::encrypt(&master, key_id, &req.api_key).map_err(Error::new)?;
+        
         let api_key = sqlx::query_as::<_, ApiKey>(
-            "INSERT INTO api_keys (name, provider, api_key, base_url, created_by)
-             VALUES ($1, $2, $3, $4, $5)
+            "INSERT INTO api_keys (
+                id, name, provider, api_key_ciphertext, wrapped_data_key, kek_id, api_key_hint,
+                base_url, priority, daily_token_quota, daily_request_quota, created_by
+             )
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, COALESCE($9, 100), NULLIF($10, 0), NULLIF($11, 0), $12)
              RETURNING *"
         )
+        .bind(key_id)
         .bind(req.name)
         .bind(req.provider)
-        .bind(req.api_key)
+        .bind(sealed.ciphertext)
+        .bind(sealed.wrapped_data_key)
+        .bind(sealed.kek_id)
+        .bind(sealed.hint)
         .bind(req.base_url)
+        .bind(req.priority)
+        .bind(req.daily_token_quota)
+        .bind(req.daily_request_quota)
         .bind(user_id)
         .fetch_one(pool)
         .await
@@ -430,20 +718,54 @@ impl RestController for ApiKeysController {
     }
     
     async fn update(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("api_keys.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let key_id = Uuid::parse_str(id).map_err(Error::new)?;
+        let req: UpdateApiKeyRequest = request.json().map_err(Error::new)?;
+        
+        if req.daily_token_quota.is_some_and(|q| q < 0) || req.daily_request_quota.is_some_and(|q| q < 0) {
+            return Err(Error::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Quotas cannot be negative",
+            )));
+        }
         
-        let _pool = crate::db::get_pool();
-        let _key_id = Uuid::parse_str(id).map_err(Error::new)?;
+        // Quotas: absent keeps the current value, 0 removes the limit
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            "UPDATE api_keys SET
+                name = COALESCE($1, name),
+                base_url = COALESCE($2, base_url),
+                is_active = COALESCE($3, is_active),
+                priority = COALESCE($4, priority),
+                daily_token_quota = CASE WHEN $5::bigint IS NULL THEN daily_token_quota ELSE NULLIF($5, 0) END,
+                daily_request_quota = CASE WHEN $6::int IS NULL THEN daily_request_quota ELSE NULLIF($6, 0) END,
+                consecutive_failures = CASE WHEN $7 THEN 0 ELSE consecutive_failures END,
+                cooldown_until = CASE WHEN $7 THEN NULL ELSE cooldown_until END,
+                updated_at = NOW()
+             WHERE id = $8
+             RETURNING *"
+        )
+        .bind(req.name)
+        .bind(req.base_url)
+        .bind(req.is_active)
+        .bind(req.priority)
+        .bind(req.daily_token_quota)
+        .bind(req.daily_request_quota)
+        .bind(req.reset_health.unwrap_or(false))
+        .bind(key_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "API key not found")))?;
         
-        // For now, just return not implemented
-        // You can add update logic if needed
-        Err(Error::new(std::io::Error::new(std::io::ErrorKind::Other, "Update not implemented")))
+        Response::new().json(&api_key).map_err(Error::new)
     }
     
     async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("api_keys.manage", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let key_id = Uuid::parse_str(id).map_err(Error::new)?;
@@ -470,8 +792,8 @@ pub struct BackgroundJobsController;
 #[async_trait]
 impl Controller for BackgroundJobsController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("jobs.view", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         let query = request.query();
@@ -521,8 +843,8 @@ pub struct BackgroundJobController;
 #[async_trait]
 impl Controller for BackgroundJobController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        // Verify admin access
-        request.require_role("admin")?;
+        // Verify permission
+        request.require_permission("jobs.view", Scope::Global)?;
         
         let pool = crate::db::get_pool();
         
@@ -540,4 +862,1327 @@ impl Controller for BackgroundJobController {
         
         Response::new().json(&job).map_err(Error::new)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default, macros::RestController)]
+pub struct InstitutionsController;
+
+#[async_trait]
+impl RestController for InstitutionsController {
+    type Resource = String;
+    
+    async fn list(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let level = request.query().get::<String>("level");
+        
+        let institutions = sqlx::query_as::<_, Institution>(
+            "SELECT * FROM institutions
+             WHERE is_active = true AND ($1::varchar IS NULL OR level = $1)
+             ORDER BY name ASC"
+        )
+        .bind(level)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&institutions).map_err(Error::new)
+    }
+    
+    async fn get(&self, _request: &Request, id: &String) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let institution_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        let institution = sqlx::query_as::<_, Institution>(
+            "SELECT * FROM institutions WHERE id = $1"
+        )
+        .bind(institution_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Institution not found")))?;
+        
+        Response::new().json(&institution).map_err(Error::new)
+    }
+    
+    async fn create(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("institutions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let req: CreateInstitutionRequest = request.json().map_err(Error::new)?;
+        
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        let institution = sqlx::query_as::<_, Institution>(
+            "INSERT INTO institutions (name, level, parent_id, description,
+                min_latitude, max_latitude, min_longitude, max_longitude, region_code, created_by)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING *"
+        )
+        .bind(req.name)
+        .bind(req.level)
+        .bind(req.parent_id)
+        .bind(req.description)
+        .bind(req.min_latitude)
+        .bind(req.max_latitude)
+        .bind(req.min_longitude)
+        .bind(req.max_longitude)
+        .bind(req.region_code)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+        
+        if let Some(category_ids) = req.category_ids {
+            sqlx::query(
+                "INSERT INTO institution_categories (institution_id, category_id)
+                 SELECT $1, UNNEST($2::uuid[])"
+            )
+            .bind(institution.id)
+            .bind(category_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(Error::new)?;
+        }
+        
+        tx.commit().await.map_err(Error::new)?;
+        
+        Response::new().json(&institution).map_err(Error::new)
+    }
+    
+    async fn update(&self, request: &Request, id: &String) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("institutions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let req: UpdateInstitutionRequest = request.json().map_err(Error::new)?;
+        let institution_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        let institution = sqlx::query_as::<_, Institution>(
+            "UPDATE institutions SET
+                name = COALESCE($1, name),
+                description = COALESCE($2, description),
+                min_latitude = COALESCE($3, min_latitude),
+                max_latitude = COALESCE($4, max_latitude),
+                min_longitude = COALESCE($5, min_longitude),
+                max_longitude = COALESCE($6, max_longitude),
+                region_code = COALESCE($7, region_code),
+                is_active = COALESCE($8, is_active),
+                updated_at = NOW()
+             WHERE id = $9
+             RETURNING *"
+        )
+        .bind(req.name)
+        .bind(req.description)
+        .bind(req.min_latitude)
+        .bind(req.max_latitude)
+        .bind(req.min_longitude)
+        .bind(req.max_longitude)
+        .bind(req.region_code)
+        .bind(req.is_active)
+        .bind(institution_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Institution not found")))?;
+        
+        if let Some(category_ids) = req.category_ids {
+            sqlx::query("DELETE FROM institution_categories WHERE institution_id = $1")
+                .bind(institution.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(Error::new)?;
+            
+            sqlx::query(
+                "INSERT INTO institution_categories (institution_id, category_id)
+                 SELECT $1, UNNEST($2::uuid[])"
+            )
+            .bind(institution.id)
+            .bind(category_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(Error::new)?;
+        }
+        
+        tx.commit().await.map_err(Error::new)?;
+        
+        Response::new().json(&institution).map_err(Error::new)
+    }
+    
+    async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("institutions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let institution_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        let result = sqlx::query(
+            "UPDATE institutions SET is_active = false, updated_at = NOW() WHERE id = $1"
+        )
+        .bind(institution_id)
+        .execute(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        if result.rows_affected() == 0 {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Institution not found")));
+        }
+        
+        Ok(Response::new())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateInstitutionRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub min_latitude: Option<f64>,
+    pub max_latitude: Option<f64>,
+    pub min_longitude: Option<f64>,
+    pub max_longitude: Option<f64>,
+    pub region_code: Option<String>,
+    pub is_active: Option<bool>,
+    pub category_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Default)]
+pub struct InstitutionMembersController;
+
+#[async_trait]
+impl Controller for InstitutionMembersController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("institutions.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let institution_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        if request.method() == &Method::Post {
+            let req: AddInstitutionMemberRequest = request.json().map_err(Error::new)?;
+            
+            let member = sqlx::query_as::<_, InstitutionMember>(
+                "INSERT INTO institution_members (institution_id, user_id, member_role)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (institution_id, user_id) DO UPDATE SET member_role = EXCLUDED.member_role
+                 RETURNING *"
+            )
+            .bind(institution_id)
+            .bind(req.user_id)
+            .bind(req.member_role.unwrap_or_else(|| "member".to_string()))
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            principal::invalidate_user(member.user_id);
+            
+            return Response::new().json(&member).map_err(Error::new);
+        }
+        
+        if request.method() == &Method::Delete {
+            let user_id = request.query().get::<String>("user_id")
+                .and_then(|s| Uuid::parse_str(&s).ok())
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Missing user_id")))?;
+            
+            let result = sqlx::query(
+                "DELETE FROM institution_members WHERE institution_id = $1 AND user_id = $2"
+            )
+            .bind(institution_id)
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            principal::invalidate_user(user_id);
+            
+            if result.rows_affected() == 0 {
+                return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Member not found")));
+            }
+            
+            return Ok(Response::new());
+        }
+        
+        let members = sqlx::query_as::<_, InstitutionMember>(
+            "SELECT * FROM institution_members WHERE institution_id = $1 ORDER BY created_at ASC"
+        )
+        .bind(institution_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&members).map_err(Error::new)
+    }
+}
+
+#[derive(Default, macros::RestController)]
+pub struct ApiClientsController;
+
+#[async_trait]
+impl RestController for ApiClientsController {
+    type Resource = String;
+    
+    async fn list(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("api_clients.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let institution_id = request.query().get::<String>("institution_id")
+            .and_then(|s| Uuid::parse_str(&s).ok());
+        
+        let clients = sqlx::query_as::<_, ApiClient>(
+            "SELECT * FROM api_clients
+             WHERE ($1::uuid IS NULL OR institution_id = $1)
+             ORDER BY created_at DESC"
+        )
+        .bind(institution_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&clients).map_err(Error::new)
+    }
+    
+    async fn get(&self, request: &Request, id: &String) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("api_clients.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let client_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        let client = sqlx::query_as::<_, ApiClient>(
+            "SELECT * FROM api_clients WHERE id = $1"
+        )
+        .bind(client_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "API client not found")))?;
+        
+        Response::new().json(&client).map_err(Error::new)
+    }
+    
+    async fn create(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("api_clients.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let req: CreateApiClientRequest = request.json().map_err(Error::new)?;
+        
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        // The service account has no Logto user behind it
+        let service_user = sqlx::query_as::<_, User>(
+            "INSERT INTO users (logto_user_id, username, full_name, role)
+             VALUES ('client:' || $1, $1, $2, 'service')
+             RETURNING *"
+        )
+        .bind(&req.logto_client_id)
+        .bind(&req.name)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+        
+        let client = sqlx::query_as::<_, ApiClient>(
+            "INSERT INTO api_clients (logto_client_id, name, institution_id, user_id, created_by)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING *"
+        )
+        .bind(&req.logto_client_id)
+        .bind(&req.name)
+        .bind(req.institution_id)
+        .bind(service_user.id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+        
+        sqlx::query(
+            "INSERT INTO user_permissions (user_id, permission, institution_id, granted_by)
+             SELECT $1, permission, $2, $3 FROM UNNEST($4::varchar[]) AS permission"
+        )
+        .bind(service_user.id)
+        .bind(req.institution_id)
+        .bind(user_id)
+        .bind(&req.permissions)
+        .execute(&mut *tx)
+        .await
+        .map_err(Error::new)?;
+        
+        tx.commit().await.map_err(Error::new)?;
+        
+        Response::new().json(&client).map_err(Error::new)
+    }
+    
+    async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("api_clients.manage", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let client_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        // Deactivate rather than delete, so progress updates keep their author
+        let service_user_id: Uuid = sqlx::query_scalar(
+            "UPDATE api_clients SET is_active = false, updated_at = NOW()
+             WHERE id = $1
+             RETURNING user_id"
+        )
+        .bind(client_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "API client not found")))?;
+        
+        principal::invalidate_user(service_user_id);
+        
+        Ok(Response::new())
+    }
+}
+
+#[derive(Default)]
+pub struct FactsModerationController;
+
+#[async_trait]
+impl Controller for FactsModerationController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Moderators holding the permission for some regions only see content from those
+        let regions = request.principal()?.region_limit("reports.moderate");
+        if regions.as_ref().is_some_and(|regions| regions.is_empty()) {
+            request.require_permission("reports.moderate", Scope::Global)?;
+        }
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        
+        let status = query.get::<String>("status").unwrap_or_else(|| "pending".to_string());
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+        
+        let facts = sqlx::query_as::<_, CitizenFact>(
+            "SELECT f.* FROM citizen_facts f
+             LEFT JOIN reports r ON r.id = f.report_id
+             LEFT JOIN report_clusters rc ON rc.id = f.cluster_id
+             WHERE f.status = $1
+             AND ($4::varchar[] IS NULL OR EXISTS (
+                 SELECT 1 FROM UNNEST($4::varchar[]) AS g(code)
+                 WHERE COALESCE(r.region_code, rc.region_code) = g.code
+                 OR COALESCE(r.region_code, rc.region_code) LIKE g.code || '.%'
+             ))
+             ORDER BY f.created_at ASC LIMIT $2 OFFSET $3"
+        )
+        .bind(status)
+        .bind(limit)
+        .bind(offset)
+        .bind(regions)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&facts).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct FactModerateController;
+
+#[async_trait]
+impl Controller for FactModerateController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        // Verify permission for the region the fact was reported in
+        let region_code: Option<String> = sqlx::query_scalar(
+            "SELECT COALESCE(r.region_code, rc.region_code) FROM citizen_facts f
+             LEFT JOIN reports r ON r.id = f.report_id
+             LEFT JOIN report_clusters rc ON rc.id = f.cluster_id
+             WHERE f.id = $1"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Fact not found")))?;
+        
+        request.require_permission("reports.moderate", Scope::region_or_global(region_code.as_deref()))?;
+        let req: ModerateFactRequest = request.json().map_err(Error::new)?;
+        
+        if !["pending", "approved", "rejected"].contains(&req.status.as_str()) {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid moderation status")));
+        }
+        
+        let fact = crate::services::facts::moderate(pool, id, user_id, &req.status, req.note)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Fact not found or already moderated")))?;
+        
+        moderation::record_fact_decision(pool, &fact, user_id)
+            .await
+            .map_err(Error::new)?;
+        
+        Response::new().json(&fact).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ModerationQueueController;
+
+#[async_trait]
+impl Controller for ModerationQueueController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Moderators holding the permission for some regions only see content from those
+        let regions = request.principal()?.region_limit("reports.moderate");
+        if regions.as_ref().is_some_and(|regions| regions.is_empty()) {
+            request.require_permission("reports.moderate", Scope::Global)?;
+        }
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        
+        let status = query.get::<String>("status").unwrap_or_else(|| "pending".to_string());
+        let content_type = query.get::<String>("content_type");
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+        
+        let entries = sqlx::query_as::<_, ModerationQueueEntry>(&format!(
+            "SELECT i.*, {} AS title, {} AS content FROM moderation_items i
+             LEFT JOIN reports r ON r.id = i.report_id
+             LEFT JOIN report_clusters rc ON rc.id = i.cluster_id
+             WHERE i.status = $1
+             AND ($2::varchar IS NULL OR i.content_type = $2)
+             AND ($5::varchar[] IS NULL OR EXISTS (
+                 SELECT 1 FROM UNNEST($5::varchar[]) AS g(code)
+                 WHERE COALESCE(r.region_code, rc.region_code) = g.code
+                 OR COALESCE(r.region_code, rc.region_code) LIKE g.code || '.%'
+             ))
+             ORDER BY i.created_at ASC LIMIT $3 OFFSET $4",
+            moderation::TITLE_SQL,
+            moderation::BODY_SQL
+        ))
+        .bind(status)
+        .bind(content_type)
+        .bind(limit)
+        .bind(offset)
+        .bind(regions)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&entries).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ModerationDecideController;
+
+#[async_trait]
+impl Controller for ModerationDecideController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        // Verify permission for the region the content was posted in
+        let region_code: Option<String> = sqlx::query_scalar(
+            "SELECT COALESCE(r.region_code, rc.region_code) FROM moderation_items i
+             LEFT JOIN reports r ON r.id = i.report_id
+             LEFT JOIN report_clusters rc ON rc.id = i.cluster_id
+             WHERE i.id = $1"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Moderation item not found")))?;
+        
+        request.require_permission("reports.moderate", Scope::region_or_global(region_code.as_deref()))?;
+        let req: ModerationActionRequest = request.json().map_err(Error::new)?;
+        
+        if let Some(message) = moderation::action_refusal(&req) {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, message)));
+        }
+        
+        let item = moderation::decide(pool, id, user_id, &req)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Moderation item not found or already decided")))?;
+        
+        Response::new().json(&item).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct DuplicateCandidatesController;
+
+#[async_trait]
+impl Controller for DuplicateCandidatesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Moderators holding the permission for some regions only see reports from those
+        let regions = request.principal()?.region_limit("reports.moderate");
+        if regions.as_ref().is_some_and(|regions| regions.is_empty()) {
+            request.require_permission("reports.moderate", Scope::Global)?;
+        }
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+        
+        let candidates = duplicates::queue(pool, regions, limit, offset)
+            .await
+            .map_err(Error::new)?;
+        
+        Response::new().json(&candidates).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct DuplicateDismissController;
+
+#[async_trait]
+impl Controller for DuplicateDismissController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let candidate_str = request.parameter::<String>("candidate_id")?.unwrap_or_default();
+        let candidate_id = Uuid::parse_str(&candidate_str).map_err(Error::new)?;
+        
+        let region_code: Option<String> = sqlx::query_scalar("SELECT region_code FROM reports WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        
+        request.require_permission("reports.moderate", Scope::region_or_global(region_code.as_deref()))?;
+        
+        if !duplicates::dismiss(pool, id, candidate_id).await.map_err(Error::new)? {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Duplicate candidate not found")));
+        }
+        
+        Ok(Response::new())
+    }
+}
+
+#[derive(Default)]
+pub struct ReportMergeController;
+
+#[async_trait]
+impl Controller for ReportMergeController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let req: MergeReportRequest = request.json().map_err(Error::new)?;
+        
+        let duplicate = sqlx::query_as::<_, Report>("SELECT * FROM reports WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        let canonical = sqlx::query_as::<_, Report>("SELECT * FROM reports WHERE id = $1")
+            .bind(req.into)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Canonical report not found")))?;
+        
+        // Merging moves people between both reports, so both regions must be moderated
+        request.require_permission("reports.moderate", Scope::region_or_global(duplicate.region_code.as_deref()))?;
+        request.require_permission("reports.moderate", Scope::region_or_global(canonical.region_code.as_deref()))?;
+        
+        // Both reports are checked again once locked
+        let report = duplicates::merge(pool, id, canonical.id, user_id)
+            .await
+            .map_err(Error::new)?;
+        
+        Response::new().json(&report).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ModerationDecisionsController;
+
+#[async_trait]
+impl Controller for ModerationDecisionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // The full audit trail is for moderators of every region
+        request.require_permission("reports.moderate", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        
+        let item_id = query.get::<String>("item_id")
+            .and_then(|s| Uuid::parse_str(&s).ok());
+        let moderator_id = query.get::<String>("moderator_id")
+            .and_then(|s| Uuid::parse_str(&s).ok());
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
+        
+        let decisions = sqlx::query_as::<_, ModerationDecision>(
+            "SELECT * FROM moderation_decisions
+             WHERE ($1::uuid IS NULL OR item_id = $1)
+             AND ($2::uuid IS NULL OR moderator_id = $2)
+             ORDER BY created_at DESC LIMIT $3 OFFSET $4"
+        )
+        .bind(item_id)
+        .bind(moderator_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&decisions).map_err(Error::new)
+    }
+}
+
+#[derive(Default, macros::RestController)]
+pub struct ModerationKeywordsController;
+
+#[async_trait]
+impl RestController for ModerationKeywordsController {
+    type Resource = String;
+    
+    async fn list(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("reports.moderate", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        let keywords = sqlx::query_as::<_, ModerationKeyword>(
+            "SELECT * FROM moderation_keywords ORDER BY category ASC, term ASC"
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&keywords).map_err(Error::new)
+    }
+    
+    async fn create(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("reports.moderate", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let req: CreateModerationKeywordRequest = request.json().map_err(Error::new)?;
+        
+        if req.term.trim().is_empty() {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Keyword term is required")));
+        }
+        if !["abuse", "defamation", "spam"].contains(&req.category.as_str()) {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid keyword category")));
+        }
+        
+        let keyword = sqlx::query_as::<_, ModerationKeyword>(
+            "INSERT INTO moderation_keywords (term, category, created_by)
+             VALUES (LOWER($1), $2, $3)
+             RETURNING *"
+        )
+        .bind(req.term.trim())
+        .bind(req.category)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&keyword).map_err(Error::new)
+    }
+    
+    async fn delete(&self, request: &Request, id: &String) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("reports.moderate", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let keyword_id = Uuid::parse_str(id).map_err(Error::new)?;
+        
+        let result = sqlx::query("DELETE FROM moderation_keywords WHERE id = $1")
+            .bind(keyword_id)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+        
+        if result.rows_affected() == 0 {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Keyword not found")));
+        }
+        
+        Ok(Response::new())
+    }
+}
+
+#[derive(Default)]
+pub struct RegionImportController;
+
+#[async_trait]
+impl Controller for RegionImportController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("regions.import", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        // Body is the Kemendagri code list as CSV
+        let rows = crate::services::regions::parse_kemendagri_csv(&request.string());
+        
+        if rows.is_empty() {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No regions found in CSV")));
+        }
+        
+        let imported = crate::services::regions::import(pool, &rows)
+            .await
+            .map_err(Error::new)?;
+        
+        Response::new().json(serde_json::json!({
+            "parsed": rows.len(),
+            "imported": imported,
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct PromptVersionsController;
+
+#[async_trait]
+impl Controller for PromptVersionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let prompt_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        if request.method() == &Method::Post {
+            let user_id: Uuid = RequestUserExt::user_id(request)?;
+            let req: CreatePromptVersionRequest = request.json().map_err(Error::new)?;
+            
+            let mut tx = pool.begin().await.map_err(Error::new)?;
+            
+            let variables = match req.variables {
+                Some(variables) => variables,
+                None => sqlx::query_scalar("SELECT variables FROM system_prompts WHERE id = $1")
+                    .bind(prompt_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(Error::new)?
+                    .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt not found")))?,
+            };
+            
+            let version = prompts::record_version(&mut tx, prompt_id, &req.prompt_text, &variables, "draft", req.note, user_id)
+                .await
+                .map_err(Error::new)?;
+            
+            tx.commit().await.map_err(Error::new)?;
+            
+            return Response::new().json(&version).map_err(Error::new);
+        }
+        
+        let versions = sqlx::query_as::<_, SystemPromptVersion>(
+            "SELECT * FROM system_prompt_versions WHERE prompt_id = $1 ORDER BY version DESC"
+        )
+        .bind(prompt_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&versions).map_err(Error::new)
+    }
+}
+
+async fn find_prompt_version(
+    pool: &sqlx::PgPool,
+    prompt_id: Uuid,
+    version: i32,
+) -> Result<SystemPromptVersion, Error> {
+    sqlx::query_as::<_, SystemPromptVersion>(
+        "SELECT * FROM system_prompt_versions WHERE prompt_id = $1 AND version = $2"
+    )
+    .bind(prompt_id)
+    .bind(version)
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::new)?
+    .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt version not found")))
+}
+
+#[derive(Default)]
+pub struct PromptVersionController;
+
+#[async_trait]
+impl Controller for PromptVersionController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let prompt_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let version = request.parameter::<i64>("version")?.unwrap_or_default() as i32;
+        
+        let version = find_prompt_version(pool, prompt_id, version).await?;
+        
+        Response::new().json(&version).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct PromptVersionDiffController;
+
+#[async_trait]
+impl Controller for PromptVersionDiffController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let prompt_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let version = request.parameter::<i64>("version")?.unwrap_or_default() as i32;
+        
+        // Compared against the active version unless ?against= is given
+        let against = match request.query().get::<i32>("against") {
+            Some(against) => against,
+            None => sqlx::query_scalar("SELECT version FROM system_prompts WHERE id = $1")
+                .bind(prompt_id)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::new)?
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt not found")))?,
+        };
+        
+        let base = find_prompt_version(pool, prompt_id, against).await?;
+        let target = find_prompt_version(pool, prompt_id, version).await?;
+        
+        Response::new().json(serde_json::json!({
+            "from": base.version,
+            "to": target.version,
+            "variables_changed": base.variables != target.variables,
+            "lines": prompts::diff_lines(&base.prompt_text, &target.prompt_text),
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct PromptVersionPromoteController;
+
+#[async_trait]
+impl Controller for PromptVersionPromoteController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let prompt_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let version = request.parameter::<i64>("version")?.unwrap_or_default() as i32;
+        
+        // Promoting a draft activates it; promoting an older version rolls back
+        let prompt = prompts::promote(pool, prompt_id, version)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt version not found")))?;
+        
+        Response::new().json(&prompt).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct PromptVersionPreviewController;
+
+#[async_trait]
+impl Controller for PromptVersionPreviewController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("prompts.edit", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let prompt_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let version = request.parameter::<i64>("version")?.unwrap_or_default() as i32;
+        let req: PreviewPromptRequest = request.json().map_err(Error::new)?;
+        
+        let prompt = sqlx::query_as::<_, SystemPrompt>("SELECT * FROM system_prompts WHERE id = $1")
+            .bind(prompt_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt not found")))?;
+        let version = find_prompt_version(pool, prompt_id, version).await?;
+        
+        let values: Vec<(&str, &str)> = req.values.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let rendered = prompts::render_version(&prompt, &version, &values)
+            .map_err(|e| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())))?;
+        
+        let config = crate::config::Config::load()
+            .map_err(|e| Error::new(std::io::Error::other(format!("Config error: {}", e))))?;
+        let provider = crate::services::providers::active(pool, &config.llm)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "No active API key configured")))?;
+        
+        // Chat prompts are system messages; the rest are sent as the user turn.
+        let request = if prompt.prompt_type == "chat_assistant" {
+            crate::services::providers::CompletionRequest {
+                system: Some(rendered.text.clone()),
+                prompt_type: Some(prompt.prompt_type.clone()),
+                ..Default::default()
+            }
+        } else {
+            crate::services::providers::CompletionRequest {
+                messages: vec![crate::services::providers::LlmMessage {
+                    role: "user".to_string(),
+                    content: rendered.text.clone(),
+                }],
+                prompt_type: Some(prompt.prompt_type.clone()),
+                ..Default::default()
+            }
+        };
+        
+        let started = std::time::Instant::now();
+        let result = provider.complete(&request).await;
+        
+        usage::record(pool, usage::LlmCall {
+            context: &usage::CallContext::default(),
+            provider: provider.as_ref(),
+            prompt_type: &prompt.prompt_type,
+            prompt: Some(&rendered.source),
+            started,
+            result: result.as_ref(),
+        })
+        .await;
+        
+        let completion = result.map_err(Error::new)?;
+        
+        Response::new().json(serde_json::json!({
+            "prompt": rendered.source,
+            "rendered": rendered.text,
+            "output": completion.content,
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct EvalCasesController;
+
+#[async_trait]
+impl Controller for EvalCasesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("evals.run", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        if request.method() == &Method::Post {
+            let user_id: Uuid = RequestUserExt::user_id(request)?;
+            let req: CreateEvalCaseRequest = request.json().map_err(Error::new)?;
+            
+            if evals::input_variable(&req.prompt_type).is_none() {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Prompt type {} cannot be evaluated", req.prompt_type),
+                )));
+            }
+            if !req.expected.is_object() {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "expected must be an object of field values",
+                )));
+            }
+            
+            let case = sqlx::query_as::<_, EvalCase>(
+                "INSERT INTO eval_cases (name, prompt_type, input, expected, created_by)
+                 VALUES ($1, $2, $3, $4, $5)
+                 RETURNING *"
+            )
+            .bind(req.name)
+            .bind(req.prompt_type)
+            .bind(req.input)
+            .bind(req.expected)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            return Response::new().json(&case).map_err(Error::new);
+        }
+        
+        let prompt_type = request.query().get::<String>("prompt_type");
+        
+        let cases = sqlx::query_as::<_, EvalCase>(
+            "SELECT * FROM eval_cases
+             WHERE is_active = true
+             AND ($1::varchar IS NULL OR prompt_type = $1)
+             ORDER BY prompt_type, created_at"
+        )
+        .bind(prompt_type)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&cases).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct EvalRunsController;
+
+#[async_trait]
+impl Controller for EvalRunsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("evals.run", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        if request.method() == &Method::Post {
+            let user_id: Uuid = RequestUserExt::user_id(request)?;
+            let req: StartEvalRunRequest = request.json().map_err(Error::new)?;
+            
+            let prompt = sqlx::query_as::<_, SystemPrompt>("SELECT * FROM system_prompts WHERE id = $1")
+                .bind(req.prompt_id)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::new)?
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Prompt not found")))?;
+            
+            if evals::input_variable(&prompt.prompt_type).is_none() {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Prompt type {} cannot be evaluated", prompt.prompt_type),
+                )));
+            }
+            
+            let version = find_prompt_version(pool, prompt.id, req.version.unwrap_or(prompt.version)).await?;
+            
+            let provider = match req.api_key_id {
+                Some(api_key_id) => sqlx::query_scalar::<_, String>("SELECT provider FROM api_keys WHERE id = $1")
+                    .bind(api_key_id)
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(Error::new)?
+                    .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "API key not found")))?,
+                None => "fake".to_string(),
+            };
+            
+            let run = sqlx::query_as::<_, EvalRun>(
+                "INSERT INTO eval_runs (prompt_id, prompt_version, api_key_id, provider, created_by)
+                 VALUES ($1, $2, $3, $4, $5)
+                 RETURNING *"
+            )
+            .bind(prompt.id)
+            .bind(version.version)
+            .bind(req.api_key_id)
+            .bind(provider)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            crate::background::jobs::EvalRunJob
+                .execute_async(serde_json::json!({ "run_id": run.id }))
+                .await
+                .map_err(Error::new)?;
+            
+            return Response::new().json(&run).map_err(Error::new);
+        }
+        
+        let query = request.query();
+        let prompt_id = query.get::<String>("prompt_id")
+            .map(|id| Uuid::parse_str(&id))
+            .transpose()
+            .map_err(Error::new)?;
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        
+        let runs = sqlx::query_as::<_, EvalRun>(
+            "SELECT * FROM eval_runs
+             WHERE ($1::uuid IS NULL OR prompt_id = $1)
+             ORDER BY created_at DESC
+             LIMIT $2"
+        )
+        .bind(prompt_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&runs).map_err(Error::new)
+    }
+}
+
+async fn find_eval_run(pool: &sqlx::PgPool, run_id: Uuid) -> Result<EvalRun, Error> {
+    sqlx::query_as::<_, EvalRun>("SELECT * FROM eval_runs WHERE id = $1")
+        .bind(run_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Eval run not found")))
+}
+
+#[derive(Default)]
+pub struct EvalRunController;
+
+#[async_trait]
+impl Controller for EvalRunController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("evals.run", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let run_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        let run = find_eval_run(pool, run_id).await?;
+        
+        let results = sqlx::query_as::<_, EvalResult>(
+            "SELECT * FROM eval_results WHERE run_id = $1 ORDER BY created_at"
+        )
+        .bind(run_id)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(serde_json::json!({
+            "run": run,
+            "results": results,
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct EvalRunCompareController;
+
+#[async_trait]
+impl Controller for EvalRunCompareController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("evals.run", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let run_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let against = request.query().get::<String>("against")
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "against is required")))?;
+        let against = Uuid::parse_str(&against).map_err(Error::new)?;
+        
+        let run = find_eval_run(pool, run_id).await?;
+        let base = find_eval_run(pool, against).await?;
+        
+        Response::new().json(evals::compare(&base, &run)).map_err(Error::new)
+    }
+}
+
+// Usage windows are capped at a year
+const MAX_USAGE_DAYS: i64 = 366;
+
+#[derive(Default)]
+pub struct ApiKeyUsageController;
+
+#[async_trait]
+impl Controller for ApiKeyUsageController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("llm.usage.view", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let key_id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let days: i64 = request.query().get::<i64>("days").unwrap_or(30).clamp(1, MAX_USAGE_DAYS);
+        
+        let key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE id = $1")
+            .bind(key_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "API key not found")))?;
+        
+        let usage = usage::report(pool, days, Some(key_id)).await.map_err(Error::new)?;
+        
+        Response::new().json(serde_json::json!({
+            "api_key": key,
+            "usage": usage,
+        })).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct LlmUsageController;
+
+#[async_trait]
+impl Controller for LlmUsageController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("llm.usage.view", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        let days: i64 = request.query().get::<i64>("days").unwrap_or(30).clamp(1, MAX_USAGE_DAYS);
+        
+        let usage = usage::report(pool, days, None).await.map_err(Error::new)?;
+        
+        Response::new().json(&usage).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct LlmPricesController;
+
+#[async_trait]
+impl Controller for LlmPricesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify permission
+        request.require_permission("llm.usage.view", Scope::Global)?;
+        
+        let pool = crate::db::get_pool();
+        
+        if request.method() == &Method::Post {
+            request.require_permission("api_keys.manage", Scope::Global)?;
+            
+            let req: UpsertModelPriceRequest = request.json().map_err(Error::new)?;
+            
+            if req.prompt_usd_per_million < 0.0 || req.completion_usd_per_million < 0.0 {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Prices cannot be negative",
+                )));
+            }
+            
+            let price = sqlx::query_as::<_, LlmModelPrice>(
+                "INSERT INTO llm_model_prices (model, prompt_usd_per_million, completion_usd_per_million)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (model) DO UPDATE SET
+                    prompt_usd_per_million = EXCLUDED.prompt_usd_per_million,
+                    completion_usd_per_million = EXCLUDED.completion_usd_per_million
+                 RETURNING *"
+            )
+            .bind(req.model.trim())
+            .bind(req.prompt_usd_per_million)
+            .bind(req.completion_usd_per_million)
+            .fetch_one(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            return Response::new().json(&price).map_err(Error::new);
+        }
+        
+        let prices = sqlx::query_as::<_, LlmModelPrice>("SELECT * FROM llm_model_prices ORDER BY model")
+            .fetch_all(pool)
+            .await
+            .map_err(Error::new)?;
+        
+        Response::new().json(&prices).map_err(Error::new)
+    }
+}
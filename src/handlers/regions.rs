@@ -0,0 +1,42 @@
+use rwf::prelude::*;
+use crate::models::*;
+use crate::middleware::auth::RequestUserExt;
+
+#[derive(Default)]
+pub struct RegionsController;
+
+#[async_trait]
+impl Controller for RegionsController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        // Verify user is authenticated
+        let _user_id = RequestUserExt::user_id(request)?;
+        
+        let pool = crate::db::get_pool();
+        let query = request.query();
+        
+        let parent_code = query.get::<String>("parent_code");
+        let level = query.get::<String>("level");
+        let search = query.get::<String>("q").filter(|q| !q.trim().is_empty());
+        let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
+        
+        // Without a search term this walks the hierarchy, provinces first.
+        let regions = sqlx::query_as::<_, Region>(
+            "SELECT * FROM regions
+             WHERE ($1::varchar IS NULL OR parent_code = $1)
+             AND ($2::varchar IS NULL OR level = $2)
+             AND ($3::text IS NULL OR name <% $3)
+             AND ($1::varchar IS NOT NULL OR $2::varchar IS NOT NULL OR $3::text IS NOT NULL OR parent_code IS NULL)
+             ORDER BY CASE WHEN $3::text IS NULL THEN 0 ELSE word_similarity(name, $3) END DESC, code ASC
+             LIMIT $4"
+        )
+        .bind(parent_code)
+        .bind(level)
+        .bind(search)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        Response::new().json(&regions).map_err(Error::new)
+    }
+}
@@ -2,6 +2,16 @@ use rwf::prelude::*;
 use uuid::Uuid;
 use crate::models::*;
 use crate::middleware::auth::RequestUserExt;
+use crate::services::moderation::{self, ContentType};
+use crate::services::{duplicates, pseudonyms, regions};
+
+/// Ids the caller's reports and tickets are filed under, including their pseudonym
+pub(crate) async fn reporter_ids(request: &Request, pool: &sqlx::PgPool) -> Result<Vec<Uuid>, Error> {
+    let user_id: Uuid = RequestUserExt::user_id(request)?;
+    let master = pseudonyms::master_key().map_err(Error::new)?;
+
+    pseudonyms::reporter_ids(pool, &master, user_id).await.map_err(Error::new)
+}
 
 #[derive(Default, macros::RestController)]
 pub struct ReportsController;
@@ -11,19 +21,20 @@ impl RestController for ReportsController {
     type Resource = String;
     
     async fn list(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         
         let query = request.query();
         let status = query.get::<String>("status");
         let category_id = query.get::<String>("category_id")
             .and_then(|s| Uuid::parse_str(&s).ok());
+        let region_code = query.get::<String>("region_code");
         
         let limit = query.get::<i64>("limit").unwrap_or(50).min(100);
         let offset = query.get::<i64>("offset").unwrap_or(0);
         
         let mut sql = String::from(
-            "SELECT r.*, row_to_json(c.*) as category FROM reports r LEFT JOIN categories c ON r.category_id = c.id WHERE r.user_id = $1"
+            "SELECT r.*, row_to_json(c.*) as category FROM reports r LEFT JOIN categories c ON r.category_id = c.id WHERE r.user_id = ANY($1)"
         );
         
         let mut bind_index = 2;
@@ -35,13 +46,18 @@ impl RestController for ReportsController {
             sql.push_str(&format!(" AND r.category_id = ${}", bind_index));
             bind_index += 1;
         }
+        if region_code.is_some() {
+            sql.push_str(&format!(" AND {}", regions::sql_filter("r.region_code", bind_index)));
+            bind_index += 1;
+        }
         
         sql.push_str(&format!(" ORDER BY r.created_at DESC LIMIT ${} OFFSET ${}", bind_index, bind_index + 1));
         
-        let mut query_obj = sqlx::query_as::<sqlx::Postgres, ReportWithCategory>(&sql).bind(user_id);
+        let mut query_obj = sqlx::query_as::<sqlx::Postgres, ReportWithCategory>(&sql).bind(&reporters);
         
         if let Some(ref s) = status { query_obj = query_obj.bind(s); }
         if let Some(c) = category_id { query_obj = query_obj.bind(c); }
+        if let Some(ref r) = region_code { query_obj = query_obj.bind(r); }
         
         let reports = query_obj
             .bind(limit)
@@ -54,15 +70,15 @@ impl RestController for ReportsController {
     }
     
     async fn get(&self, request: &Request, id: &String) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         let report_id = Uuid::parse_str(id).map_err(Error::new)?;
         
         let report = sqlx::query_as::<sqlx::Postgres, ReportWithCategory>(
-            "SELECT r.*, row_to_json(c.*) as category FROM reports r LEFT JOIN categories c ON r.category_id = c.id WHERE r.id = $1 AND r.user_id = $2"
+            "SELECT r.*, row_to_json(c.*) as category FROM reports r LEFT JOIN categories c ON r.category_id = c.id WHERE r.id = $1 AND r.user_id = ANY($2)"
         )
         .bind(report_id)
-        .bind(user_id)
+        .bind(&reporters)
         .fetch_optional(pool)
         .await
         .map_err(Error::new)?
@@ -76,18 +92,40 @@ impl RestController for ReportsController {
         let pool = crate::db::get_pool();
         let req: CreateReportRequest = request.json().map_err(Error::new)?;
         
+        // Pseudonymous reports and their tickets belong to the citizen's pseudonym
+        let pseudonymous = pseudonyms::is_pseudonymous(req.anonymity.as_deref()).map_err(Error::new)?;
+        let reporter_id = if pseudonymous {
+            let master = pseudonyms::master_key().map_err(Error::new)?;
+            pseudonyms::find_or_create(pool, &master, user_id).await.map_err(Error::new)?
+        } else {
+            user_id
+        };
+        // The chat session is the citizen's, so a pseudonymous report keeps no link to it
+        let session_id = (!pseudonymous).then_some(req.session_id);
+        
         let report = sqlx::query_as::<sqlx::Postgres, Report>(
-            "INSERT INTO reports (session_id, user_id, category_id, title, description, location_text, latitude, longitude, incident_date, status, is_complete, completeness_score) 
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'submitted', false, 0.0) RETURNING *"
+            "INSERT INTO reports (session_id, user_id, anonymity, category_id, title, description, location_text, latitude, longitude, incident_date, status, is_complete, completeness_score) 
+            VALUES ($1, $2, COALESCE($3, 'identified'), $4, $5, $6, $7, $8, $9, $10, 'submitted', false, 0.0) RETURNING *"
         )
-        .bind(req.session_id).bind(user_id).bind(req.category_id)
+        .bind(session_id).bind(reporter_id).bind(req.anonymity).bind(req.category_id)
         .bind(req.title).bind(req.description).bind(req.location_text)
         .bind(req.latitude).bind(req.longitude).bind(req.incident_date)
         .fetch_one(pool).await.map_err(Error::new)?;
         
         let tkt = format!("TKT-{}", &report.id.to_string()[..8].to_uppercase());
         sqlx::query("INSERT INTO tickets (ticket_number, report_id, user_id, status, priority) VALUES ($1, $2, $3, 'open', 'medium')")
-            .bind(&tkt).bind(report.id).bind(user_id).execute(pool).await.map_err(Error::new)?;
+            .bind(&tkt).bind(report.id).bind(reporter_id).execute(pool).await.map_err(Error::new)?;
+        
+        // Reports that cannot be placed yet are retried by the clustering job.
+        let region_code = regions::resolve_report(pool, report.id).await.map_err(Error::new)?;
+        let mut report = Report { region_code, ..report };
+        
+        if let Some(status) = moderation::screen(pool, ContentType::Report, report.id).await.map_err(Error::new)? {
+            report.moderation_status = status.to_string();
+        }
+        
+        // The citizen answers through /reports/:id/duplicates
+        duplicates::detect(pool, report.id).await.map_err(Error::new)?;
         
         Response::new().json(&report).map_err(Error::new)
     }
@@ -98,11 +136,50 @@ impl RestController for ReportsController {
         let report_id = Uuid::parse_str(id).map_err(Error::new)?;
         let req: UpdateReportRequest = request.json().map_err(Error::new)?;
         
-        let report = sqlx::query_as::<sqlx::Postgres, Report>(
-            "UPDATE reports SET title = COALESCE($1, title), description = COALESCE($2, description), updated_at = NOW() WHERE id = $3 AND user_id = $4 RETURNING *"
+        let master = pseudonyms::master_key().map_err(Error::new)?;
+        let reporters = pseudonyms::reporter_ids(pool, &master, user_id).await.map_err(Error::new)?;
+        
+        // Switching modes moves the report and its ticket to the citizen or their pseudonym
+        let reporter_id = match req.anonymity.as_deref() {
+            Some(mode) if pseudonyms::is_pseudonymous(Some(mode)).map_err(Error::new)? => {
+                Some(pseudonyms::find_or_create(pool, &master, user_id).await.map_err(Error::new)?)
+            }
+            Some(_) => Some(user_id),
+            None => None,
+        };
+        
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        let mut report = sqlx::query_as::<sqlx::Postgres, Report>(
+            "UPDATE reports SET
+                title = COALESCE($1, title),
+                description = COALESCE($2, description),
+                anonymity = COALESCE($3, anonymity),
+                user_id = COALESCE($4, user_id),
+                updated_at = NOW()
+             WHERE id = $5 AND user_id = ANY($6) RETURNING *"
         )
-        .bind(req.title).bind(req.description).bind(report_id).bind(user_id)
-        .fetch_one(pool).await.map_err(Error::new)?;
+        .bind(&req.title).bind(&req.description).bind(req.anonymity).bind(reporter_id)
+        .bind(report_id).bind(&reporters)
+        .fetch_optional(&mut *tx).await.map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        
+        sqlx::query("UPDATE tickets SET user_id = $1, updated_at = NOW() WHERE report_id = $2 AND user_id <> $1")
+            .bind(report.user_id).bind(report.id)
+            .execute(&mut *tx).await.map_err(Error::new)?;
+        
+        // A draft keeps its chat until it is submitted
+        if report.status != "draft" && pseudonyms::detach_session(&mut tx, report.id).await.map_err(Error::new)? {
+            report.session_id = None;
+        }
+        
+        tx.commit().await.map_err(Error::new)?;
+        
+        if (req.title.is_some() || req.description.is_some())
+            && let Some(status) = moderation::screen(pool, ContentType::Report, report.id).await.map_err(Error::new)?
+        {
+            report.moderation_status = status.to_string();
+        }
         
         Response::new().json(&report).map_err(Error::new)
     }
@@ -113,6 +190,7 @@ pub struct UpdateReportRequest {
     pub title: Option<String>,
     pub description: Option<String>,
     pub category_id: Option<Uuid>,
+    pub anonymity: Option<String>,
 }
 
 #[derive(Default)]
@@ -121,16 +199,136 @@ pub struct ReportCompleteController;
 #[async_trait]
 impl Controller for ReportCompleteController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         let id_str = request.parameter::<String>("id")?.unwrap_or_default();
         let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
         
-        let report = sqlx::query_as::<sqlx::Postgres, Report>(
-            "UPDATE reports SET is_complete = true, updated_at = NOW() WHERE id = $1 AND user_id = $2 RETURNING *"
+        let status: String = sqlx::query_scalar("SELECT status FROM reports WHERE id = $1 AND user_id = ANY($2)")
+            .bind(id).bind(&reporters)
+            .fetch_optional(pool).await.map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        
+        let mut tx = pool.begin().await.map_err(Error::new)?;
+        
+        // Completing a chat draft submits it
+        let mut report = sqlx::query_as::<sqlx::Postgres, Report>(
+            "UPDATE reports SET
+                is_complete = true,
+                status = CASE WHEN status = 'draft' THEN 'submitted' ELSE status END,
+                updated_at = NOW()
+             WHERE id = $1 AND user_id = ANY($2) RETURNING *"
         )
-        .bind(id).bind(user_id).fetch_one(pool).await.map_err(Error::new)?;
+        .bind(id).bind(&reporters).fetch_one(&mut *tx).await.map_err(Error::new)?;
+        
+        if pseudonyms::detach_session(&mut tx, report.id).await.map_err(Error::new)? {
+            report.session_id = None;
+        }
+        
+        tx.commit().await.map_err(Error::new)?;
+        
+        if status == "draft" {
+            let tkt = format!("TKT-{}", &report.id.to_string()[..8].to_uppercase());
+            sqlx::query(
+                "INSERT INTO tickets (ticket_number, report_id, user_id, status, priority)
+                 SELECT $1, $2, $3, 'open', 'medium'
+                 WHERE NOT EXISTS (SELECT 1 FROM tickets WHERE report_id = $2)"
+            )
+            .bind(&tkt).bind(report.id).bind(report.user_id).execute(pool).await.map_err(Error::new)?;
+            
+            // Drafts are screened once, when they are about to be published
+            if let Some(status) = moderation::screen(pool, ContentType::Report, report.id).await.map_err(Error::new)? {
+                report.moderation_status = status.to_string();
+            }
+        }
         
         Response::new().json(&report).map_err(Error::new)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Default)]
+pub struct ReportDisclosuresController;
+
+#[async_trait]
+impl Controller for ReportDisclosuresController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        let report = sqlx::query_as::<sqlx::Postgres, Report>(
+            "SELECT * FROM reports WHERE id = $1 AND user_id = ANY($2)"
+        )
+        .bind(id).bind(&reporters)
+        .fetch_optional(pool).await.map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Report not found")))?;
+        
+        if request.method() == &Method::Post {
+            if report.anonymity != "pseudonymous" {
+                return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Report is not pseudonymous")));
+            }
+            
+            let req: DiscloseIdentityRequest = request.json().map_err(Error::new)?;
+            
+            // The institution finds the citizen through the pseudonym's escrow
+            let disclosure = sqlx::query_as::<sqlx::Postgres, IdentityDisclosure>(
+                "INSERT INTO identity_disclosures (report_id, institution_id)
+                 VALUES ($1, $2)
+                 ON CONFLICT (report_id, institution_id) DO UPDATE SET report_id = EXCLUDED.report_id
+                 RETURNING *"
+            )
+            .bind(report.id).bind(req.institution_id)
+            .fetch_one(pool).await.map_err(Error::new)?;
+            
+            return Response::new().json(&disclosure).map_err(Error::new);
+        }
+        
+        if request.method() == &Method::Delete {
+            let institution_id = request.query().get::<String>("institution_id")
+                .and_then(|s| Uuid::parse_str(&s).ok())
+                .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Missing institution_id")))?;
+            
+            sqlx::query("DELETE FROM identity_disclosures WHERE report_id = $1 AND institution_id = $2")
+                .bind(report.id).bind(institution_id)
+                .execute(pool).await.map_err(Error::new)?;
+            
+            return Ok(Response::new());
+        }
+        
+        let disclosures = sqlx::query_as::<sqlx::Postgres, IdentityDisclosure>(
+            "SELECT * FROM identity_disclosures WHERE report_id = $1 ORDER BY created_at ASC"
+        )
+        .bind(report.id)
+        .fetch_all(pool).await.map_err(Error::new)?;
+        
+        Response::new().json(&disclosures).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct ReportDuplicatesController;
+
+#[async_trait]
+impl Controller for ReportDuplicatesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        if request.method() == &Method::Post {
+            let req: ConfirmDuplicateRequest = request.json().map_err(Error::new)?;
+            
+            let answered = duplicates::answer(pool, id, req.candidate_id, &reporters, req.same)
+                .await.map_err(Error::new)?;
+            if !answered {
+                return Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Duplicate candidate not found")));
+            }
+        }
+        
+        let candidates = duplicates::candidates(pool, id, &reporters).await.map_err(Error::new)?;
+        
+        Response::new().json(&candidates).map_err(Error::new)
+    }
+}
@@ -3,6 +3,11 @@ use uuid::Uuid;
 use serde::Deserialize;
 use crate::models::*;
 use crate::middleware::auth::RequestUserExt;
+use crate::middleware::principal::Scope;
+use crate::middleware::rbac::denied;
+use crate::services::moderation::{self, ContentType};
+use crate::services::resolution;
+use super::reports::reporter_ids;
 
 #[derive(Default)]
 pub struct TicketsListController;
@@ -10,8 +15,8 @@ pub struct TicketsListController;
 #[async_trait]
 impl Controller for TicketsListController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         
         let query = request.query();
         let status = query.get::<String>("status");
@@ -21,7 +26,12 @@ impl Controller for TicketsListController {
         let limit: i64 = query.get::<i64>("limit").unwrap_or(50).min(100);
         let offset: i64 = query.get::<i64>("offset").unwrap_or(0);
         
-        let mut sql = String::from("SELECT t.* FROM tickets t WHERE t.user_id = $1");
+        // Supporters follow the ticket of the report their duplicate was merged into
+        let mut sql = String::from(
+            "SELECT t.* FROM tickets t WHERE (t.user_id = ANY($1) OR EXISTS (
+                SELECT 1 FROM report_supporters s WHERE s.report_id = t.report_id AND s.user_id = ANY($1)
+            ))"
+        );
         
         let mut bind_index = 2;
         if status.is_some() {
@@ -39,7 +49,7 @@ impl Controller for TicketsListController {
         
         sql.push_str(&format!(" ORDER BY t.created_at DESC LIMIT ${} OFFSET ${}", bind_index, bind_index + 1));
         
-        let mut query_builder = sqlx::query_as::<_, Ticket>(&sql).bind(user_id);
+        let mut query_builder = sqlx::query_as::<_, Ticket>(&sql).bind(&reporters);
         
         if let Some(ref status) = status {
             query_builder = query_builder.bind(status);
@@ -68,17 +78,19 @@ pub struct TicketController;
 #[async_trait]
 impl Controller for TicketController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         
         let id_str = request.parameter::<String>("id")?.unwrap_or_default();
         let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
         
         let ticket = sqlx::query_as::<_, Ticket>(
-            "SELECT * FROM tickets WHERE id = $1 AND user_id = $2"
+            "SELECT t.* FROM tickets t WHERE t.id = $1 AND (t.user_id = ANY($2) OR EXISTS (
+                SELECT 1 FROM report_supporters s WHERE s.report_id = t.report_id AND s.user_id = ANY($2)
+            ))"
         )
         .bind(id)
-        .bind(user_id)
+        .bind(&reporters)
         .fetch_optional(pool)
         .await
         .map_err(Error::new)?
@@ -92,6 +104,7 @@ impl Controller for TicketController {
         .await
         .map_err(Error::new)?;
         
+        // The pseudonym, for pseudonymous reports
         let user = sqlx::query_as::<_, User>(
             "SELECT * FROM users WHERE id = $1"
         )
@@ -129,75 +142,121 @@ pub struct TicketCommentsController;
 #[async_trait]
 impl Controller for TicketCommentsController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         
         let id_str = request.parameter::<String>("id")?.unwrap_or_default();
         let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
         let req: AddCommentRequest = request.json().map_err(Error::new)?;
         
         let ticket = sqlx::query_as::<_, Ticket>(
-            "SELECT * FROM tickets WHERE id = $1 AND user_id = $2"
+            "SELECT * FROM tickets WHERE id = $1 AND user_id = ANY($2)"
         )
         .bind(id)
-        .bind(user_id)
+        .bind(&reporters)
         .fetch_optional(pool)
         .await
         .map_err(Error::new)?
         .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found")))?;
         
-        let comment = sqlx::query_as::<_, TicketComment>(
+        let mut comment = sqlx::query_as::<_, TicketComment>(
             "INSERT INTO ticket_comments (ticket_id, user_id, comment, is_internal)
              VALUES ($1, $2, $3, $4)
              RETURNING *"
         )
         .bind(ticket.id)
-        .bind(user_id)
+        // Under the same identity as the ticket, so a pseudonym stays one
+        .bind(ticket.user_id)
         .bind(req.comment)
         .bind(req.is_internal.unwrap_or(false))
         .fetch_one(pool)
         .await
         .map_err(Error::new)?;
         
+        if let Some(status) = moderation::screen(pool, ContentType::Comment, comment.id).await.map_err(Error::new)? {
+            comment.moderation_status = status.to_string();
+        }
+        
         Response::new().json(&comment).map_err(Error::new)
     }
 }
 
+// Statuses a reporter may set; `resolved` opens a resolution review
+const REPORTER_STATUSES: [&str; 5] = ["open", "in_progress", "pending", "reopened", "resolved"];
+
 #[derive(Default)]
 pub struct TicketStatusController;
 
 #[async_trait]
 impl Controller for TicketStatusController {
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
-        let user_id: Uuid = RequestUserExt::user_id(request)?;
         let pool = crate::db::get_pool();
+        let reporters = reporter_ids(request, pool).await?;
         
         let id_str = request.parameter::<String>("id")?.unwrap_or_default();
         let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
         let req: UpdateStatusRequest = request.json().map_err(Error::new)?;
         
-        let is_resolved = req.status == "resolved" || req.status == "closed";
+        // Citizens decide when a problem is solved: reporting it as done only
+        // opens a resolution review, and nobody can close a ticket directly.
+        if !REPORTER_STATUSES.contains(&req.status.as_str()) {
+            return Err(Error::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid status; tickets are closed by citizen resolution review",
+            )));
+        }
+        
+        if req.status == "resolved" {
+            let ticket = sqlx::query_as::<_, Ticket>(
+                "SELECT * FROM tickets WHERE id = $1 AND user_id = ANY($2)"
+            )
+            .bind(id)
+            .bind(&reporters)
+            .fetch_optional(pool)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found")))?;
+            
+            let config = crate::config::Config::load()
+                .map_err(|e| Error::new(std::io::Error::other(format!("Config error: {}", e))))?;
+            
+            let review = resolution::open_review(pool, ticket.id, Some(ticket.user_id), req.resolution, config.resolution_quorum)
+                .await
+                .map_err(Error::new)?;
+            
+            return Response::new().json(&review).map_err(Error::new);
+        }
         
+        // A ticket under review or closed only moves through its review
         let ticket = sqlx::query_as::<_, Ticket>(
             "UPDATE tickets SET
                 status = $1,
                 resolution = COALESCE($2, resolution),
-                resolved_at = CASE WHEN $3 THEN NOW() ELSE resolved_at END,
-                resolved_by = CASE WHEN $3 THEN $4 ELSE resolved_by END,
                 updated_at = NOW()
-             WHERE id = $5 AND user_id = $6
+             WHERE id = $3 AND user_id = ANY($4) AND status NOT IN ('pending_review', 'closed')
              RETURNING *"
         )
         .bind(req.status)
         .bind(req.resolution)
-        .bind(is_resolved)
-        .bind(user_id)
         .bind(id)
-        .bind(user_id)
+        .bind(&reporters)
         .fetch_optional(pool)
         .await
-        .map_err(Error::new)?
-        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found")))?;
+        .map_err(Error::new)?;
+        
+        let Some(ticket) = ticket else {
+            let exists: Option<Uuid> = sqlx::query_scalar("SELECT id FROM tickets WHERE id = $1 AND user_id = ANY($2)")
+                .bind(id)
+                .bind(&reporters)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::new)?;
+            
+            return match exists {
+                Some(_) => Ok(denied(409, "Ticket is under resolution review or closed")),
+                None => Err(Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found"))),
+            };
+        };
         
         Response::new().json(&ticket).map_err(Error::new)
     }
@@ -207,4 +266,172 @@ impl Controller for TicketStatusController {
 pub struct UpdateStatusRequest {
     pub status: String,
     pub resolution: Option<String>,
-}
\ No newline at end of file
+}
+
+const PROGRESS_STAGES: [&str; 4] = ["surveyed", "budgeted", "work_started", "work_finished"];
+
+#[derive(Default)]
+pub struct TicketProgressController;
+
+#[async_trait]
+impl Controller for TicketProgressController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        // Progress is public so citizens can review it before voting.
+        if request.method() != &Method::Post {
+            let updates = sqlx::query_as::<_, TicketProgressUpdate>(
+                "SELECT * FROM ticket_progress_updates WHERE ticket_id = $1 ORDER BY created_at ASC"
+            )
+            .bind(id)
+            .fetch_all(pool)
+            .await
+            .map_err(Error::new)?;
+            
+            return Response::new().json(&updates).map_err(Error::new);
+        }
+        
+        let user_id: Uuid = RequestUserExt::user_id(request)?;
+        let req: CreateProgressUpdateRequest = request.json().map_err(Error::new)?;
+        
+        if !PROGRESS_STAGES.contains(&req.stage.as_str()) {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid progress stage")));
+        }
+        
+        let ticket = sqlx::query_as::<_, Ticket>(
+            "SELECT * FROM tickets WHERE id = $1"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::new)?
+        .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Ticket not found")))?;
+        
+        let institution_id = ticket.assigned_institution_id
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Ticket has not been adopted by an institution")))?;
+        request.require_permission("institution.progress.post", Scope::Institution(institution_id))?;
+        
+        if ticket.status == "closed" || ticket.status == "pending_review" {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Ticket is awaiting or past citizen review")));
+        }
+        
+        let finished = req.stage == "work_finished";
+        let percentage = if finished { 100 } else { req.percentage.unwrap_or(0) };
+        
+        let update = sqlx::query_as::<_, TicketProgressUpdate>(
+            "INSERT INTO ticket_progress_updates
+                (ticket_id, institution_id, posted_by, stage, percentage, expected_completion_date, note, attachments)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             RETURNING *"
+        )
+        .bind(ticket.id)
+        .bind(institution_id)
+        .bind(user_id)
+        .bind(&req.stage)
+        .bind(percentage)
+        .bind(req.expected_completion_date)
+        .bind(&req.note)
+        .bind(req.attachments.unwrap_or(serde_json::json!([])))
+        .fetch_one(pool)
+        .await
+        .map_err(Error::new)?;
+        
+        if finished {
+            let config = crate::config::Config::load()
+                .map_err(|e| Error::new(std::io::Error::other(format!("Config error: {}", e))))?;
+            
+            resolution::open_review(pool, ticket.id, Some(user_id), req.note, config.resolution_quorum)
+                .await
+                .map_err(Error::new)?;
+        } else {
+            sqlx::query(
+                "UPDATE tickets SET status = 'in_progress', updated_at = NOW()
+                 WHERE id = $1 AND status IN ('assigned', 'reopened')"
+            )
+            .bind(ticket.id)
+            .execute(pool)
+            .await
+            .map_err(Error::new)?;
+        }
+        
+        Response::new().json(&update).map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct TicketReviewController;
+
+#[async_trait]
+impl Controller for TicketReviewController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let _user_id: Uuid = RequestUserExt::user_id(request)?;
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        
+        let review = resolution::current_review(pool, id)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "No resolution review for this ticket")))?;
+        
+        let tally = resolution::tally(pool, &review).await.map_err(Error::new)?;
+        let votes = resolution::votes(pool, review.id).await.map_err(Error::new)?;
+        
+        Response::new()
+            .json(&ResolutionReviewWithVotes { review, tally, votes })
+            .map_err(Error::new)
+    }
+}
+
+#[derive(Default)]
+pub struct TicketReviewVotesController;
+
+#[async_trait]
+impl Controller for TicketReviewVotesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let pool = crate::db::get_pool();
+        
+        let id_str = request.parameter::<String>("id")?.unwrap_or_default();
+        let id = Uuid::parse_str(&id_str).map_err(Error::new)?;
+        let req: CastVoteRequest = request.json().map_err(Error::new)?;
+        
+        if req.vote != "accept" && req.vote != "reject" {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Vote must be 'accept' or 'reject'")));
+        }
+        
+        if req.vote == "reject" && req.reason.as_deref().map(str::trim).unwrap_or_default().is_empty() {
+            return Err(Error::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "A reason is required when rejecting")));
+        }
+        
+        let review = resolution::current_review(pool, id)
+            .await
+            .map_err(Error::new)?
+            .filter(|r| r.status == "open")
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::NotFound, "No open resolution review for this ticket")))?;
+        
+        // Citizens vote as whoever filed their linked report, possibly their pseudonym
+        let reporters = reporter_ids(request, pool).await?;
+        let voter_id = resolution::eligible_voter(pool, id, &reporters)
+            .await
+            .map_err(Error::new)?
+            .ok_or_else(|| Error::new(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Only citizens linked to this problem may vote")))?;
+        
+        let Some(review) = resolution::cast_vote(pool, &review, voter_id, &req.vote, req.reason)
+            .await
+            .map_err(Error::new)?
+        else {
+            return Ok(denied(409, "This resolution round is already settled"));
+        };
+        
+        let tally = resolution::tally(pool, &review).await.map_err(Error::new)?;
+        let votes = resolution::votes(pool, review.id).await.map_err(Error::new)?;
+        
+        Response::new()
+            .json(&ResolutionReviewWithVotes { review, tally, votes })
+            .map_err(Error::new)
+    }
+}
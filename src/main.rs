@@ -7,6 +7,7 @@ mod websocket;
 mod background;
 mod db;
 mod error;
+mod routes;
 
 use rwf::prelude::*;
 use rwf::http::Server;
@@ -28,6 +29,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     rwf_admin::install()?;
     
     db::run_migrations(&db_pool).await?;
+
+    let master_key = services::providers::crypto::MasterKey::parse(&config.llm.api_key_encryption_key)?;
+
+    // `rotate-api-keys` re-seals the stored keys under NEW_API_KEY_ENCRYPTION_KEY
+    // and exits; afterwards API_KEY_ENCRYPTION_KEY must be set to the new key.
+    if std::env::args().nth(1).as_deref() == Some("rotate-api-keys") {
+        let new_key = std::env::var("NEW_API_KEY_ENCRYPTION_KEY")
+            .map_err(|_| config::ConfigError("NEW_API_KEY_ENCRYPTION_KEY not set".to_string()))?;
+        let new_master_key = services::providers::crypto::MasterKey::parse(&new_key)?;
+        let rotated = services::providers::crypto::rotate(&db_pool, &master_key, &new_master_key).await?;
+        let pseudonyms = services::pseudonyms::rotate(&db_pool, &master_key, &new_master_key).await?;
+        tracing::info!("Rotated {} API keys and {} pseudonyms to the new encryption key", rotated, pseudonyms);
+        return Ok(());
+    }
+
+    let encrypted = services::providers::crypto::encrypt_plaintext_keys(&db_pool, &master_key).await?;
+    if encrypted > 0 {
+        tracing::info!("Encrypted {} plaintext API keys", encrypted);
+    }
     
     let schedule = vec![
         background::jobs::ClusteringJob::default().schedule(
@@ -43,41 +63,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let worker = Worker::new(vec![
         background::jobs::ClusteringJob::default().job(),
         background::jobs::CleanupJob::default().job(),
+        background::jobs::EvalRunJob.job(),
+        background::jobs::ModerationScreenJob.job(),
     ])
     .clock(schedule);
 
     worker.start().await?;
     
-    let mut routes = vec![
-        route!("/health" => handlers::health::HealthCheckController),
-        
-        route!("/ws/chat" => websocket::handler::ChatWebSocketController),
-
-        route!("/chat/sessions" => handlers::chat::ChatSessionsController),
-        route!("/chat/sessions/:id" => handlers::chat::ChatSessionController),
-        route!("/chat/sessions/:id/messages" => handlers::chat::ChatMessagesController),
-        
-        route!("/reports" => handlers::reports::ReportsController),
-        route!("/reports/:id/complete" => handlers::reports::ReportCompleteController),
-        
-        route!("/tickets" => handlers::tickets::TicketsListController),
-        route!("/tickets/:id" => handlers::tickets::TicketController),
-        route!("/tickets/:id/comments" => handlers::tickets::TicketCommentsController),
-        route!("/tickets/:id/status" => handlers::tickets::TicketStatusController),    
-        
-        route!("/dashboard/stats" => handlers::dashboard::DashboardStatsController),
-        route!("/dashboard/trends" => handlers::dashboard::DashboardTrendsController),
-        route!("/dashboard/clusters" => handlers::dashboard::DashboardClustersController),
-        route!("/dashboard/heatmap" => handlers::dashboard::DashboardHeatmapController),
-        
-        route!("/panel/users" => handlers::panel::AdminUsersController),
-        route!("/panel/users/:id/role" => handlers::panel::AdminUserRoleController),
-        route!("/panel/categories" => handlers::panel::CategoriesController),
-        route!("/panel/prompts" => handlers::panel::PromptsController),
-        route!("/panel/api-keys" => handlers::panel::ApiKeysController),
-        route!("/panel/jobs" => handlers::panel::BackgroundJobsController),
-        route!("/panel/jobs/:id" => handlers::panel::BackgroundJobController),
-    ];
+    let mut routes = routes::table(&config);
 
     routes.extend(rwf_admin::routes()?);
 
@@ -4,10 +4,10 @@ use jsonwebtoken::{decode, decode_header, DecodingKey, Validation, Algorithm};
 use rwf::controller::Middleware;
 use rwf::controller::Outcome;
 use uuid::Uuid;
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use once_cell::sync::Lazy;
-use base64::{engine::general_purpose, Engine as _};
+use std::sync::Arc;
+use super::jwks::{JwksCache, JwksCacheConfig};
+use super::principal::{self, CachedUser, Principal, Scope, PRINCIPAL_HEADER};
+use super::rbac::denied;
 
 #[derive(Debug)]
 struct AuthError(String);
@@ -20,20 +20,12 @@ impl std::fmt::Display for AuthError {
 
 impl std::error::Error for AuthError {}
 
-// Global storage for user context indexed by logto_user_id (sub claim)
-static USER_CONTEXT: Lazy<Arc<RwLock<HashMap<String, UserContext>>>> = 
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-#[derive(Debug, Clone)]
-struct UserContext {
-    user_id: Uuid,
-    user: crate::models::User,
-}
-
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct LogtoAuthMiddleware {
     logto_endpoint: String,
     app_id: String,
+    api_resource: Option<String>,
+    jwks: Arc<JwksCache>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -49,45 +41,46 @@ struct Claims {
     username: Option<String>,
     #[serde(default)]
     name: Option<String>,
+    #[serde(default)]
+    client_id: Option<String>,
+    /// Space-separated scopes granted to the token
+    #[serde(default)]
+    scope: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JwksResponse {
-    keys: Vec<Jwk>,
-}
+impl Claims {
+    /// Client credentials tokens are issued to the application itself
+    fn is_client(&self) -> bool {
+        self.client_id.as_deref() == Some(self.sub.as_str())
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Jwk {
-    kid: String,
-    kty: String,
-    n: String,
-    e: String,
+    fn has_scope(&self, scope: &str) -> bool {
+        self.scope
+            .as_deref()
+            .is_some_and(|scopes| scopes.split_whitespace().any(|s| s == scope))
+    }
 }
 
 impl LogtoAuthMiddleware {
     pub fn new(config: crate::config::LogtoConfig) -> Self {
+        let jwks = Arc::new(JwksCache::new(
+            format!("{}/oidc/jwks", config.endpoint),
+            JwksCacheConfig::default(),
+        ));
+
+        // Outside a runtime the keys are still fetched on demand
+        if tokio::runtime::Handle::try_current().is_ok() {
+            jwks.spawn_refresh();
+        }
+
         Self {
             logto_endpoint: config.endpoint,
             app_id: config.app_id,
+            api_resource: config.api_resource,
+            jwks,
         }
     }
 
-    async fn get_jwks(&self) -> Result<JwksResponse, Error> {
-        let jwks_url = format!("{}/oidc/jwks", self.logto_endpoint);
-        let client = reqwest::Client::new();
-        
-        let response = client
-            .get(&jwks_url)
-            .send()
-            .await
-            .map_err(|e| Error::new(AuthError(format!("Failed to fetch JWKS: {}", e))))?;
-
-        response
-            .json()
-            .await
-            .map_err(|e| Error::new(AuthError(format!("Failed to parse JWKS: {}", e))))
-    }
-
     async fn verify_token(&self, token: &str) -> Result<Claims, Error> {
         let header = decode_header(token)
             .map_err(|e| Error::new(AuthError(format!("Invalid token header: {}", e))))?;
@@ -95,16 +88,13 @@ impl LogtoAuthMiddleware {
         let kid = header.kid
             .ok_or_else(|| Error::new(AuthError("Missing kid in token".to_string())))?;
 
-        let jwks = self.get_jwks().await?;
-        
-        let jwk = jwks
-            .keys
-            .iter()
-            .find(|k| k.kid == kid)
-            .ok_or_else(|| Error::new(AuthError("Key not found".to_string())))?;
+        let jwk = self.jwks.key(&kid).await.map_err(|e| Error::new(AuthError(e.to_string())))?;
 
         let mut validation = Validation::new(Algorithm::RS256);
-        validation.set_audience(&[&self.app_id]);
+        match &self.api_resource {
+            Some(api_resource) => validation.set_audience(&[&self.app_id, api_resource]),
+            None => validation.set_audience(&[&self.app_id]),
+        }
         validation.set_issuer(&[&format!("{}/oidc", self.logto_endpoint)]);
 
         let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e)
@@ -116,6 +106,21 @@ impl LogtoAuthMiddleware {
         Ok(token_data.claims)
     }
 
+    /// Claims of the bearer token, verified against Logto's keys
+    async fn authenticate(&self, request: &Request) -> Result<Claims, Error> {
+        let auth_header = request
+            .headers()
+            .get("authorization")
+            .or_else(|| request.headers().get("Authorization"))
+            .ok_or_else(|| Error::new(AuthError("Missing authorization header".to_string())))?;
+
+        let token = auth_header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| Error::new(AuthError("Invalid authorization format".to_string())))?;
+
+        self.verify_token(token).await
+    }
+
     async fn get_or_create_user(&self, claims: &Claims, pool: &sqlx::PgPool) -> Result<crate::models::User, Error> {
         let user = sqlx::query_as::<_, crate::models::User>(
             "SELECT * FROM users WHERE logto_user_id = $1"
@@ -146,147 +151,176 @@ impl LogtoAuthMiddleware {
 
         Ok(new_user)
     }
-}
 
-#[async_trait]
-impl Middleware for LogtoAuthMiddleware {
-    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
-        let path = request.path().path();
-        if path.starts_with("/auth/") || path == "/health" {
-            return Ok(Outcome::Forward(request));
+    /// The caller's user row and memberships, from the cache when fresh
+    async fn load_user(&self, claims: &Claims, pool: &sqlx::PgPool) -> Result<CachedUser, Error> {
+        if let Some(cached) = principal::cached_user(&claims.sub) {
+            return Ok(cached);
         }
+        let generation = principal::cache_generation();
 
-        let auth_header = request
-            .headers()
-            .get("authorization")
-            .or_else(|| request.headers().get("Authorization"))
-            .ok_or_else(|| Error::new(AuthError("Missing authorization header".to_string())))?;
+        let user = self.get_or_create_user(claims, pool).await?;
+        let memberships = sqlx::query_as::<_, crate::models::InstitutionMember>(
+            "SELECT m.* FROM institution_members m
+             JOIN institutions i ON i.id = m.institution_id
+             WHERE m.user_id = $1 AND i.is_active = true"
+        )
+        .bind(user.id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::new(AuthError(format!("Database error: {}", e))))?;
 
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .ok_or_else(|| Error::new(AuthError("Invalid authorization format".to_string())))?;
+        let grants = crate::services::permissions::grants_for(pool, user.id, &user.role)
+            .await
+            .map_err(|e| Error::new(AuthError(format!("Database error: {}", e))))?;
 
-        let claims = self.verify_token(token).await?;
+        let cached = CachedUser { user, memberships, grants };
+        principal::cache_user(claims.sub.clone(), cached.clone(), generation);
+
+        Ok(cached)
+    }
 
+    /// The caller behind verified claims, or `None` for an unknown client
+    async fn resolve(&self, claims: &Claims) -> Result<Option<Principal>, Error> {
         let pool = crate::db::get_pool();
-        let user = self.get_or_create_user(&claims, pool).await?;
-
-        // Store user context indexed by logto_user_id
-        {
-            let mut context = USER_CONTEXT.write().unwrap();
-            context.insert(claims.sub.clone(), UserContext {
-                user_id: user.id,
-                user: user.clone(),
-            });
+        let caller = if claims.is_client() {
+            self.load_client(claims, pool).await?
+        } else {
+            Some(self.load_user(claims, pool).await?)
+        };
+
+        let Some(CachedUser { user, memberships, mut grants }) = caller else {
+            return Ok(None);
+        };
+
+        // Clients get what their token was scoped to, named after permissions
+        if claims.is_client() {
+            grants.retain(|grant| claims.has_scope(&grant.permission));
         }
 
+        Ok(Some(Principal {
+            user,
+            memberships,
+            grants,
+            expires_at: claims.exp,
+        }))
+    }
+
+    /// The caller behind a bearer token, for connections that cannot carry
+    /// an `Authorization` header, such as WebSockets opened by a browser
+    pub async fn principal_for_token(&self, token: &str) -> Result<Principal, Error> {
+        let claims = self.verify_token(token).await?;
+
+        self.resolve(&claims)
+            .await?
+            .ok_or_else(|| Error::new(AuthError("Unknown API client".to_string())))
+    }
+
+    /// The service account of a machine-to-machine client, from the cache
+    /// when fresh. Clients are never given a citizen account, so `None` is
+    /// returned for clients not registered through the panel.
+    async fn load_client(&self, claims: &Claims, pool: &sqlx::PgPool) -> Result<Option<CachedUser>, Error> {
+        let cache_key = format!("client:{}", claims.sub);
+        if let Some(cached) = principal::cached_user(&cache_key) {
+            return Ok(Some(cached));
+        }
+        let generation = principal::cache_generation();
+
+        let client = sqlx::query_as::<_, (Uuid, Uuid)>(
+            "SELECT c.user_id, c.institution_id FROM api_clients c
+             JOIN institutions i ON i.id = c.institution_id
+             WHERE c.logto_client_id = $1 AND c.is_active = true AND i.is_active = true"
+        )
+        .bind(&claims.sub)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| Error::new(AuthError(format!("Database error: {}", e))))?;
+
+        let Some((user_id, institution_id)) = client else {
+            return Ok(None);
+        };
+
+        let user = sqlx::query_as::<_, crate::models::User>(
+            "SELECT * FROM users WHERE id = $1"
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| Error::new(AuthError(format!("Database error: {}", e))))?;
+
+        // A client only ever acts for its own institution
+        let mut grants = crate::services::permissions::grants_for(pool, user.id, &user.role)
+            .await
+            .map_err(|e| Error::new(AuthError(format!("Database error: {}", e))))?;
+        grants.retain(|grant| grant.institution_id == Some(institution_id));
+
+        let cached = CachedUser { user, memberships: Vec::new(), grants };
+        principal::cache_user(cache_key, cached.clone(), generation);
+
+        Ok(Some(cached))
+    }
+}
+
+#[async_trait]
+impl Middleware for LogtoAuthMiddleware {
+    async fn handle_request(&self, mut request: Request) -> Result<Outcome, Error> {
+        // Only this middleware may set the principal
+        request.head_mut().headers_mut().remove(PRINCIPAL_HEADER);
+
+        let claims = match self.authenticate(&request).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                let response = denied(401, &e.to_string());
+                return Ok(Outcome::Stop(request, response));
+            }
+        };
+
+        let Some(principal) = self.resolve(&claims).await? else {
+            let response = denied(403, "Unknown API client");
+            return Ok(Outcome::Stop(request, response));
+        };
+
+        let encoded = principal.encode().map_err(Error::new)?;
+        request.head_mut().headers_mut().insert(PRINCIPAL_HEADER, encoded);
+
         Ok(Outcome::Forward(request))
     }
 }
 
 pub trait RequestUserExt {
+    fn principal(&self) -> Result<Principal, Error>;
     fn user_id(&self) -> Result<Uuid, Error>;
     fn get_user(&self) -> Result<crate::models::User, Error>;
-    fn require_role(&self, required_role: &str) -> Result<(), Error>;
+    fn require_permission(&self, permission: &str, scope: Scope) -> Result<(), Error>;
 }
 
 impl RequestUserExt for Request {
-    fn user_id(&self) -> Result<Uuid, Error> {
-        // Extract token and get claims again
-        let auth_header = self
+    /// The caller as verified by `LogtoAuthMiddleware`
+    fn principal(&self) -> Result<Principal, Error> {
+        let principal = self
             .headers()
-            .get("authorization")
-            .or_else(|| self.headers().get("Authorization"))
-            .ok_or_else(|| Error::new(AuthError("Missing authorization header".to_string())))?;
+            .get(PRINCIPAL_HEADER)
+            .and_then(|value| Principal::decode(value))
+            .ok_or_else(|| Error::new(AuthError("Not authenticated".to_string())))?;
 
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .ok_or_else(|| Error::new(AuthError("Invalid authorization format".to_string())))?;
-
-        // Decode token without validation (we already validated in middleware)
-        let token_data = decode::<Claims>(
-            token, 
-            &DecodingKey::from_secret(&[]), 
-            &Validation::default()
-        );
-        
-        // If decoding fails, try to extract sub from token manually
-        let sub = if let Ok(data) = token_data {
-            data.claims.sub
-        } else {
-            // Fallback: decode JWT payload manually
-            let parts: Vec<&str> = token.split('.').collect();
-            if parts.len() != 3 {
-                return Err(Error::new(AuthError("Invalid token format".to_string())));
-            }
-            
-            let payload = general_purpose::URL_SAFE_NO_PAD
-                .decode(parts[1])
-                .map_err(|_| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid base64")))?;
-            
-            let claims: Claims = serde_json::from_slice(&payload)
-                .map_err(|_| Error::new(AuthError("Failed to parse claims".to_string())))?;
-            
-            claims.sub
-        };
+        if principal.is_expired() {
+            return Err(Error::new(AuthError("Token expired".to_string())));
+        }
 
-        let context = USER_CONTEXT.read().unwrap();
-        let user_context = context
-            .get(&sub)
-            .ok_or_else(|| Error::new(AuthError("User context not found".to_string())))?;
+        Ok(principal)
+    }
 
-        Ok(user_context.user_id)
+    fn user_id(&self) -> Result<Uuid, Error> {
+        Ok(self.principal()?.user_id())
     }
 
     fn get_user(&self) -> Result<crate::models::User, Error> {
-        // Extract token and get claims again
-        let auth_header = self
-            .headers()
-            .get("authorization")
-            .or_else(|| self.headers().get("Authorization"))
-            .ok_or_else(|| Error::new(AuthError("Missing authorization header".to_string())))?;
-
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .ok_or_else(|| Error::new(AuthError("Invalid authorization format".to_string())))?;
-
-        // Decode JWT payload manually
-        let parts: Vec<&str> = token.split('.').collect();
-        if parts.len() != 3 {
-            return Err(Error::new(AuthError("Invalid token format".to_string())));
-        }
-        
-        let payload = general_purpose::URL_SAFE_NO_PAD
-            .decode(parts[1])
-            .map_err(|_| Error::new(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid base64")))?;
-        
-        let claims: Claims = serde_json::from_slice(&payload)
-            .map_err(|_| Error::new(AuthError("Failed to parse claims".to_string())))?;
-
-        let context = USER_CONTEXT.read().unwrap();
-        let user_context = context
-            .get(&claims.sub)
-            .ok_or_else(|| Error::new(AuthError("User context not found".to_string())))?;
-
-        Ok(user_context.user.clone())
+        Ok(self.principal()?.user)
     }
 
-    fn require_role(&self, required_role: &str) -> Result<(), Error> {
-        let user = self.get_user()?;
-        
-        let roles_hierarchy = vec!["user", "moderator", "admin"];
-        let user_role_index = roles_hierarchy
-            .iter()
-            .position(|r| r == &user.role)
-            .ok_or_else(|| Error::new(AuthError("Invalid user role".to_string())))?;
-
-        let required_role_index = roles_hierarchy
-            .iter()
-            .position(|r| r == &required_role)
-            .ok_or_else(|| Error::new(AuthError("Invalid required role".to_string())))?;
-
-        if user_role_index < required_role_index {
-            return Err(Error::new(AuthError("Insufficient permissions".to_string())));
+    fn require_permission(&self, permission: &str, scope: Scope) -> Result<(), Error> {
+        if !self.principal()?.has_permission(permission, scope) {
+            return Err(Error::new(AuthError(format!("Missing permission {}", permission))));
         }
 
         Ok(())
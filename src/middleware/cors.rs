@@ -8,15 +8,43 @@ pub struct CorsMiddleware {
 }
 
 impl CorsMiddleware {
-    pub fn new() -> Self {
+    pub fn with_origins(origins: Vec<String>) -> Self {
         Self {
-            allowed_origins: vec!["*".to_string()],
+            allowed_origins: origins,
         }
     }
 
-    pub fn with_origins(origins: Vec<String>) -> Self {
-        Self {
-            allowed_origins: origins,
+    /// The origin to send back, if it is allowed: a listed origin is echoed,
+    /// otherwise "*" when any origin is allowed
+    fn allowed_origin<'a>(&self, request: &'a Request) -> Option<&'a str> {
+        let origin = request
+            .headers()
+            .get("origin")
+            .or_else(|| request.headers().get("Origin"))
+            .map(|s| s.as_str())?;
+
+        if self.allowed_origins.iter().any(|allowed| allowed == origin) {
+            Some(origin)
+        } else if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            Some("*")
+        } else {
+            None
+        }
+    }
+
+    /// Credentials are only allowed for listed origins, never for "*"
+    fn cors_headers(response: Response, origin: &str) -> Response {
+        let response = response
+            .header("Access-Control-Allow-Origin", origin)
+            .header("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS")
+            .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
+            .header("Access-Control-Max-Age", "86400")
+            .header("Vary", "Origin");
+
+        if origin == "*" {
+            response
+        } else {
+            response.header("Access-Control-Allow-Credentials", "true")
         }
     }
 }
@@ -26,19 +54,10 @@ impl Middleware for CorsMiddleware {
     async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
         // Handle OPTIONS preflight request
         if request.method().to_string() == "OPTIONS" {
-            let origin = request
-                .headers()
-                .get("origin")
-                .or_else(|| request.headers().get("Origin"))
-                .map(|s| s.as_str())
-                .unwrap_or("*");
-
-            let response = Response::new()
-                .header("Access-Control-Allow-Origin", origin)
-                .header("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS")
-                .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
-                .header("Access-Control-Allow-Credentials", "true")
-                .header("Access-Control-Max-Age", "86400");
+            let mut response = Response::new().code(204);
+            if let Some(origin) = self.allowed_origin(&request) {
+                response = Self::cors_headers(response, origin);
+            }
             
             return Ok(Outcome::Stop(request, response));
         }
@@ -47,20 +66,10 @@ impl Middleware for CorsMiddleware {
     }
 
     async fn handle_response(&self, request: &Request, response: Response) -> Result<Response, Error> {
-        let origin = request
-            .headers()
-            .get("origin")
-            .or_else(|| request.headers().get("Origin"))
-            .map(|s| s.as_str())
-            .unwrap_or("*");
+        let Some(origin) = self.allowed_origin(request) else {
+            return Ok(response);
+        };
 
-        let response = response
-            .header("Access-Control-Allow-Origin", origin)
-            .header("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS")
-            .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
-            .header("Access-Control-Allow-Credentials", "true")
-            .header("Access-Control-Max-Age", "86400");
-
-        Ok(response)
+        Ok(Self::cors_headers(response, origin))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,472 @@
+//! Cache for Logto's signing keys. Keys are kept for as long as the JWKS
+//! response's `Cache-Control` allows, refreshed in the background before they
+//! expire, and refetched early when a token names a key we have not seen
+//! (Logto rotated its keys). When Logto cannot be reached the last keys that
+//! were fetched successfully keep being used for a while.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum JwksError {
+    Fetch(String),
+    UnknownKey(String),
+}
+
+impl std::fmt::Display for JwksError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwksError::Fetch(message) => write!(f, "Failed to fetch JWKS: {}", message),
+            JwksError::UnknownKey(kid) => write!(f, "Key not found: {}", kid),
+        }
+    }
+}
+
+impl std::error::Error for JwksError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JwksResponse {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kid: String,
+    pub kty: String,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwksCacheConfig {
+    /// Lifetime when the response has no `max-age`
+    pub default_ttl: Duration,
+    pub min_ttl: Duration,
+    pub max_ttl: Duration,
+    /// How long expired keys are still used while Logto is unreachable
+    pub max_stale: Duration,
+    /// Minimum time between two fetches, so tokens with made-up `kid`s or an
+    /// outage cannot turn every request into a call to Logto
+    pub min_refresh_interval: Duration,
+    pub request_timeout: Duration,
+}
+
+impl Default for JwksCacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(600),
+            min_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(86_400),
+            max_stale: Duration::from_secs(86_400),
+            min_refresh_interval: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    keys: HashMap<String, Jwk>,
+    expires_at: Option<Instant>,
+    /// When the last fetch finished, successful or not
+    last_attempt: Option<Instant>,
+    last_error: Option<String>,
+}
+
+pub struct JwksCache {
+    url: String,
+    config: JwksCacheConfig,
+    client: reqwest::Client,
+    state: RwLock<CacheState>,
+    // Lets concurrent requests wait for one fetch instead of each starting their own
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl JwksCache {
+    pub fn new(url: String, config: JwksCacheConfig) -> Self {
+        Self {
+            url,
+            config,
+            client: reqwest::Client::new(),
+            state: RwLock::new(CacheState::default()),
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The key with the given `kid`, fetching the key set when it is missing
+    /// or expired.
+    pub async fn key(&self, kid: &str) -> Result<Jwk, JwksError> {
+        if let Some(jwk) = self.cached(kid, Duration::ZERO) {
+            return Ok(jwk);
+        }
+
+        let mut error = None;
+        if self.may_refresh() {
+            match self.refresh().await {
+                // Just fetched, so usable whatever its max-age
+                Ok(()) => return self.lookup(kid),
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    error = Some(e);
+                }
+            }
+        }
+
+        // Last known good keys, if the refresh failed or was skipped
+        if let Some(jwk) = self.cached(kid, self.config.max_stale) {
+            return Ok(jwk);
+        }
+
+        Err(error.unwrap_or_else(|| JwksError::UnknownKey(kid.to_string())))
+    }
+
+    /// Fetches the key set now, unless another caller finished a fetch
+    /// while this one waited for its turn.
+    pub async fn refresh(&self) -> Result<(), JwksError> {
+        let requested = Instant::now();
+        let _guard = self.refresh_lock.lock().await;
+
+        {
+            let state = self.state.read().unwrap();
+            if state.last_attempt.is_some_and(|at| at > requested) {
+                return match &state.last_error {
+                    Some(message) => Err(JwksError::Fetch(message.clone())),
+                    None => Ok(()),
+                };
+            }
+        }
+
+        let fetched = self.fetch().await;
+        let mut state = self.state.write().unwrap();
+        state.last_attempt = Some(Instant::now());
+
+        match fetched {
+            Ok((keys, ttl)) => {
+                state.keys = keys.into_iter().map(|jwk| (jwk.kid.clone(), jwk)).collect();
+                state.expires_at = Some(Instant::now() + ttl);
+                state.last_error = None;
+                Ok(())
+            }
+            Err(message) => {
+                state.last_error = Some(message.clone());
+                Err(JwksError::Fetch(message))
+            }
+        }
+    }
+
+    /// Keeps the cache warm by refreshing shortly before it expires. The
+    /// task ends once the cache is dropped.
+    pub fn spawn_refresh(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let cache: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                let Some(wait) = cache.upgrade().map(|cache| cache.until_refresh()) else {
+                    return;
+                };
+                tokio::time::sleep(wait).await;
+
+                let Some(cache) = cache.upgrade() else {
+                    return;
+                };
+                if let Err(e) = cache.refresh().await {
+                    tracing::warn!("Background JWKS refresh failed: {}", e);
+                }
+            }
+        })
+    }
+
+    fn lookup(&self, kid: &str) -> Result<Jwk, JwksError> {
+        self.state
+            .read()
+            .unwrap()
+            .keys
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| JwksError::UnknownKey(kid.to_string()))
+    }
+
+    fn cached(&self, kid: &str, grace: Duration) -> Option<Jwk> {
+        let state = self.state.read().unwrap();
+        let usable = state.expires_at.is_some_and(|at| Instant::now() < at + grace);
+
+        if usable { state.keys.get(kid).cloned() } else { None }
+    }
+
+    fn may_refresh(&self) -> bool {
+        let state = self.state.read().unwrap();
+
+        state
+            .last_attempt
+            .is_none_or(|at| at.elapsed() >= self.config.min_refresh_interval)
+    }
+
+    fn until_refresh(&self) -> Duration {
+        let state = self.state.read().unwrap();
+        let remaining = state
+            .expires_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+
+        remaining
+            .saturating_sub(self.config.min_refresh_interval)
+            .max(self.config.min_refresh_interval)
+    }
+
+    async fn fetch(&self) -> Result<(Vec<Jwk>, Duration), String> {
+        let response = self
+            .client
+            .get(&self.url)
+            .timeout(self.config.request_timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+
+        let ttl = response
+            .headers()
+            .get(reqwest::header::CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| self.ttl_from_cache_control(value))
+            .unwrap_or(self.config.default_ttl);
+
+        let jwks: JwksResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid JWKS: {}", e))?;
+
+        Ok((jwks.keys, ttl))
+    }
+
+    fn ttl_from_cache_control(&self, value: &str) -> Duration {
+        let mut max_age = None;
+
+        for directive in value.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+            if directive == "no-cache" || directive == "no-store" {
+                return self.config.min_ttl;
+            }
+            if let Some(seconds) = directive.strip_prefix("max-age=") {
+                max_age = seconds.trim_matches('"').parse::<u64>().ok();
+            }
+        }
+
+        match max_age {
+            Some(seconds) => Duration::from_secs(seconds).clamp(self.config.min_ttl, self.config.max_ttl),
+            None => self.config.default_ttl,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    struct StubJwks {
+        kids: Mutex<Vec<&'static str>>,
+        cache_control: Mutex<Option<&'static str>>,
+        failing: Mutex<bool>,
+        hits: AtomicUsize,
+    }
+
+    impl StubJwks {
+        fn set_kids(&self, kids: &[&'static str]) {
+            *self.kids.lock().unwrap() = kids.to_vec();
+        }
+
+        fn set_failing(&self, failing: bool) {
+            *self.failing.lock().unwrap() = failing;
+        }
+
+        fn hits(&self) -> usize {
+            self.hits.load(Ordering::SeqCst)
+        }
+
+        fn response(&self) -> String {
+            if *self.failing.lock().unwrap() {
+                return "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+            }
+
+            let keys: Vec<Jwk> = self
+                .kids
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|kid| Jwk {
+                    kid: kid.to_string(),
+                    kty: "RSA".to_string(),
+                    n: "n".to_string(),
+                    e: "AQAB".to_string(),
+                })
+                .collect();
+            let body = serde_json::to_string(&JwksResponse { keys }).unwrap();
+            let cache_control = self
+                .cache_control
+                .lock()
+                .unwrap()
+                .map(|value| format!("Cache-Control: {}\r\n", value))
+                .unwrap_or_default();
+
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                cache_control,
+                body.len(),
+                body
+            )
+        }
+    }
+
+    /// Serves a JWKS on a random local port until the test ends.
+    async fn stub_server(kids: &[&'static str], cache_control: Option<&'static str>) -> (String, Arc<StubJwks>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/oidc/jwks", listener.local_addr().unwrap());
+        let stub = Arc::new(StubJwks {
+            kids: Mutex::new(kids.to_vec()),
+            cache_control: Mutex::new(cache_control),
+            failing: Mutex::new(false),
+            hits: AtomicUsize::new(0),
+        });
+
+        let server = Arc::clone(&stub);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                server.hits.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.write_all(server.response().as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (url, stub)
+    }
+
+    fn test_config() -> JwksCacheConfig {
+        JwksCacheConfig {
+            default_ttl: Duration::from_secs(600),
+            min_ttl: Duration::ZERO,
+            max_ttl: Duration::from_secs(3600),
+            max_stale: Duration::from_secs(3600),
+            min_refresh_interval: Duration::ZERO,
+            request_timeout: Duration::from_secs(2),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_repeated_lookups_from_cache() {
+        let (url, stub) = stub_server(&["a"], Some("public, max-age=300")).await;
+        let cache = JwksCache::new(url, test_config());
+
+        assert_eq!(cache.key("a").await.unwrap().kid, "a");
+        assert_eq!(cache.key("a").await.unwrap().kid, "a");
+        assert_eq!(stub.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn refetches_once_max_age_has_passed() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=0")).await;
+        let cache = JwksCache::new(url, test_config());
+
+        cache.key("a").await.unwrap();
+        cache.key("a").await.unwrap();
+        assert_eq!(stub.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn refreshes_when_a_new_kid_appears() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=300")).await;
+        let cache = JwksCache::new(url, test_config());
+
+        cache.key("a").await.unwrap();
+        stub.set_kids(&["a", "b"]);
+
+        assert_eq!(cache.key("b").await.unwrap().kid, "b");
+        assert_eq!(stub.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_refreshes_for_unknown_kids() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=300")).await;
+        let config = JwksCacheConfig {
+            min_refresh_interval: Duration::from_secs(60),
+            ..test_config()
+        };
+        let cache = JwksCache::new(url, config);
+
+        cache.key("a").await.unwrap();
+        for _ in 0..5 {
+            assert!(matches!(cache.key("forged").await, Err(JwksError::UnknownKey(_))));
+        }
+        assert_eq!(stub.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_last_known_good_keys() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=0")).await;
+        let cache = JwksCache::new(url, test_config());
+
+        cache.key("a").await.unwrap();
+        stub.set_failing(true);
+
+        assert_eq!(cache.key("a").await.unwrap().kid, "a");
+        assert!(matches!(cache.key("b").await, Err(JwksError::Fetch(_))));
+        assert_eq!(stub.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_keys_older_than_max_stale() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=0")).await;
+        let config = JwksCacheConfig {
+            max_stale: Duration::ZERO,
+            ..test_config()
+        };
+        let cache = JwksCache::new(url, config);
+
+        cache.key("a").await.unwrap();
+        stub.set_failing(true);
+
+        assert!(matches!(cache.key("a").await, Err(JwksError::Fetch(_))));
+    }
+
+    #[tokio::test]
+    async fn refreshes_in_the_background() {
+        let (url, stub) = stub_server(&["a"], Some("max-age=0")).await;
+        let config = JwksCacheConfig {
+            min_refresh_interval: Duration::from_millis(20),
+            ..test_config()
+        };
+        let cache = Arc::new(JwksCache::new(url, config));
+
+        let task = cache.spawn_refresh();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(stub.hits() >= 2);
+
+        drop(cache);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(task.is_finished());
+    }
+
+    #[test]
+    fn reads_max_age_from_cache_control() {
+        let cache = JwksCache::new(String::new(), JwksCacheConfig::default());
+
+        assert_eq!(cache.ttl_from_cache_control("public, max-age=3600"), Duration::from_secs(3600));
+        assert_eq!(cache.ttl_from_cache_control("max-age=5"), Duration::from_secs(60));
+        assert_eq!(cache.ttl_from_cache_control("max-age=9999999"), Duration::from_secs(86_400));
+        assert_eq!(cache.ttl_from_cache_control("no-store"), Duration::from_secs(60));
+        assert_eq!(cache.ttl_from_cache_control("public"), Duration::from_secs(600));
+    }
+}
@@ -1,4 +1,6 @@
 pub mod auth;
+pub mod jwks;
+pub mod principal;
 pub mod rbac;
 pub mod cors;
 
@@ -0,0 +1,345 @@
+//! The authenticated caller of a request. `LogtoAuthMiddleware` resolves it
+//! once per request and hands it to controllers in a signed internal header,
+//! since RWF requests carry no extensions.
+
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use crate::models::{InstitutionMember, PermissionGrant, User};
+
+pub(super) const PRINCIPAL_HEADER: &str = "x-tulang-principal";
+
+const USER_CACHE_TTL: Duration = Duration::from_secs(60);
+const USER_CACHE_CAPACITY: usize = 10_000;
+
+// Signs the principal header so a client cannot forge it. A fresh key per
+// process is enough: the header never leaves the process.
+static SIGNING_KEY: Lazy<hmac::Key> = Lazy::new(|| {
+    let mut secret = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .expect("No randomness available");
+    hmac::Key::new(hmac::HMAC_SHA256, &secret)
+});
+
+static USER_CACHE: Lazy<UserCache> = Lazy::new(|| UserCache::new(USER_CACHE_TTL, USER_CACHE_CAPACITY));
+
+/// Where a permission is needed
+#[derive(Debug, Clone, Copy)]
+pub enum Scope<'a> {
+    /// Platform-wide; only unscoped grants apply
+    Global,
+    Institution(Uuid),
+    /// A region code; grants for a parent region apply too
+    Region(&'a str),
+}
+
+impl<'a> Scope<'a> {
+    /// Scope of something placed in a region, or global when it has none
+    pub fn region_or_global(region_code: Option<&'a str>) -> Self {
+        region_code.map(Scope::Region).unwrap_or(Scope::Global)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Principal {
+    pub user: User,
+    /// Memberships in active institutions
+    pub memberships: Vec<InstitutionMember>,
+    pub grants: Vec<PermissionGrant>,
+    /// Expiry of the bearer token, as a Unix timestamp
+    pub expires_at: i64,
+}
+
+impl Principal {
+    pub fn user_id(&self) -> Uuid {
+        self.user.id
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= chrono::Utc::now().timestamp()
+    }
+
+    pub fn has_permission(&self, permission: &str, scope: Scope) -> bool {
+        self.grants
+            .iter()
+            .filter(|grant| grant.permission == permission)
+            .any(|grant| match (grant.institution_id, grant.region_code.as_deref(), scope) {
+                (None, None, _) => true,
+                (Some(granted), _, Scope::Institution(id)) => granted == id,
+                (None, Some(granted), Scope::Region(code)) => {
+                    code == granted || code.strip_prefix(granted).is_some_and(|rest| rest.starts_with('.'))
+                }
+                _ => false,
+            })
+    }
+
+    /// Regions the permission is limited to, or `None` when it is held
+    /// platform-wide. An empty list means it is not held in any region.
+    pub fn region_limit(&self, permission: &str) -> Option<Vec<String>> {
+        if self.has_permission(permission, Scope::Global) {
+            return None;
+        }
+
+        Some(self.granted_regions(permission))
+    }
+
+    /// Regions the permission was granted for, leaving out wider grants
+    pub fn granted_regions(&self, permission: &str) -> Vec<String> {
+        self.grants
+            .iter()
+            .filter(|grant| grant.permission == permission)
+            .filter_map(|grant| grant.region_code.clone())
+            .collect()
+    }
+
+    pub(super) fn encode(&self) -> Result<String, serde_json::Error> {
+        let payload = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?);
+        let tag = hmac::sign(&SIGNING_KEY, payload.as_bytes());
+
+        Ok(format!("{}.{}", payload, general_purpose::URL_SAFE_NO_PAD.encode(tag.as_ref())))
+    }
+
+    pub(super) fn decode(value: &str) -> Option<Self> {
+        let (payload, tag) = value.split_once('.')?;
+        let tag = general_purpose::URL_SAFE_NO_PAD.decode(tag).ok()?;
+        hmac::verify(&SIGNING_KEY, payload.as_bytes(), &tag).ok()?;
+
+        let json = general_purpose::URL_SAFE_NO_PAD.decode(payload).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// Who a user is, which institutions they belong to and what they may do
+#[derive(Clone)]
+pub(super) struct CachedUser {
+    pub user: User,
+    pub memberships: Vec<InstitutionMember>,
+    pub grants: Vec<PermissionGrant>,
+}
+
+/// Users by Logto `sub`, so most requests need no database round trip.
+/// Entries live for a short TTL and are dropped as soon as the user's role,
+/// memberships or permissions change. Every invalidation starts a new
+/// generation, and a fill loaded in an earlier one is not stored, since it
+/// may predate the change.
+struct UserCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: RwLock<HashMap<String, (CachedUser, Instant)>>,
+    generation: AtomicU64,
+}
+
+impl UserCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn get(&self, sub: &str) -> Option<CachedUser> {
+        let entries = self.entries.read().unwrap();
+
+        entries
+            .get(sub)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+            .map(|(user, _)| user.clone())
+    }
+
+    /// Stores a user loaded during `generation`, unless an invalidation
+    /// came in since
+    fn insert(&self, sub: String, user: CachedUser, generation: u64) {
+        let mut entries = self.entries.write().unwrap();
+        if self.generation() != generation {
+            return;
+        }
+
+        if entries.len() >= self.capacity && !entries.contains_key(&sub) {
+            entries.retain(|_, (_, cached_at)| cached_at.elapsed() < self.ttl);
+
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (_, cached_at))| *cached_at)
+                    .map(|(sub, _)| sub.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(sub, (user, Instant::now()));
+    }
+
+    fn invalidate(&self, user_id: Uuid) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.retain(|_, (cached, _)| cached.user.id != user_id);
+    }
+
+    fn clear(&self) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+    }
+}
+
+pub(super) fn cached_user(sub: &str) -> Option<CachedUser> {
+    USER_CACHE.get(sub)
+}
+
+/// Generation to pass to `cache_user`, taken before loading the user
+pub(super) fn cache_generation() -> u64 {
+    USER_CACHE.generation()
+}
+
+pub(super) fn cache_user(sub: String, user: CachedUser, generation: u64) {
+    USER_CACHE.insert(sub, user, generation);
+}
+
+/// Drops every cached user, e.g. after a role gained or lost a permission.
+pub fn invalidate_all() {
+    USER_CACHE.clear();
+}
+
+/// Drops the cached copy of a user, e.g. after changing their role or
+/// permissions, so the change applies from their next request.
+pub fn invalidate_user(user_id: Uuid) {
+    USER_CACHE.invalidate(user_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: Uuid) -> CachedUser {
+        let now = chrono::Utc::now();
+        CachedUser {
+            user: User {
+                id,
+                logto_user_id: "logto-1".to_string(),
+                email: None,
+                username: None,
+                full_name: None,
+                role: "user".to_string(),
+                created_at: now,
+                updated_at: now,
+                is_active: true,
+            },
+            memberships: vec![],
+            grants: vec![],
+        }
+    }
+
+    fn principal(expires_at: i64) -> Principal {
+        Principal {
+            user: user(Uuid::new_v4()).user,
+            memberships: vec![],
+            grants: vec![],
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn cache_returns_fresh_entries_only() {
+        let cache = UserCache::new(Duration::from_secs(60), 10);
+        let id = Uuid::new_v4();
+        cache.insert("sub".to_string(), user(id), cache.generation());
+        assert_eq!(cache.get("sub").map(|c| c.user.id), Some(id));
+
+        let expired = UserCache::new(Duration::ZERO, 10);
+        expired.insert("sub".to_string(), user(id), expired.generation());
+        assert!(expired.get("sub").is_none());
+    }
+
+    #[test]
+    fn invalidation_drops_the_user() {
+        let cache = UserCache::new(Duration::from_secs(60), 10);
+        let (id, other) = (Uuid::new_v4(), Uuid::new_v4());
+        cache.insert("a".to_string(), user(id), cache.generation());
+        cache.insert("b".to_string(), user(other), cache.generation());
+
+        cache.invalidate(id);
+
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn fill_started_before_an_invalidation_is_not_stored() {
+        let cache = UserCache::new(Duration::from_secs(60), 10);
+        let id = Uuid::new_v4();
+
+        let generation = cache.generation();
+        cache.invalidate(id);
+        cache.insert("sub".to_string(), user(id), generation);
+        assert!(cache.get("sub").is_none());
+
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("sub".to_string(), user(id), generation);
+        assert!(cache.get("sub").is_none());
+    }
+
+    #[test]
+    fn full_cache_evicts_the_oldest_entry() {
+        let cache = UserCache::new(Duration::from_secs(60), 2);
+        for sub in ["a", "b", "c"] {
+            cache.insert(sub.to_string(), user(Uuid::new_v4()), cache.generation());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn principal_header_round_trips() {
+        let principal = principal(i64::MAX);
+        let decoded = Principal::decode(&principal.encode().unwrap()).unwrap();
+
+        assert_eq!(decoded.user_id(), principal.user_id());
+        assert!(!decoded.is_expired());
+    }
+
+    #[test]
+    fn tampered_principal_header_is_rejected() {
+        let encoded = principal(i64::MAX).encode().unwrap();
+        let (payload, tag) = encoded.split_once('.').unwrap();
+
+        // Another caller's payload under this tag
+        let other = principal(i64::MAX).encode().unwrap();
+        let (forged, _) = other.split_once('.').unwrap();
+        assert!(Principal::decode(&format!("{}.{}", forged, tag)).is_none());
+
+        let mut bad_tag = general_purpose::URL_SAFE_NO_PAD.decode(tag).unwrap();
+        bad_tag[0] ^= 1;
+        let bad_tag = general_purpose::URL_SAFE_NO_PAD.encode(bad_tag);
+        assert!(Principal::decode(&format!("{}.{}", payload, bad_tag)).is_none());
+
+        assert!(Principal::decode(payload).is_none());
+    }
+
+    #[test]
+    fn expired_principal_is_reported() {
+        let now = chrono::Utc::now().timestamp();
+
+        assert!(principal(now - 1).is_expired());
+        assert!(!principal(now + 60).is_expired());
+    }
+}
@@ -1,68 +1,67 @@
 use rwf::controller::middleware::prelude::*;
 use rwf::controller::Middleware;
 use rwf::http::{Request, Response};
+use uuid::Uuid;
 use crate::middleware::auth::RequestUserExt;
+use crate::middleware::principal::Scope;
 
+/// JSON error response for requests stopped by middleware
+pub(crate) fn denied(code: u16, message: &str) -> Response {
+    Response::new()
+        .json(serde_json::json!({ "error": message }))
+        .unwrap_or_default()
+        .code(code)
+}
+
+/// Lets through users holding a permission, either platform-wide or for
+/// the institution named by the `:id` route parameter. Runs after
+/// `LogtoAuthMiddleware`.
 #[derive(Debug, Clone)]
-pub struct RoleMiddleware;
+pub struct PermissionMiddleware {
+    permission: &'static str,
+    institution_scoped: bool,
+}
 
-impl RoleMiddleware {
-    pub fn new() -> Self {
-        Self
+impl PermissionMiddleware {
+    pub fn global(permission: &'static str) -> Self {
+        Self { permission, institution_scoped: false }
+    }
+
+    pub fn institution(permission: &'static str) -> Self {
+        Self { permission, institution_scoped: true }
     }
 }
 
 #[async_trait]
-impl Middleware for RoleMiddleware {
+impl Middleware for PermissionMiddleware {
     async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
-        let path = request.path().path();
+        let Ok(principal) = request.principal() else {
+            let response = denied(401, "Unauthorized");
+            return Ok(Outcome::Stop(request, response));
+        };
 
-        // Define protected routes and required roles
-        let admin_routes = vec![
-            "/api/admin/",
-            "/api/dashboard/",
-        ];
+        let scope = if self.institution_scoped {
+            let institution_id = request
+                .parameter::<String>("id")
+                .ok()
+                .flatten()
+                .and_then(|id| Uuid::parse_str(&id).ok());
 
-        let moderator_routes = vec![
-            "/api/tickets/",
-        ];
+            let Some(institution_id) = institution_id else {
+                let response = denied(400, "Invalid institution id");
+                return Ok(Outcome::Stop(request, response));
+            };
 
-        // Check if route requires admin
-        if admin_routes.iter().any(|prefix| path.starts_with(prefix)) {
-            match request.get_user() {
-                Ok(user) => {
-                    if user.role != "admin" && user.role != "moderator" {
-                        let response = Response::new()
-                            .html("<h1>Admin access Required</h1>");
-                        return Ok(Outcome::Stop(request, response));
-                    }
-                }
-                Err(_) => {
-                    let response = Response::new()
-                        .html("<h1>Unauthorized</h1>");
-                    return Ok(Outcome::Stop(request, response));
-                }
-            }
-        }
+            Scope::Institution(institution_id)
+        } else {
+            Scope::Global
+        };
 
-        // Check if route requires moderator
-        if moderator_routes.iter().any(|prefix| path.starts_with(prefix)) {
-            match request.get_user() {
-                Ok(user) => {
-                    if user.role != "admin" && user.role != "moderator" {
-                        let response = Response::new()
-                            .html("<h1>Moderator access Required</h1>");
-                        return Ok(Outcome::Stop(request, response));
-                    }
-                }
-                Err(_) => {
-                    let response = Response::new()
-                        .html("<h1>Unauthorized</h1>");
-                    return Ok(Outcome::Stop(request, response));
-                }
-            }
+        if !principal.has_permission(self.permission, scope) {
+            let response = denied(403, &format!("Missing permission {}", self.permission));
+            return Ok(Outcome::Stop(request, response));
         }
 
         Ok(Outcome::Forward(request))
     }
-}
\ No newline at end of file
+}
@@ -46,6 +46,8 @@ pub struct ChatSession {
     pub user_id: Uuid,
     pub title: Option<String>,
     pub status: String,
+    /// Mode of the report drafted from this chat
+    pub anonymity: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub last_message_at: DateTime<Utc>,
@@ -71,7 +73,8 @@ pub struct ChatMessageRequest {
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Report {
     pub id: Uuid,
-    pub session_id: Uuid,
+    /// The chat the report was drafted in; none for submitted pseudonymous reports
+    pub session_id: Option<Uuid>,
     pub user_id: Uuid,
     pub category_id: Option<Uuid>,
     pub title: String,
@@ -80,9 +83,17 @@ pub struct Report {
     pub latitude: Option<rust_decimal::Decimal>,
     pub longitude: Option<rust_decimal::Decimal>,
     pub address: Option<String>,
+    pub region_code: Option<String>,
+    /// `identified`, or `pseudonymous` when `user_id` is the reporter's pseudonym
+    pub anonymity: String,
     pub incident_date: Option<DateTime<Utc>>,
     pub reported_date: DateTime<Utc>,
     pub status: String,
+    /// `pending` until pre-screening or a moderator lets it be published
+    pub moderation_status: String,
+    pub rejection_reason: Option<String>,
+    /// The report this one was merged into, for status `duplicate`
+    pub duplicate_of: Option<Uuid>,
     pub is_complete: bool,
     pub completeness_score: rust_decimal::Decimal,
     pub missing_fields: serde_json::Value,
@@ -104,6 +115,8 @@ pub struct CreateReportRequest {
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub incident_date: Option<DateTime<Utc>>,
+    /// `identified` (default) or `pseudonymous`
+    pub anonymity: Option<String>,
 }
 
 #[derive(Debug, Serialize, FromRow)] 
@@ -113,6 +126,152 @@ pub struct ReportWithCategory {
     pub category: Option<serde_json::Value>, 
 }
 
+// Citizen Fact Model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CitizenFact {
+    pub id: Uuid,
+    pub report_id: Option<Uuid>,
+    pub cluster_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub content: String,
+    pub photo_url: Option<String>,
+    pub latitude: Option<rust_decimal::Decimal>,
+    pub longitude: Option<rust_decimal::Decimal>,
+    pub observed_date: Option<DateTime<Utc>>,
+    pub status: String,
+    pub moderation_note: Option<String>,
+    pub moderated_by: Option<Uuid>,
+    pub moderated_at: Option<DateTime<Utc>>,
+    /// First approval, when the report was credited
+    pub approved_at: Option<DateTime<Utc>>,
+    pub confirmation_count: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFactRequest {
+    pub content: String,
+    pub photo_url: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub observed_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerateFactRequest {
+    pub status: String,
+    pub note: Option<String>,
+}
+
+// Duplicate Models
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DuplicateCandidate {
+    pub report_id: Uuid,
+    pub candidate_id: Uuid,
+    /// Ticket of the candidate, for "Is this the same as TKT-XXXX?"
+    pub ticket_number: String,
+    pub title: String,
+    pub score: rust_decimal::Decimal,
+    pub text_similarity: rust_decimal::Decimal,
+    pub distance_meters: Option<rust_decimal::Decimal>,
+    pub citizen_confirmed: Option<bool>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmDuplicateRequest {
+    pub candidate_id: Uuid,
+    pub same: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeReportRequest {
+    /// The canonical report the duplicate is merged into
+    pub into: Uuid,
+}
+
+// Moderation Models
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModerationKeyword {
+    pub id: Uuid,
+    pub term: String,
+    /// `abuse`, `defamation` or `spam`
+    pub category: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateModerationKeywordRequest {
+    pub term: String,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModerationItem {
+    pub id: Uuid,
+    /// `report`, `comment` or `fact`
+    pub content_type: String,
+    pub content_id: Uuid,
+    pub report_id: Option<Uuid>,
+    pub cluster_id: Option<Uuid>,
+    pub flags: serde_json::Value,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Queue entry with the text under review, as an edit would change it
+#[derive(Debug, Serialize, FromRow)]
+pub struct ModerationQueueEntry {
+    #[sqlx(flatten)]
+    pub item: ModerationItem,
+    /// A report's title, `None` for comments and facts
+    pub title: Option<String>,
+    /// A report's description, or the comment or fact
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModerationDecision {
+    pub id: Uuid,
+    pub item_id: Uuid,
+    pub moderator_id: Uuid,
+    pub action: String,
+    pub note: Option<String>,
+    pub previous_content: Option<String>,
+    pub new_content: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationActionRequest {
+    /// `approve`, `reject` or `edit`; an edit publishes the edited text
+    pub action: String,
+    /// Required when rejecting or editing; shown to the author as the reason
+    pub note: Option<String>,
+    /// The edited text: a report's description, or the comment or fact
+    pub content: Option<String>,
+    /// A report's edited title; it stays as it is when left out
+    pub title: Option<String>,
+}
+
+// Region Model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Region {
+    pub code: String,
+    pub parent_code: Option<String>,
+    pub name: String,
+    pub level: String,
+    pub centroid_latitude: Option<rust_decimal::Decimal>,
+    pub centroid_longitude: Option<rust_decimal::Decimal>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
 // Report Cluster Model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ReportCluster {
@@ -125,12 +284,71 @@ pub struct ReportCluster {
     pub center_latitude: Option<rust_decimal::Decimal>,
     pub center_longitude: Option<rust_decimal::Decimal>,
     pub radius_meters: Option<rust_decimal::Decimal>,
+    pub region_code: Option<String>,
     pub earliest_incident: Option<DateTime<Utc>>,
     pub latest_incident: Option<DateTime<Utc>>,
+    pub adopted_by: Option<Uuid>,
+    pub adopted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+// Institution Model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Institution {
+    pub id: Uuid,
+    pub name: String,
+    pub level: String,
+    pub parent_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub min_latitude: Option<rust_decimal::Decimal>,
+    pub max_latitude: Option<rust_decimal::Decimal>,
+    pub min_longitude: Option<rust_decimal::Decimal>,
+    pub max_longitude: Option<rust_decimal::Decimal>,
+    pub region_code: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub is_active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInstitutionRequest {
+    pub name: String,
+    pub level: String,
+    pub parent_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub min_latitude: Option<f64>,
+    pub max_latitude: Option<f64>,
+    pub min_longitude: Option<f64>,
+    pub max_longitude: Option<f64>,
+    pub region_code: Option<String>,
+    pub category_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InstitutionMember {
+    pub institution_id: Uuid,
+    pub user_id: Uuid,
+    pub member_role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddInstitutionMemberRequest {
+    pub user_id: Uuid,
+    pub member_role: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ProblemShopEntry {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub cluster: ReportCluster,
+    pub open_tickets: i64,
+    pub category_ids: Vec<Uuid>,
+}
+
 // Ticket Model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Ticket {
@@ -142,6 +360,7 @@ pub struct Ticket {
     pub priority: String,
     pub assigned_to: Option<Uuid>,
     pub assigned_at: Option<DateTime<Utc>>,
+    pub assigned_institution_id: Option<Uuid>,
     pub resolution: Option<String>,
     pub resolved_at: Option<DateTime<Utc>>,
     pub resolved_by: Option<Uuid>,
@@ -163,9 +382,11 @@ pub struct TicketWithDetails {
 pub struct TicketComment {
     pub id: Uuid,
     pub ticket_id: Uuid,
-    pub user_id: Uuid,
+    /// `None` for comments posted by the platform
+    pub user_id: Option<Uuid>,
     pub comment: String,
     pub is_internal: bool,
+    pub moderation_status: String,
     pub created_at: DateTime<Utc>,
 }
 
@@ -175,14 +396,82 @@ pub struct AddCommentRequest {
     pub is_internal: Option<bool>,
 }
 
+// Ticket Progress Update Model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TicketProgressUpdate {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub institution_id: Uuid,
+    pub posted_by: Uuid,
+    pub stage: String,
+    pub percentage: i16,
+    pub expected_completion_date: Option<chrono::NaiveDate>,
+    pub note: Option<String>,
+    pub attachments: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateProgressUpdateRequest {
+    pub stage: String,
+    pub percentage: Option<i16>,
+    pub expected_completion_date: Option<chrono::NaiveDate>,
+    pub note: Option<String>,
+    pub attachments: Option<serde_json::Value>,
+}
+
+// Resolution Review Model
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ResolutionReview {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub round: i32,
+    pub summary: Option<String>,
+    pub requested_by: Option<Uuid>,
+    pub quorum: rust_decimal::Decimal,
+    pub status: String,
+    pub opened_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ResolutionVote {
+    pub id: Uuid,
+    pub review_id: Uuid,
+    pub user_id: Uuid,
+    pub vote: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ResolutionTally {
+    pub eligible: i64,
+    pub required_accepts: i64,
+    pub accepts: i64,
+    pub rejects: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolutionReviewWithVotes {
+    #[serde(flatten)]
+    pub review: ResolutionReview,
+    pub tally: ResolutionTally,
+    pub votes: Vec<ResolutionVote>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CastVoteRequest {
+    pub vote: String,
+    pub reason: Option<String>,
+}
+
 // API Key Model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ApiKey {
     pub id: Uuid,
     pub name: String,
     pub provider: String,
-    #[serde(skip_serializing)]
-    pub api_key: String,
     pub base_url: Option<String>,
     pub is_active: bool,
     pub usage_count: i64,
@@ -190,6 +479,21 @@ pub struct ApiKey {
     pub created_by: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub priority: i32,
+    pub daily_token_quota: Option<i64>,
+    pub daily_request_quota: Option<i32>,
+    pub consecutive_failures: i32,
+    pub cooldown_until: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub api_key_ciphertext: Option<String>,
+    #[serde(skip_serializing)]
+    pub wrapped_data_key: Option<String>,
+    #[serde(skip_serializing)]
+    pub kek_id: Option<String>,
+    /// Last characters of the key, e.g. "****a1b2"
+    pub api_key_hint: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -198,6 +502,155 @@ pub struct CreateApiKeyRequest {
     pub provider: String,
     pub api_key: String,
     pub base_url: Option<String>,
+    pub priority: Option<i32>,
+    pub daily_token_quota: Option<i64>,
+    pub daily_request_quota: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateApiKeyRequest {
+    pub name: Option<String>,
+    pub base_url: Option<String>,
+    pub is_active: Option<bool>,
+    pub priority: Option<i32>,
+    /// 0 removes the quota
+    pub daily_token_quota: Option<i64>,
+    /// 0 removes the quota
+    pub daily_request_quota: Option<i32>,
+    /// Clears the cooldown and failure count
+    pub reset_health: Option<bool>,
+}
+
+// Permission Models
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Permission {
+    pub key: String,
+    pub description: String,
+    /// Roles holding this permission
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UserPermission {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub permission: String,
+    pub institution_id: Option<Uuid>,
+    pub region_code: Option<String>,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A permission a user holds, through a role or a direct grant. Without an
+/// institution or region it applies everywhere.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PermissionGrant {
+    pub permission: String,
+    pub institution_id: Option<Uuid>,
+    pub region_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RolePermissionRequest {
+    pub permission: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantPermissionRequest {
+    pub permission: String,
+    pub institution_id: Option<Uuid>,
+    pub region_code: Option<String>,
+}
+
+// Pseudonymous Reporting Models
+/// An institution a citizen revealed themselves to for a pseudonymous report
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct IdentityDisclosure {
+    pub report_id: Uuid,
+    pub institution_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscloseIdentityRequest {
+    pub institution_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EscrowRequest {
+    /// Legal basis of the request, kept for audit
+    pub reason: String,
+}
+
+// API Client Models
+/// A Logto machine-to-machine application acting for an institution
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ApiClient {
+    pub id: Uuid,
+    pub logto_client_id: String,
+    pub name: String,
+    pub institution_id: Uuid,
+    /// Service account the client acts as
+    pub user_id: Uuid,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiClientRequest {
+    pub logto_client_id: String,
+    pub name: String,
+    pub institution_id: Uuid,
+    /// Granted to the service account, limited to the institution
+    pub permissions: Vec<String>,
+}
+
+// LLM Usage Models
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct LlmModelPrice {
+    pub model: String,
+    pub prompt_usd_per_million: f64,
+    pub completion_usd_per_million: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpsertModelPriceRequest {
+    pub model: String,
+    pub prompt_usd_per_million: f64,
+    pub completion_usd_per_million: f64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LlmUsageTotals {
+    pub calls: i64,
+    pub failed_calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub estimated_cost_usd: f64,
+    pub avg_latency_ms: Option<f64>,
+}
+
+/// Usage for one day or one group (prompt type, model)
+#[derive(Debug, Serialize, FromRow)]
+pub struct LlmUsageBucket {
+    pub key: String,
+    pub calls: i64,
+    pub failed_calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub estimated_cost_usd: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LlmUsageReport {
+    pub days: i64,
+    pub totals: LlmUsageTotals,
+    pub daily: Vec<LlmUsageBucket>,
+    pub by_prompt_type: Vec<LlmUsageBucket>,
+    pub by_model: Vec<LlmUsageBucket>,
 }
 
 // System Prompt Model
@@ -215,6 +668,106 @@ pub struct SystemPrompt {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SystemPromptVersion {
+    pub id: Uuid,
+    pub prompt_id: Uuid,
+    pub version: i32,
+    pub prompt_text: String,
+    pub variables: serde_json::Value,
+    pub status: String,
+    pub note: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePromptVersionRequest {
+    pub prompt_text: String,
+    pub variables: Option<serde_json::Value>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewPromptRequest {
+    #[serde(default)]
+    pub values: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptDiffLine {
+    /// "equal", "insert" or "delete"
+    pub op: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EvalCase {
+    pub id: Uuid,
+    pub name: String,
+    pub prompt_type: String,
+    pub input: String,
+    pub expected: serde_json::Value,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEvalCaseRequest {
+    pub name: String,
+    pub prompt_type: String,
+    pub input: String,
+    pub expected: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EvalRun {
+    pub id: Uuid,
+    pub prompt_id: Uuid,
+    pub prompt_version: i32,
+    pub api_key_id: Option<Uuid>,
+    pub provider: String,
+    pub model: Option<String>,
+    pub status: String,
+    pub case_count: i32,
+    pub json_valid_rate: Option<f64>,
+    pub field_scores: serde_json::Value,
+    pub avg_latency_ms: Option<f64>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub error: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EvalResult {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub case_id: Option<Uuid>,
+    pub output: Option<String>,
+    pub json_valid: bool,
+    pub field_scores: serde_json::Value,
+    pub latency_ms: Option<i32>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartEvalRunRequest {
+    pub prompt_id: Uuid,
+    /// Defaults to the prompt's active version
+    pub version: Option<i32>,
+    /// Defaults to the fake provider, which needs no network
+    pub api_key_id: Option<Uuid>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreatePromptRequest {
     pub name: String,
@@ -257,6 +810,16 @@ pub struct CategoryStats {
     pub percentage: f64,
 }
 
+#[derive(Debug, Serialize, FromRow)]
+pub struct RegionStats {
+    pub region_code: String,
+    pub region_name: String,
+    pub level: String,
+    pub report_count: i64,
+    pub active_reports: i64,
+    pub resolved_reports: i64,
+}
+
 #[derive(Debug, Serialize, FromRow)]  // Added FromRow here
 pub struct TrendData {
     pub date: String,
@@ -276,12 +839,42 @@ pub struct HeatmapPoint {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum WsMessage {
+    /// First message of a connection not opened with a `token` parameter
+    Authenticate {
+        token: String,
+    },
+    Authenticated {
+        user_id: Uuid,
+    },
+    StartSession {
+        /// Mode of the report drafted from the chat; `identified` when absent
+        #[serde(default)]
+        anonymity: Option<String>,
+    },
+    /// Continue one of the user's chats, e.g. from another device
+    ResumeSession {
+        session_id: Uuid,
+    },
+    /// The chat that messages now go to, with its history
+    SessionReady {
+        session_id: Uuid,
+        messages: Vec<ChatMessage>,
+    },
     UserMessage {
         content: String,
     },
     AssistantMessage {
         content: String,
     },
+    /// A piece of a streamed assistant answer
+    AssistantDelta {
+        content: String,
+    },
+    /// End of a streamed answer, with the full text as stored
+    AssistantDone {
+        message_id: Uuid,
+        content: String,
+    },
     SystemMessage {
         content: String,
     },
@@ -295,6 +888,17 @@ pub enum WsMessage {
         report_id: Uuid,
         ticket_number: String,
     },
+    /// Existing reports the new one may duplicate: "Is this the same as TKT-XXXX?"
+    DuplicateCandidates {
+        report_id: Uuid,
+        candidates: Vec<DuplicateCandidate>,
+    },
+    /// The citizen's answer to a duplicate candidate
+    ConfirmDuplicate {
+        report_id: Uuid,
+        candidate_id: Uuid,
+        same: bool,
+    },
     Error {
         message: String,
     },
@@ -0,0 +1,198 @@
+//! Every route of the API together with who may call it. The access level
+//! decides which middleware runs in front of the controller, so a route
+//! cannot be mounted without its checks.
+
+use rwf::controller::{Middleware, MiddlewareSet};
+use rwf::http::{Handler, Stream};
+use rwf::prelude::*;
+use crate::config::Config;
+use crate::handlers::*;
+use crate::middleware::auth::LogtoAuthMiddleware;
+use crate::middleware::cors::CorsMiddleware;
+use crate::middleware::rbac::PermissionMiddleware;
+use crate::websocket;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Access {
+    /// No token needed
+    Public,
+    /// Any signed-in user
+    Authenticated,
+    /// Users holding this permission platform-wide
+    Permission(&'static str),
+    /// Users holding this permission for the institution in the `:id`
+    /// parameter, or platform-wide
+    Institution(&'static str),
+}
+
+/// Runs a controller behind the middleware for its access level.
+struct Guarded<C> {
+    inner: C,
+    middleware: MiddlewareSet,
+}
+
+#[async_trait]
+impl<C: Controller> Controller for Guarded<C> {
+    fn middleware(&self) -> &MiddlewareSet {
+        &self.middleware
+    }
+
+    fn skip_csrf(&self) -> bool {
+        self.inner.skip_csrf()
+    }
+
+    fn controller_name(&self) -> &'static str {
+        self.inner.controller_name()
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        self.inner.handle(request).await
+    }
+
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        self.inner.handle_stream(request, stream).await
+    }
+}
+
+struct RouteTable {
+    cors: CorsMiddleware,
+    auth: LogtoAuthMiddleware,
+}
+
+impl RouteTable {
+    fn middleware(&self, access: Access) -> MiddlewareSet {
+        let mut handlers = vec![self.cors.clone().middleware()];
+
+        match access {
+            Access::Public => {}
+            Access::Authenticated => handlers.push(self.auth.clone().middleware()),
+            Access::Permission(permission) => {
+                handlers.push(self.auth.clone().middleware());
+                handlers.push(PermissionMiddleware::global(permission).middleware());
+            }
+            Access::Institution(permission) => {
+                handlers.push(self.auth.clone().middleware());
+                handlers.push(PermissionMiddleware::institution(permission).middleware());
+            }
+        }
+
+        MiddlewareSet::new(handlers)
+    }
+
+    fn guard<C: Controller>(&self, inner: C, access: Access) -> Guarded<C> {
+        Guarded {
+            inner,
+            middleware: self.middleware(access),
+        }
+    }
+
+    /// Serves exactly `path`
+    fn route<C: Controller + Default + 'static>(&self, path: &str, access: Access) -> Handler {
+        self.route_to(path, C::default(), access)
+    }
+
+    /// Serves exactly `path` with a controller that needs constructing
+    fn route_to<C: Controller + 'static>(&self, path: &str, controller: C, access: Access) -> Handler {
+        Handler::route(path, self.guard(controller, access))
+    }
+
+    /// Serves `path` and `path/:id`
+    fn rest<C: Controller + Default + 'static>(&self, path: &str, access: Access) -> Handler {
+        Handler::rest(path, self.guard(C::default(), access))
+    }
+}
+
+pub fn table(config: &Config) -> Vec<Handler> {
+    use Access::*;
+
+    let t = RouteTable {
+        cors: CorsMiddleware::with_origins(config.cors_allowed_origins.clone()),
+        auth: LogtoAuthMiddleware::new(config.logto.clone()),
+    };
+
+    vec![
+        t.route::<health::HealthCheckController>("/health", Public),
+
+        t.route::<auth::CallbackController>("/auth/callback", Public),
+        t.route::<auth::MeController>("/auth/me", Authenticated),
+        t.route::<auth::LogoutController>("/auth/logout", Public),
+
+        // Browsers cannot set headers on a WebSocket handshake, the controller
+        // authenticates connections itself
+        t.route_to("/ws/chat", websocket::handler::ChatWebSocketController::new(t.auth.clone()), Public),
+
+        t.route::<chat::ChatSessionsController>("/chat/sessions", Authenticated),
+        t.route::<chat::ChatSessionController>("/chat/sessions/:id", Authenticated),
+        t.route::<chat::ChatMessagesController>("/chat/sessions/:id/messages", Authenticated),
+
+        t.route::<reports::ReportsController>("/reports", Authenticated),
+        t.route::<reports::ReportCompleteController>("/reports/:id/complete", Authenticated),
+        t.route::<reports::ReportDisclosuresController>("/reports/:id/disclosures", Authenticated),
+        t.route::<reports::ReportDuplicatesController>("/reports/:id/duplicates", Authenticated),
+        t.route::<facts::ReportFactsController>("/reports/:id/facts", Authenticated),
+        t.route::<facts::ClusterFactsController>("/clusters/:id/facts", Authenticated),
+        t.route::<facts::FactConfirmController>("/facts/:id/confirm", Authenticated),
+
+        t.route::<tickets::TicketsListController>("/tickets", Authenticated),
+        t.route::<tickets::TicketController>("/tickets/:id", Authenticated),
+        t.route::<tickets::TicketCommentsController>("/tickets/:id/comments", Authenticated),
+        t.route::<tickets::TicketStatusController>("/tickets/:id/status", Authenticated),
+        t.route::<tickets::TicketProgressController>("/tickets/:id/progress", Authenticated),
+        t.route::<tickets::TicketReviewController>("/tickets/:id/review", Authenticated),
+        t.route::<tickets::TicketReviewVotesController>("/tickets/:id/review/votes", Authenticated),
+
+        t.route::<institutions::MyInstitutionsController>("/institutions/mine", Authenticated),
+        t.route::<institutions::ProblemShopController>("/institutions/:id/problems", Institution("institution.problems.view")),
+        t.route::<institutions::InstitutionTicketsController>("/institutions/:id/tickets", Institution("institution.tickets.view")),
+        t.route::<institutions::TicketReporterController>("/institutions/:id/tickets/:ticket_id/reporter", Institution("institution.tickets.view")),
+        t.route::<institutions::AdoptClusterController>("/institutions/:id/problems/:cluster_id/adopt", Institution("tickets.assign")),
+
+        t.route::<dashboard::DashboardStatsController>("/dashboard/stats", Authenticated),
+        t.route::<dashboard::DashboardTrendsController>("/dashboard/trends", Authenticated),
+        t.route::<dashboard::DashboardClustersController>("/dashboard/clusters", Authenticated),
+        t.route::<dashboard::DashboardHeatmapController>("/dashboard/heatmap", Authenticated),
+        t.route::<dashboard::DashboardRegionsController>("/dashboard/regions", Authenticated),
+
+        t.route::<regions::RegionsController>("/regions", Authenticated),
+
+        t.route::<panel::AdminUsersController>("/panel/users", Permission("users.manage")),
+        t.route::<panel::AdminUserRoleController>("/panel/users/:id/role", Permission("users.manage")),
+        t.route::<panel::UserPermissionsController>("/panel/users/:id/permissions", Permission("users.manage")),
+        t.route::<panel::ReportIdentityController>("/panel/reports/:id/identity", Permission("identity.escrow")),
+        t.route::<panel::PermissionsController>("/panel/permissions", Permission("permissions.manage")),
+        t.route::<panel::RolePermissionsController>("/panel/roles/:role/permissions", Permission("permissions.manage")),
+        // Listing is open to every user, changes are checked in the controller
+        t.route::<panel::CategoriesController>("/panel/categories", Authenticated),
+        t.route::<panel::PromptsController>("/panel/prompts", Permission("prompts.edit")),
+        t.route::<panel::PromptVersionsController>("/panel/prompts/:id/versions", Permission("prompts.edit")),
+        t.route::<panel::PromptVersionController>("/panel/prompts/:id/versions/:version", Permission("prompts.edit")),
+        t.route::<panel::PromptVersionDiffController>("/panel/prompts/:id/versions/:version/diff", Permission("prompts.edit")),
+        t.route::<panel::PromptVersionPromoteController>("/panel/prompts/:id/versions/:version/promote", Permission("prompts.edit")),
+        t.route::<panel::PromptVersionPreviewController>("/panel/prompts/:id/versions/:version/preview", Permission("prompts.edit")),
+        t.route::<panel::EvalCasesController>("/panel/evals/cases", Permission("evals.run")),
+        t.route::<panel::EvalRunsController>("/panel/evals/runs", Permission("evals.run")),
+        t.route::<panel::EvalRunController>("/panel/evals/runs/:id", Permission("evals.run")),
+        t.route::<panel::EvalRunCompareController>("/panel/evals/runs/:id/compare", Permission("evals.run")),
+        t.rest::<panel::InstitutionsController>("/panel/institutions", Permission("institutions.manage")),
+        t.route::<panel::InstitutionMembersController>("/panel/institutions/:id/members", Permission("institutions.manage")),
+        t.rest::<panel::ApiClientsController>("/panel/api-clients", Permission("api_clients.manage")),
+        // Moderation may be granted per region, checked in the controller
+        t.route::<panel::FactsModerationController>("/panel/facts", Authenticated),
+        t.route::<panel::FactModerateController>("/panel/facts/:id/moderate", Authenticated),
+        t.route::<panel::ModerationQueueController>("/panel/moderation", Authenticated),
+        t.route::<panel::ModerationDecideController>("/panel/moderation/:id/decide", Authenticated),
+        t.route::<panel::ModerationDecisionsController>("/panel/moderation/decisions", Permission("reports.moderate")),
+        t.rest::<panel::ModerationKeywordsController>("/panel/moderation/keywords", Permission("reports.moderate")),
+        t.route::<panel::DuplicateCandidatesController>("/panel/duplicates", Authenticated),
+        t.route::<panel::DuplicateDismissController>("/panel/reports/:id/duplicates/:candidate_id/dismiss", Authenticated),
+        t.route::<panel::ReportMergeController>("/panel/reports/:id/merge", Authenticated),
+        t.route::<panel::RegionImportController>("/panel/regions/import", Permission("regions.import")),
+        t.rest::<panel::ApiKeysController>("/panel/api-keys", Permission("api_keys.manage")),
+        t.route::<panel::ApiKeyUsageController>("/panel/api-keys/:id/usage", Permission("llm.usage.view")),
+        t.route::<panel::LlmUsageController>("/panel/llm/usage", Permission("llm.usage.view")),
+        // Changing prices is checked in the controller
+        t.route::<panel::LlmPricesController>("/panel/llm/prices", Permission("llm.usage.view")),
+        t.route::<panel::BackgroundJobsController>("/panel/jobs", Permission("jobs.view")),
+        t.route::<panel::BackgroundJobController>("/panel/jobs/:id", Permission("jobs.view")),
+    ]
+}
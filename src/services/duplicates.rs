@@ -0,0 +1,394 @@
+//! Duplicate reports. A new report is compared with published reports of
+//! the same category filed around the same time and place; the citizen is
+//! asked whether a candidate is the same problem, and moderators merge
+//! duplicates into the canonical report, whose supporters they join.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::*;
+
+// How far apart, in metres and days, two reports of one problem may be
+const MAX_DISTANCE_METERS: f64 = 500.0;
+const TIME_WINDOW_DAYS: i32 = 14;
+// Weighted text similarity, proximity and category match, 0 to 1
+const MIN_SCORE: f64 = 0.45;
+const MAX_CANDIDATES: i64 = 3;
+
+// Reports without coordinates only match within the same region, at half
+// the proximity score.
+const DETECT_SQL: &str = "
+    INSERT INTO duplicate_candidates (report_id, candidate_id, score, text_similarity, distance_meters)
+    SELECT $1, candidate_id, score, text_similarity, distance_meters FROM (
+        SELECT candidate_id, text_similarity, distance_meters,
+               0.5 * text_similarity
+               + 0.3 * CASE
+                   WHEN distance_meters IS NOT NULL THEN GREATEST(0, 1 - distance_meters / $3)
+                   WHEN same_region THEN 0.5
+                   ELSE 0
+                 END
+               + 0.2 * CASE WHEN same_category THEN 1 ELSE 0 END AS score
+        FROM (
+            SELECT c.id AS candidate_id,
+                   similarity(r.title || ' ' || r.description, c.title || ' ' || c.description)::float8 AS text_similarity,
+                   CASE WHEN r.latitude IS NOT NULL AND c.latitude IS NOT NULL THEN earth_distance(
+                       ll_to_earth(CAST(r.latitude AS DOUBLE PRECISION), CAST(r.longitude AS DOUBLE PRECISION)),
+                       ll_to_earth(CAST(c.latitude AS DOUBLE PRECISION), CAST(c.longitude AS DOUBLE PRECISION))
+                   ) END AS distance_meters,
+                   r.category_id IS NOT NULL AND r.category_id = c.category_id AS same_category,
+                   r.region_code IS NOT NULL AND r.region_code = c.region_code AS same_region
+            FROM reports r
+            JOIN reports c ON c.id <> r.id
+            WHERE r.id = $1
+            AND c.status IN ('submitted', 'verified', 'in_progress')
+            AND c.moderation_status = 'approved'
+            AND (r.category_id IS NULL OR c.category_id IS NULL OR c.category_id = r.category_id)
+            AND COALESCE(c.incident_date, c.created_at)
+                BETWEEN COALESCE(r.incident_date, r.created_at) - make_interval(days => $2)
+                AND COALESCE(r.incident_date, r.created_at) + make_interval(days => $2)
+        ) pairs
+        WHERE (distance_meters IS NOT NULL AND distance_meters <= $3)
+        OR (distance_meters IS NULL AND same_region)
+    ) scored
+    WHERE score >= $4
+    ORDER BY score DESC
+    LIMIT $5
+    ON CONFLICT (report_id, candidate_id) DO NOTHING
+    RETURNING candidate_id";
+
+#[derive(Debug)]
+pub struct DuplicateError(pub String);
+
+impl std::fmt::Display for DuplicateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateError {}
+
+impl From<sqlx::Error> for DuplicateError {
+    fn from(e: sqlx::Error) -> Self {
+        DuplicateError(format!("Database error: {}", e))
+    }
+}
+
+const CANDIDATES_SQL: &str = "
+    SELECT d.report_id, d.candidate_id, t.ticket_number, c.title, d.score, d.text_similarity,
+           d.distance_meters, d.citizen_confirmed, d.status, d.created_at
+    FROM duplicate_candidates d
+    JOIN reports c ON c.id = d.candidate_id
+    JOIN tickets t ON t.report_id = c.id";
+
+/// Looks for duplicates of a report, returning the candidates not found
+/// before. Runs again as a draft gains a location, category or date.
+pub async fn detect(pool: &PgPool, report_id: Uuid) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    let found: Vec<Uuid> = sqlx::query_scalar(DETECT_SQL)
+        .bind(report_id)
+        .bind(TIME_WINDOW_DAYS)
+        .bind(MAX_DISTANCE_METERS)
+        .bind(MIN_SCORE)
+        .bind(MAX_CANDIDATES)
+        .fetch_all(pool)
+        .await?;
+
+    if found.is_empty() {
+        return Ok(vec![]);
+    }
+
+    sqlx::query_as::<_, DuplicateCandidate>(&format!(
+        "{} WHERE d.report_id = $1 AND d.candidate_id = ANY($2) ORDER BY d.score DESC",
+        CANDIDATES_SQL
+    ))
+    .bind(report_id)
+    .bind(&found)
+    .fetch_all(pool)
+    .await
+}
+
+/// Open candidates of a report filed under one of `reporters`
+pub async fn candidates(pool: &PgPool, report_id: Uuid, reporters: &[Uuid]) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    sqlx::query_as::<_, DuplicateCandidate>(&format!(
+        "{} JOIN reports r ON r.id = d.report_id
+         WHERE d.report_id = $1 AND r.user_id = ANY($2) AND d.status = 'open'
+         ORDER BY d.score DESC",
+        CANDIDATES_SQL
+    ))
+    .bind(report_id)
+    .bind(reporters)
+    .fetch_all(pool)
+    .await
+}
+
+/// Records the citizen's answer on an open candidate of their report.
+/// Returns `false` if there is no such candidate.
+pub async fn answer(
+    pool: &PgPool,
+    report_id: Uuid,
+    candidate_id: Uuid,
+    reporters: &[Uuid],
+    same: bool,
+) -> Result<bool, sqlx::Error> {
+    let answered = sqlx::query(
+        "UPDATE duplicate_candidates d SET citizen_confirmed = $3
+         FROM reports r
+         WHERE r.id = d.report_id AND r.user_id = ANY($4)
+         AND d.report_id = $1 AND d.candidate_id = $2 AND d.status = 'open'"
+    )
+    .bind(report_id)
+    .bind(candidate_id)
+    .bind(same)
+    .bind(reporters)
+    .execute(pool)
+    .await?;
+
+    Ok(answered.rows_affected() > 0)
+}
+
+/// Open candidates across all reports, those the citizen confirmed first.
+/// `regions` limits them to reports from those regions.
+pub async fn queue(
+    pool: &PgPool,
+    regions: Option<Vec<String>>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    sqlx::query_as::<_, DuplicateCandidate>(&format!(
+        "{} JOIN reports r ON r.id = d.report_id
+         WHERE d.status = 'open'
+         AND d.citizen_confirmed IS DISTINCT FROM false
+         AND ($1::varchar[] IS NULL OR EXISTS (
+             SELECT 1 FROM UNNEST($1::varchar[]) AS g(code)
+             WHERE r.region_code = g.code OR r.region_code LIKE g.code || '.%'
+         ))
+         ORDER BY d.citizen_confirmed IS NULL ASC, d.score DESC, d.created_at ASC
+         LIMIT $2 OFFSET $3",
+        CANDIDATES_SQL
+    ))
+    .bind(regions)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn dismiss(pool: &PgPool, report_id: Uuid, candidate_id: Uuid) -> Result<bool, sqlx::Error> {
+    let dismissed = sqlx::query(
+        "UPDATE duplicate_candidates SET status = 'dismissed'
+         WHERE report_id = $1 AND candidate_id = $2 AND status = 'open'"
+    )
+    .bind(report_id)
+    .bind(candidate_id)
+    .execute(pool)
+    .await?;
+
+    Ok(dismissed.rows_affected() > 0)
+}
+
+/// Why `duplicate` cannot be merged into `canonical`, if it cannot
+fn merge_refusal(duplicate: &Report, canonical: &Report) -> Option<&'static str> {
+    if duplicate.id == canonical.id {
+        Some("A report cannot be merged into itself")
+    } else if duplicate.status == "duplicate" {
+        Some("Report is already merged")
+    } else if matches!(canonical.status.as_str(), "draft" | "duplicate" | "rejected")
+        || canonical.moderation_status != "approved"
+    {
+        Some("Reports can only be merged into a published report")
+    } else {
+        None
+    }
+}
+
+/// Merges a duplicate into the canonical report: the duplicate is marked and
+/// linked, its ticket closed, and its reporter, supporters and facts move to
+/// the canonical report. Returns the merged duplicate.
+pub async fn merge(
+    pool: &PgPool,
+    duplicate_id: Uuid,
+    canonical_id: Uuid,
+    merged_by: Uuid,
+) -> Result<Report, DuplicateError> {
+    let mut tx = pool.begin().await?;
+
+    // Both locked, in id order so merges running the other way wait instead
+    // of deadlocking, and checked only once locked
+    let locked = sqlx::query_as::<_, Report>(
+        "SELECT * FROM reports WHERE id = ANY($1) ORDER BY id FOR UPDATE"
+    )
+    .bind(vec![duplicate_id, canonical_id])
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let find = |id: Uuid| locked.iter().find(|r| r.id == id);
+    let (Some(duplicate), Some(canonical)) = (find(duplicate_id), find(canonical_id)) else {
+        return Err(DuplicateError("Report not found".to_string()));
+    };
+    if let Some(refusal) = merge_refusal(duplicate, canonical) {
+        return Err(DuplicateError(refusal.to_string()));
+    }
+    let (reporter_id, left_cluster, canonical_reporter) = (duplicate.user_id, duplicate.cluster_id, canonical.user_id);
+
+    let merged = sqlx::query_as::<_, Report>(
+        "UPDATE reports SET
+            status = 'duplicate',
+            duplicate_of = $2,
+            merged_by = $3,
+            merged_at = NOW(),
+            cluster_id = NULL,
+            updated_at = NOW()
+         WHERE id = $1
+         RETURNING *"
+    )
+    .bind(duplicate_id)
+    .bind(canonical_id)
+    .bind(merged_by)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Duplicates merged into this one earlier follow it
+    sqlx::query("UPDATE reports SET duplicate_of = $2, updated_at = NOW() WHERE duplicate_of = $1")
+        .bind(duplicate_id)
+        .bind(canonical_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO report_supporters (report_id, user_id, merged_from)
+         SELECT $2, s.user_id, $1 FROM (
+             SELECT $3::uuid AS user_id
+             UNION
+             SELECT user_id FROM report_supporters WHERE report_id = $1
+         ) s
+         WHERE s.user_id <> $4
+         ON CONFLICT (report_id, user_id) DO NOTHING"
+    )
+    .bind(duplicate_id)
+    .bind(canonical_id)
+    .bind(reporter_id)
+    .bind(canonical_reporter)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM report_supporters WHERE report_id = $1")
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE citizen_facts SET report_id = $2, updated_at = NOW() WHERE report_id = $1")
+        .bind(duplicate_id)
+        .bind(canonical_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // The canonical ticket carries on; its supporters follow it there
+    sqlx::query(
+        "UPDATE tickets SET status = 'closed', updated_at = NOW()
+         WHERE report_id = $1 AND status NOT IN ('resolved', 'closed')"
+    )
+    .bind(duplicate_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE duplicate_candidates SET status = CASE
+            WHEN (report_id = $1 AND candidate_id = $2) OR (report_id = $2 AND candidate_id = $1) THEN 'merged'
+            ELSE 'dismissed'
+         END
+         WHERE (report_id = $1 OR candidate_id = $1) AND status = 'open'"
+    )
+    .bind(duplicate_id)
+    .bind(canonical_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if let Some(cluster_id) = left_cluster {
+        crate::background::jobs::update_cluster_metadata(pool, cluster_id).await?;
+    }
+
+    let canonical_cluster: Option<Uuid> = sqlx::query_scalar("SELECT cluster_id FROM reports WHERE id = $1")
+        .bind(canonical_id)
+        .fetch_one(pool)
+        .await?;
+    if let Some(cluster_id) = canonical_cluster {
+        // The facts moved over now count for this cluster
+        crate::background::jobs::regenerate_cluster_description(pool, cluster_id).await?;
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(status: &str, moderation_status: &str) -> Report {
+        let now = chrono::Utc::now();
+        Report {
+            id: Uuid::new_v4(),
+            session_id: None,
+            user_id: Uuid::new_v4(),
+            category_id: None,
+            title: "Jalan rusak".to_string(),
+            description: "Jalan berlubang".to_string(),
+            location_text: None,
+            latitude: None,
+            longitude: None,
+            address: None,
+            region_code: None,
+            anonymity: "identified".to_string(),
+            incident_date: None,
+            reported_date: now,
+            status: status.to_string(),
+            moderation_status: moderation_status.to_string(),
+            rejection_reason: None,
+            duplicate_of: None,
+            is_complete: true,
+            completeness_score: rust_decimal::Decimal::ONE,
+            missing_fields: serde_json::json!([]),
+            entities: serde_json::json!({}),
+            cluster_id: None,
+            attachments: serde_json::json!([]),
+            metadata: serde_json::json!({}),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn merges_into_a_published_report() {
+        let canonical = report("verified", "approved");
+
+        assert_eq!(merge_refusal(&report("submitted", "pending"), &canonical), None);
+    }
+
+    #[test]
+    fn refuses_to_merge_a_report_into_itself() {
+        let published = report("verified", "approved");
+
+        assert!(merge_refusal(&published, &published).is_some());
+    }
+
+    #[test]
+    fn refuses_to_merge_a_duplicate_again() {
+        let canonical = report("verified", "approved");
+
+        assert!(merge_refusal(&report("duplicate", "approved"), &canonical).is_some());
+    }
+
+    #[test]
+    fn refuses_canonical_reports_that_are_not_published() {
+        let duplicate = report("submitted", "approved");
+
+        for (status, moderation_status) in [
+            ("draft", "approved"),
+            ("duplicate", "approved"),
+            ("rejected", "rejected"),
+            ("submitted", "pending"),
+            ("submitted", "screening"),
+        ] {
+            assert!(merge_refusal(&duplicate, &report(status, moderation_status)).is_some(), "{}/{}", status, moderation_status);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+use uuid::Uuid;
+use crate::models::{EvalCase, EvalRun, SystemPrompt, SystemPromptVersion};
+use super::prompts;
+use super::providers::{CompletionRequest, LlmMessage, LlmProvider};
+use super::structured;
+use super::usage::{self, CallContext, LlmCall};
+
+// Array items count as matched at or above this trigram similarity
+const ITEM_MATCH_THRESHOLD: f64 = 0.5;
+
+/// Template variable that receives a case's input, per evaluable prompt type.
+pub fn input_variable(prompt_type: &str) -> Option<&'static str> {
+    match prompt_type {
+        "report_extraction" => Some("conversation"),
+        "completeness_check" => Some("report"),
+        "ner_extraction" => Some("text"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct FieldScore {
+    pub exact: bool,
+    /// 0..1
+    pub fuzzy: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FieldSummary {
+    /// Share of cases matching exactly
+    pub exact: f64,
+    /// Mean fuzzy score
+    pub fuzzy: f64,
+}
+
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Word trigrams padded the way pg_trgm pads them
+fn trigrams(text: &str) -> HashSet<String> {
+    normalize(text)
+        .split(' ')
+        .filter(|word| !word.is_empty())
+        .flat_map(|word| {
+            let padded: Vec<char> = format!("  {} ", word).chars().collect();
+            padded
+                .windows(3)
+                .map(|w| w.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+fn is_blank(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::String(s) => s.trim().is_empty() || s.trim() == "null",
+        _ => false,
+    }
+}
+
+fn as_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Scores one output field against its expected value. Strings compare
+/// normalised text, arrays compare their items as sets, everything else must
+/// be equal.
+pub fn score_field(expected: &serde_json::Value, actual: &serde_json::Value) -> FieldScore {
+    let hit = |exact: bool| FieldScore { exact, fuzzy: if exact { 1.0 } else { 0.0 } };
+
+    match expected {
+        serde_json::Value::Null => hit(is_blank(actual)),
+        _ if is_blank(actual) => hit(false),
+        serde_json::Value::String(expected) => {
+            let actual = as_text(actual);
+            // A bare date matches any timestamp on that day
+            let exact = normalize(expected) == normalize(&actual)
+                || (expected.len() == 10 && actual.starts_with(expected.as_str()));
+            FieldScore {
+                exact,
+                fuzzy: if exact { 1.0 } else { similarity(expected, &actual) },
+            }
+        }
+        serde_json::Value::Bool(expected) => {
+            hit(actual.as_bool().or_else(|| as_text(actual).parse().ok()) == Some(*expected))
+        }
+        serde_json::Value::Number(expected) => {
+            let expected = expected.as_f64().unwrap_or_default();
+            match actual.as_f64().or_else(|| as_text(actual).parse().ok()) {
+                Some(actual) => {
+                    let diff = (expected - actual).abs();
+                    FieldScore {
+                        exact: diff < f64::EPSILON,
+                        fuzzy: (1.0 - diff / expected.abs().max(1.0)).max(0.0),
+                    }
+                }
+                None => hit(false),
+            }
+        }
+        serde_json::Value::Array(expected) => {
+            let expected: Vec<String> = expected.iter().map(as_text).collect();
+            let actual: Vec<String> = actual
+                .as_array()
+                .map(|items| items.iter().filter(|v| !is_blank(v)).map(as_text).collect())
+                .unwrap_or_else(|| vec![as_text(actual)]);
+
+            if expected.is_empty() || actual.is_empty() {
+                return hit(expected.is_empty() && actual.is_empty());
+            }
+
+            let matched = |item: &String, pool: &[String]| {
+                pool.iter().any(|other| similarity(item, other) >= ITEM_MATCH_THRESHOLD)
+            };
+            let recall = expected.iter().filter(|e| matched(e, &actual)).count() as f64 / expected.len() as f64;
+            let precision = actual.iter().filter(|a| matched(a, &expected)).count() as f64 / actual.len() as f64;
+
+            let normalized = |items: &[String]| items.iter().map(|i| normalize(i)).collect::<HashSet<_>>();
+            FieldScore {
+                exact: normalized(&expected) == normalized(&actual),
+                fuzzy: if recall + precision > 0.0 {
+                    2.0 * recall * precision / (recall + precision)
+                } else {
+                    0.0
+                },
+            }
+        }
+        serde_json::Value::Object(_) => hit(expected == actual),
+    }
+}
+
+/// Scores every field named in `expected`. An unparseable output scores zero
+/// on all of them.
+pub fn score_output(expected: &serde_json::Value, output: Option<&serde_json::Value>) -> BTreeMap<String, FieldScore> {
+    let Some(fields) = expected.as_object() else {
+        return BTreeMap::new();
+    };
+
+    fields
+        .iter()
+        .map(|(field, expected)| {
+            let score = match output {
+                Some(output) => score_field(expected, output.get(field).unwrap_or(&serde_json::Value::Null)),
+                None => FieldScore::default(),
+            };
+            (field.clone(), score)
+        })
+        .collect()
+}
+
+fn summarize(scores: &[BTreeMap<String, FieldScore>]) -> BTreeMap<String, FieldSummary> {
+    let mut summary: BTreeMap<String, (usize, usize, f64)> = BTreeMap::new();
+
+    for case in scores {
+        for (field, score) in case {
+            let entry = summary.entry(field.clone()).or_default();
+            entry.0 += 1;
+            entry.1 += score.exact as usize;
+            entry.2 += score.fuzzy;
+        }
+    }
+
+    summary
+        .into_iter()
+        .map(|(field, (count, exact, fuzzy))| {
+            (field, FieldSummary {
+                exact: exact as f64 / count as f64,
+                fuzzy: fuzzy / count as f64,
+            })
+        })
+        .collect()
+}
+
+/// Runs the prompt version of a queued run over the active cases of its
+/// type and stores per-case results plus the aggregate report. Case-level
+/// provider errors are recorded on the case; only setup failures fail the run.
+pub async fn execute_run(
+    pool: &PgPool,
+    run_id: Uuid,
+    provider: &dyn LlmProvider,
+) -> Result<EvalRun, Box<dyn std::error::Error + Send + Sync>> {
+    let run = sqlx::query_as::<_, EvalRun>(
+        "UPDATE eval_runs SET status = 'running', model = $2 WHERE id = $1 RETURNING *"
+    )
+    .bind(run_id)
+    .bind(provider.model())
+    .fetch_one(pool)
+    .await?;
+
+    let prompt = sqlx::query_as::<_, SystemPrompt>("SELECT * FROM system_prompts WHERE id = $1")
+        .bind(run.prompt_id)
+        .fetch_one(pool)
+        .await?;
+    let version = sqlx::query_as::<_, SystemPromptVersion>(
+        "SELECT * FROM system_prompt_versions WHERE prompt_id = $1 AND version = $2"
+    )
+    .bind(run.prompt_id)
+    .bind(run.prompt_version)
+    .fetch_one(pool)
+    .await?;
+
+    let variable = input_variable(&prompt.prompt_type)
+        .ok_or_else(|| format!("Prompt type {} cannot be evaluated", prompt.prompt_type))?;
+
+    let cases = sqlx::query_as::<_, EvalCase>(
+        "SELECT * FROM eval_cases WHERE prompt_type = $1 AND is_active = true ORDER BY created_at"
+    )
+    .bind(&prompt.prompt_type)
+    .fetch_all(pool)
+    .await?;
+
+    let context = CallContext {
+        api_key_id: run.api_key_id,
+        ..Default::default()
+    };
+    let mut scores = vec![];
+    let mut json_valid = 0usize;
+    let mut latencies = vec![];
+    let (mut prompt_tokens, mut completion_tokens) = (0i64, 0i64);
+
+    for case in &cases {
+        let rendered = prompts::render_version(&prompt, &version, &[(variable, &case.input)])?;
+        let request = CompletionRequest {
+            messages: vec![LlmMessage {
+                role: "user".to_string(),
+                content: rendered.text.clone(),
+            }],
+            temperature: Some(0.0),
+            max_tokens: Some(2000),
+            prompt_type: Some(prompt.prompt_type.clone()),
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let completion = provider.complete(&request).await;
+        let latency_ms = started.elapsed().as_millis() as i32;
+
+        usage::record(pool, LlmCall {
+            context: &context,
+            provider,
+            prompt_type: &prompt.prompt_type,
+            prompt: Some(&rendered.source),
+            started,
+            result: completion.as_ref(),
+        })
+        .await;
+
+        let (output, parsed, usage, error) = match completion {
+            Ok(completion) => {
+                let parsed = structured::parse::<serde_json::Value>(&completion.content).ok();
+                let usage = (completion.prompt_tokens, completion.completion_tokens);
+                (Some(completion.content), parsed, usage, None)
+            }
+            Err(e) => (None, None, (None, None), Some(e.to_string())),
+        };
+
+        let case_scores = score_output(&case.expected, parsed.as_ref());
+        json_valid += parsed.is_some() as usize;
+        latencies.push(latency_ms as f64);
+        prompt_tokens += usage.0.unwrap_or(0) as i64;
+        completion_tokens += usage.1.unwrap_or(0) as i64;
+
+        sqlx::query(
+            "INSERT INTO eval_results (run_id, case_id, output, json_valid, field_scores, latency_ms, prompt_tokens, completion_tokens, error)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+        )
+        .bind(run_id)
+        .bind(case.id)
+        .bind(output)
+        .bind(parsed.is_some())
+        .bind(serde_json::to_value(&case_scores)?)
+        .bind(latency_ms)
+        .bind(usage.0.map(|t| t as i32))
+        .bind(usage.1.map(|t| t as i32))
+        .bind(error)
+        .execute(pool)
+        .await?;
+
+        scores.push(case_scores);
+    }
+
+    let case_count = cases.len();
+    let rate = |n: usize| (case_count > 0).then(|| n as f64 / case_count as f64);
+
+    let run = sqlx::query_as::<_, EvalRun>(
+        "UPDATE eval_runs SET
+            status = 'completed',
+            case_count = $2,
+            json_valid_rate = $3,
+            field_scores = $4,
+            avg_latency_ms = $5,
+            prompt_tokens = $6,
+            completion_tokens = $7,
+            completed_at = NOW()
+         WHERE id = $1
+         RETURNING *"
+    )
+    .bind(run_id)
+    .bind(case_count as i32)
+    .bind(rate(json_valid))
+    .bind(serde_json::to_value(summarize(&scores))?)
+    .bind((case_count > 0).then(|| latencies.iter().sum::<f64>() / case_count as f64))
+    .bind(prompt_tokens)
+    .bind(completion_tokens)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(run)
+}
+
+/// Per-field score changes from `base` to `run`, for fields both runs scored.
+pub fn compare(base: &EvalRun, run: &EvalRun) -> serde_json::Value {
+    let fields = |run: &EvalRun| -> BTreeMap<String, FieldSummary> {
+        serde_json::from_value(run.field_scores.clone()).unwrap_or_default()
+    };
+    let (before, after) = (fields(base), fields(run));
+
+    let deltas: BTreeMap<&String, FieldSummary> = after
+        .iter()
+        .filter_map(|(field, now)| {
+            let was = before.get(field)?;
+            Some((field, FieldSummary {
+                exact: now.exact - was.exact,
+                fuzzy: now.fuzzy - was.fuzzy,
+            }))
+        })
+        .collect();
+
+    let delta = |now: Option<f64>, was: Option<f64>| Some(now? - was?);
+
+    serde_json::json!({
+        "base_run_id": base.id,
+        "run_id": run.id,
+        "json_valid_rate": delta(run.json_valid_rate, base.json_valid_rate),
+        "avg_latency_ms": delta(run.avg_latency_ms, base.avg_latency_ms),
+        "prompt_tokens": run.prompt_tokens - base.prompt_tokens,
+        "completion_tokens": run.completion_tokens - base.completion_tokens,
+        "fields": deltas,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(field_scores: serde_json::Value, json_valid_rate: f64, prompt_tokens: i64) -> EvalRun {
+        EvalRun {
+            id: Uuid::new_v4(),
+            prompt_id: Uuid::nil(),
+            prompt_version: 1,
+            api_key_id: None,
+            provider: "fake".to_string(),
+            model: Some("fake".to_string()),
+            status: "completed".to_string(),
+            case_count: 2,
+            json_valid_rate: Some(json_valid_rate),
+            field_scores,
+            avg_latency_ms: None,
+            prompt_tokens,
+            completion_tokens: 10,
+            error: None,
+            created_by: None,
+            created_at: chrono::Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn similarity_ranges_from_disjoint_to_identical() {
+        assert_eq!(similarity("Jalan Sudirman", "jalan sudirman"), 1.0);
+        assert_eq!(similarity("banjir", "sampah"), 0.0);
+        assert_eq!(similarity("", ""), 1.0);
+
+        let close = similarity("Jalan Sudirman", "Jl. Sudirman");
+        assert!(close > 0.0 && close < 1.0);
+    }
+
+    #[test]
+    fn strings_match_after_normalisation_and_dates_by_day() {
+        assert!(score_field(&json!("Jalan Rusak!"), &json!("jalan  rusak")).exact);
+        assert!(score_field(&json!("2024-05-01"), &json!("2024-05-01T08:00:00+07:00")).exact);
+
+        let partial = score_field(&json!("Jalan rusak parah"), &json!("Jalan rusak"));
+        assert!(!partial.exact);
+        assert!(partial.fuzzy > 0.0 && partial.fuzzy < 1.0);
+    }
+
+    #[test]
+    fn blank_answers_match_only_null_expectations() {
+        assert!(score_field(&json!(null), &json!("")).exact);
+        assert!(score_field(&json!(null), &json!("null")).exact);
+        assert!(!score_field(&json!(null), &json!("Pasar Baru")).exact);
+
+        let missing = score_field(&json!("Pasar Baru"), &json!(null));
+        assert!(!missing.exact);
+        assert_eq!(missing.fuzzy, 0.0);
+    }
+
+    #[test]
+    fn numbers_and_booleans_accept_text_answers() {
+        assert!(score_field(&json!(true), &json!("true")).exact);
+        assert!(!score_field(&json!(true), &json!(false)).exact);
+        assert!(score_field(&json!(0.5), &json!("0.5")).exact);
+
+        let off = score_field(&json!(0.8), &json!(0.6));
+        assert!(!off.exact);
+        assert!((off.fuzzy - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn arrays_score_items_as_sets() {
+        assert!(score_field(&json!(["Budi", "Siti"]), &json!(["siti", "budi"])).exact);
+        assert!(score_field(&json!([]), &json!([])).exact);
+        assert!(!score_field(&json!(["Budi"]), &json!([])).exact);
+
+        // Half the expected items found, nothing spurious: F1 of 2/3
+        let half = score_field(&json!(["Budi", "Siti"]), &json!(["Budi"]));
+        assert!(!half.exact);
+        assert!((half.fuzzy - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unparseable_output_scores_zero_on_every_field() {
+        let scores = score_output(&json!({"title": "Banjir", "urgency": "high"}), None);
+
+        assert_eq!(scores.len(), 2);
+        assert!(scores.values().all(|s| !s.exact && s.fuzzy == 0.0));
+    }
+
+    #[test]
+    fn summarize_averages_fields_over_cases() {
+        let case = |exact: bool, fuzzy: f64| {
+            BTreeMap::from([("title".to_string(), FieldScore { exact, fuzzy })])
+        };
+        let summary = summarize(&[case(true, 1.0), case(false, 0.5)]);
+
+        assert_eq!(summary["title"].exact, 0.5);
+        assert_eq!(summary["title"].fuzzy, 0.75);
+    }
+
+    #[test]
+    fn compare_reports_deltas_for_fields_both_runs_scored() {
+        let base = run(json!({
+            "title": {"exact": 0.5, "fuzzy": 0.75},
+            "urgency": {"exact": 1.0, "fuzzy": 1.0},
+        }), 0.5, 100);
+        let next = run(json!({
+            "title": {"exact": 1.0, "fuzzy": 1.0},
+            "category": {"exact": 1.0, "fuzzy": 1.0},
+        }), 1.0, 80);
+
+        let report = compare(&base, &next);
+
+        assert_eq!(report["json_valid_rate"], json!(0.5));
+        assert_eq!(report["prompt_tokens"], json!(-20));
+        assert_eq!(report["completion_tokens"], json!(0));
+        assert_eq!(report["avg_latency_ms"], json!(null));
+        assert_eq!(report["fields"], json!({"title": {"exact": 0.5, "fuzzy": 0.25}}));
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use super::llm::{CompletenessResult, LlmService};
+use super::{pseudonyms, regions};
+
+// Dates without a zone are taken as WIB
+const WIB_OFFSET_SECONDS: i32 = 7 * 3600;
+const MAX_TITLE_CHARS: usize = 100;
+
+pub struct DraftSync {
+    pub report_id: Uuid,
+    /// First time a report was stored for this session
+    pub created: bool,
+    pub completeness: CompletenessResult,
+}
+
+fn text_field(extracted: &serde_json::Value, field: &str) -> Option<String> {
+    extracted[field]
+        .as_str()
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && *v != "null")
+        .map(str::to_string)
+}
+
+/// Accepts the ISO forms the extraction prompt asks for: a full timestamp,
+/// a local date-time or a bare date.
+fn parse_incident_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    let wib = FixedOffset::east_opt(WIB_OFFSET_SECONDS)?;
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+
+    wib.from_local_datetime(&local)
+        .single()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+async fn match_category(pool: &PgPool, category: &str) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, name FROM categories
+         WHERE is_active = true
+         AND (name ILIKE $1 OR similarity(name, $1) > 0.3 OR description ILIKE '%' || $1 || '%')
+         ORDER BY (name ILIKE $1) DESC, similarity(name, $1) DESC
+         LIMIT 1"
+    )
+    .bind(category)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Runs the conversation through the extraction and completeness prompts and
+/// creates or refreshes the session's draft report. Returns `None` once the
+/// session's report has left the draft state or the session was closed.
+pub async fn sync_draft_report(
+    pool: &PgPool,
+    llm: &LlmService,
+    session_id: Uuid,
+    conversation: &str,
+) -> Result<Option<DraftSync>, Box<dyn std::error::Error>> {
+    let (user_id, anonymity, session_status): (Uuid, String, String) = sqlx::query_as(
+        "SELECT user_id, anonymity, status FROM chat_sessions WHERE id = $1"
+    )
+    .bind(session_id)
+    .fetch_one(pool)
+    .await?;
+
+    // Submitting a pseudonymous report closes its chat and unlinks it
+    if session_status != "active" {
+        return Ok(None);
+    }
+
+    let existing: Option<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, status FROM reports WHERE session_id = $1 ORDER BY created_at DESC LIMIT 1"
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if existing.as_ref().is_some_and(|(_, status)| status != "draft") {
+        return Ok(None);
+    }
+
+    // Calls for an existing draft are accounted to it
+    let scoped = existing.as_ref().map(|(report_id, _)| llm.for_report(*report_id));
+    let llm = scoped.as_ref().unwrap_or(llm);
+
+    let (mut extracted, extraction_prompt) = llm.extract_report_info(pool, conversation).await?;
+
+    let title = text_field(&extracted, "title")
+        .map(|t| t.chars().take(MAX_TITLE_CHARS).collect::<String>())
+        .unwrap_or_else(|| "Laporan dari percakapan".to_string());
+    let description = text_field(&extracted, "description")
+        .unwrap_or_else(|| conversation.lines().take(5).collect::<Vec<_>>().join(" "));
+    let location_text = text_field(&extracted, "location_text");
+
+    let category = match text_field(&extracted, "category") {
+        Some(name) => match_category(pool, &name).await?,
+        None => None,
+    };
+    let incident_date = text_field(&extracted, "incident_date")
+        .and_then(|d| parse_incident_date(&d));
+    let geocoded = match &location_text {
+        Some(text) => regions::geocode(pool, text).await?,
+        None => None,
+    };
+
+    // Let the completeness check see what could actually be resolved.
+    extracted["category"] = category
+        .as_ref()
+        .map(|(_, name)| serde_json::Value::from(name.clone()))
+        .unwrap_or(serde_json::Value::Null);
+    extracted["incident_date"] = incident_date
+        .map(|d| serde_json::Value::from(d.to_rfc3339()))
+        .unwrap_or(serde_json::Value::Null);
+
+    let (mut completeness, completeness_prompt) = llm.check_completeness(pool, &extracted).await?;
+    // Stored and sent to the client alike
+    completeness.completeness_score = completeness.completeness_score.clamp(0.0, 1.0);
+    let score = completeness.completeness_score;
+    let missing_fields = serde_json::to_value(&completeness.missing_fields)?;
+    let metadata = serde_json::json!({
+        "extraction_prompt": extraction_prompt,
+        "completeness_prompt": completeness_prompt,
+    });
+
+    let category_id = category.map(|(id, _)| id);
+    let region_code = geocoded.as_ref().map(|g| g.region_code.clone());
+    let latitude = geocoded.as_ref().and_then(|g| g.latitude);
+    let longitude = geocoded.as_ref().and_then(|g| g.longitude);
+
+    let (report_id, created) = match existing {
+        Some((report_id, _)) => {
+            sqlx::query(
+                "UPDATE reports SET
+                    title = $1,
+                    description = $2,
+                    category_id = COALESCE($3, category_id),
+                    location_text = COALESCE($4, location_text),
+                    latitude = COALESCE(latitude, $5),
+                    longitude = COALESCE(longitude, $6),
+                    region_code = COALESCE(region_code, $7),
+                    incident_date = COALESCE($8, incident_date),
+                    is_complete = $9,
+                    completeness_score = $10,
+                    missing_fields = $11,
+                    metadata = metadata || $12,
+                    updated_at = NOW()
+                 WHERE id = $13"
+            )
+            .bind(&title)
+            .bind(&description)
+            .bind(category_id)
+            .bind(&location_text)
+            .bind(latitude)
+            .bind(longitude)
+            .bind(&region_code)
+            .bind(incident_date)
+            .bind(completeness.is_complete)
+            .bind(score)
+            .bind(&missing_fields)
+            .bind(&metadata)
+            .bind(report_id)
+            .execute(pool)
+            .await?;
+
+            (report_id, false)
+        }
+        None => {
+            // Pseudonymous chats file their draft under the citizen's pseudonym
+            let reporter_id = if anonymity == "pseudonymous" {
+                let master = pseudonyms::master_key()?;
+                pseudonyms::find_or_create(pool, &master, user_id).await?
+            } else {
+                user_id
+            };
+
+            let report_id: Uuid = sqlx::query_scalar(
+                "INSERT INTO reports (
+                    session_id, user_id, anonymity, category_id, title, description,
+                    location_text, latitude, longitude, region_code, incident_date,
+                    status, is_complete, completeness_score, missing_fields, metadata
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 'draft', $12, $13, $14, $15)
+                RETURNING id"
+            )
+            .bind(session_id)
+            .bind(reporter_id)
+            .bind(&anonymity)
+            .bind(category_id)
+            .bind(&title)
+            .bind(&description)
+            .bind(&location_text)
+            .bind(latitude)
+            .bind(longitude)
+            .bind(&region_code)
+            .bind(incident_date)
+            .bind(completeness.is_complete)
+            .bind(score)
+            .bind(&missing_fields)
+            .bind(&metadata)
+            .fetch_one(pool)
+            .await?;
+
+            // The calls that drafted it ran before it existed
+            sqlx::query(
+                "UPDATE llm_calls SET report_id = $1
+                 WHERE session_id = $2 AND report_id IS NULL
+                 AND prompt_type IN ('report_extraction', 'completeness_check')"
+            )
+            .bind(report_id)
+            .bind(session_id)
+            .execute(pool)
+            .await?;
+
+            (report_id, true)
+        }
+    };
+
+    Ok(Some(DraftSync {
+        report_id,
+        created,
+        completeness,
+    }))
+}
+
+/// Names of people found so far in the session's reports, redacted from
+/// every later prompt of the session
+pub async fn known_persons(pool: &PgPool, session_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT DISTINCT person FROM reports r,
+         jsonb_array_elements_text(
+             CASE WHEN jsonb_typeof(r.entities->'persons') = 'array' THEN r.entities->'persons' ELSE '[]'::jsonb END
+         ) AS person
+         WHERE r.session_id = $1"
+    )
+    .bind(session_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Runs the conversation through the NER prompt and stores the entities on
+/// the report. Persons already known stay known, even if the model misses
+/// them this time or only saw their placeholder.
+pub async fn sync_entities(
+    pool: &PgPool,
+    llm: &LlmService,
+    session_id: Uuid,
+    report_id: Uuid,
+    conversation: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut entities, ner_prompt) = llm.extract_entities(pool, conversation).await?;
+
+    let mut persons = known_persons(pool, session_id).await?;
+    persons.extend(entities.persons.into_iter().filter(|p| !p.starts_with('[')));
+    persons.sort();
+    persons.dedup();
+    entities.persons = persons;
+
+    sqlx::query(
+        "UPDATE reports SET entities = $1, metadata = metadata || $2, updated_at = NOW() WHERE id = $3"
+    )
+    .bind(serde_json::to_value(&entities)?)
+    .bind(serde_json::json!({ "ner_prompt": ner_prompt }))
+    .bind(report_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
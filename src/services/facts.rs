@@ -0,0 +1,164 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::*;
+
+// Completeness credited when an approved fact supplies a field the report is missing,
+// plus a small bonus for any corroborating evidence.
+const MISSING_FIELD_WEIGHT: f64 = 0.25;
+const EVIDENCE_WEIGHT: f64 = 0.05;
+
+#[derive(sqlx::FromRow)]
+struct StatusChange {
+    #[sqlx(flatten)]
+    fact: CitizenFact,
+    first_approval: bool,
+}
+
+/// Sets the moderation status of a fact. Returns `None` if the fact does not
+/// exist or already has that status. The report is credited on the fact's
+/// first approval only.
+pub async fn moderate(
+    pool: &PgPool,
+    fact_id: Uuid,
+    moderator_id: Uuid,
+    status: &str,
+    note: Option<String>,
+) -> Result<Option<CitizenFact>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let fact = set_status(&mut tx, fact_id, moderator_id, status, note).await?;
+    tx.commit().await?;
+
+    if let Some(fact) = &fact {
+        refresh_cluster(pool, fact).await?;
+    }
+
+    Ok(fact)
+}
+
+/// `moderate` within the caller's transaction. The cluster description is
+/// left to `refresh_cluster` once it is committed.
+pub async fn set_status(
+    tx: &mut sqlx::PgConnection,
+    fact_id: Uuid,
+    moderator_id: Uuid,
+    status: &str,
+    note: Option<String>,
+) -> Result<Option<CitizenFact>, sqlx::Error> {
+    let change = sqlx::query_as::<_, StatusChange>(
+        "UPDATE citizen_facts f SET
+            status = $1,
+            moderation_note = $2,
+            moderated_by = $3,
+            moderated_at = NOW(),
+            approved_at = CASE WHEN $1 = 'approved' THEN COALESCE(f.approved_at, NOW()) ELSE f.approved_at END,
+            updated_at = NOW()
+         FROM citizen_facts previous
+         WHERE f.id = $4 AND previous.id = f.id AND f.status <> $1
+         RETURNING f.*, $1 = 'approved' AND previous.approved_at IS NULL AS first_approval"
+    )
+    .bind(status)
+    .bind(note)
+    .bind(moderator_id)
+    .bind(fact_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(change) = change else {
+        return Ok(None);
+    };
+
+    if change.first_approval {
+        apply_approved_fact(tx, &change.fact).await?;
+    }
+
+    Ok(Some(change.fact))
+}
+
+async fn apply_approved_fact(tx: &mut sqlx::PgConnection, fact: &CitizenFact) -> Result<(), sqlx::Error> {
+    let Some(report_id) = fact.report_id else {
+        return Ok(());
+    };
+
+    let has_location = fact.latitude.is_some() && fact.longitude.is_some();
+    let has_date = fact.observed_date.is_some();
+
+    sqlx::query(
+        "UPDATE reports SET
+            completeness_score = LEAST(1.0, completeness_score
+                + CASE WHEN $2 AND missing_fields ? 'location' THEN $4 ELSE 0 END
+                + CASE WHEN $3 AND missing_fields ? 'incident_date' THEN $4 ELSE 0 END
+                + $5),
+            missing_fields = missing_fields
+                - CASE WHEN $2 THEN 'location' ELSE '' END
+                - CASE WHEN $3 THEN 'incident_date' ELSE '' END,
+            updated_at = NOW()
+         WHERE id = $1"
+    )
+    .bind(report_id)
+    .bind(has_location)
+    .bind(has_date)
+    .bind(MISSING_FIELD_WEIGHT)
+    .bind(EVIDENCE_WEIGHT)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Rebuilds the description of the cluster an approved fact counts for
+pub async fn refresh_cluster(pool: &PgPool, fact: &CitizenFact) -> Result<(), sqlx::Error> {
+    if fact.status != "approved" {
+        return Ok(());
+    }
+
+    let cluster_id = match fact.report_id {
+        Some(report_id) => sqlx::query_scalar::<_, Option<Uuid>>("SELECT cluster_id FROM reports WHERE id = $1")
+            .bind(report_id)
+            .fetch_optional(pool)
+            .await?
+            .flatten(),
+        None => fact.cluster_id,
+    };
+
+    if let Some(cluster_id) = cluster_id {
+        crate::background::jobs::regenerate_cluster_description(pool, cluster_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Records a confirmation from `user_id`. Returns the updated fact, or `None`
+/// if that user had already confirmed it.
+pub async fn confirm(
+    pool: &PgPool,
+    fact_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<CitizenFact>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let inserted = sqlx::query(
+        "INSERT INTO citizen_fact_confirmations (fact_id, user_id)
+         VALUES ($1, $2)
+         ON CONFLICT DO NOTHING"
+    )
+    .bind(fact_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if inserted.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let fact = sqlx::query_as::<_, CitizenFact>(
+        "UPDATE citizen_facts SET confirmation_count = confirmation_count + 1
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(fact_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(fact))
+}
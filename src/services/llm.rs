@@ -1,271 +1,244 @@
 use rwf::prelude::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+use super::prompts::{self, PromptRef, RenderedPrompt};
+use super::providers::{CompletionRequest, LlmError, LlmProvider};
+use super::structured::{self, StructuredError};
+use super::usage::{self, CallContext, LlmCall};
 
-#[derive(Debug)]
-struct LlmError(String);
+pub use super::providers::LlmMessage;
 
-impl std::fmt::Display for LlmError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl std::error::Error for LlmError {}
-
-#[derive(Debug, Serialize)]
-struct OpenRouterRequest {
-    model: String,
-    messages: Vec<LlmMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LlmMessage {
-    pub role: String,
-    pub content: String,
-}
+// Corrective follow-ups after an unusable JSON answer
+const DEFAULT_STRUCTURED_RETRIES: u32 = 2;
 
-#[derive(Debug, Deserialize)]
-struct OpenRouterResponse {
-    choices: Vec<Choice>,
-    usage: Usage,
+pub struct LlmService {
+    provider: Arc<dyn LlmProvider>,
+    structured_retries: u32,
+    context: CallContext,
+    sensitive_terms: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: LlmMessage,
-}
+impl LlmService {
+    pub fn new(provider: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            provider,
+            structured_retries: DEFAULT_STRUCTURED_RETRIES,
+            context: CallContext::default(),
+            sensitive_terms: vec![],
+        }
+    }
 
-#[derive(Debug, Deserialize)]
-struct Usage {
-    total_tokens: u32,
-}
+    pub fn with_retries(mut self, structured_retries: u32) -> Self {
+        self.structured_retries = structured_retries;
+        self
+    }
 
-pub struct LlmService {
-    client: reqwest::Client,
-    api_key: String,
-    base_url: String,
-    default_model: String,
-}
+    /// Key and session the calls are accounted to
+    pub fn with_context(mut self, context: CallContext) -> Self {
+        self.context = context;
+        self
+    }
 
-impl LlmService {
-    pub fn new(api_key: String, base_url: String) -> Self {
+    /// The same service with its calls also accounted to `report_id`
+    pub fn for_report(&self, report_id: Uuid) -> Self {
         Self {
-            client: reqwest::Client::new(),
-            api_key,
-            base_url,
-            default_model: "anthropic/claude-3.5-sonnet".to_string(),
+            provider: self.provider.clone(),
+            structured_retries: self.structured_retries,
+            context: CallContext {
+                report_id: Some(report_id),
+                ..self.context.clone()
+            },
+            sensitive_terms: self.sensitive_terms.clone(),
         }
     }
 
-    pub async fn chat(
+    /// Names redacted from every prompt, e.g. persons already found by NER
+    pub fn with_sensitive_terms(mut self, sensitive_terms: Vec<String>) -> Self {
+        self.sensitive_terms = sensitive_terms;
+        self
+    }
+
+    async fn complete(
         &self,
+        pool: &PgPool,
         messages: Vec<LlmMessage>,
-        system_prompt: Option<String>,
+        prompt: &PromptRef,
     ) -> Result<String, Error> {
-        let mut final_messages = vec![];
-        
-        if let Some(prompt) = system_prompt {
-            final_messages.push(LlmMessage {
-                role: "system".to_string(),
-                content: prompt,
-            });
-        }
-        
-        final_messages.extend(messages);
-
-        let request = OpenRouterRequest {
-            model: self.default_model.clone(),
-            messages: final_messages,
+        let request = CompletionRequest {
+            system: None,
+            messages,
             temperature: Some(0.7),
             max_tokens: Some(2000),
+            sensitive_terms: self.sensitive_terms.clone(),
+            prompt_type: Some(prompt.prompt_type.clone()),
         };
 
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("HTTP-Referer", "https://balungpisah.app")
-            .header("X-Title", "BalungPisah")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| Error::new(LlmError(format!("HTTP request failed: {}", e))))?;
+        let started = Instant::now();
+        let result = self.provider.complete(&request).await;
 
-        if !response.status().is_success() {
-            let error_text: String = response.text().await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(Error::new(LlmError(format!("OpenRouter API error: {}", error_text))));
-        }
+        usage::record(pool, LlmCall {
+            context: &self.context,
+            provider: self.provider.as_ref(),
+            prompt_type: &prompt.prompt_type,
+            prompt: Some(prompt),
+            started,
+            result: result.as_ref(),
+        })
+        .await;
 
-        let result: OpenRouterResponse = response.json().await
-            .map_err(|e| Error::new(LlmError(format!("Failed to parse response: {}", e))))?;
-        
-        Ok(result
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_default())
+        Ok(result.map_err(Error::new)?.content)
+    }
+
+    /// Asks for a JSON answer of type `T`. An answer that cannot be read is
+    /// sent back with a corrective message, up to the configured retries.
+    async fn ask_structured<T: DeserializeOwned>(&self, pool: &PgPool, prompt: &RenderedPrompt) -> Result<T, Error> {
+        let mut messages = vec![LlmMessage {
+            role: "user".to_string(),
+            content: prompt.text.clone(),
+        }];
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            let response = self.complete(pool, messages.clone(), &prompt.source).await?;
+
+            let error = match structured::parse::<T>(&response) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+
+            if attempts > self.structured_retries {
+                return Err(Error::new(StructuredError::Exhausted {
+                    attempts,
+                    last: Box::new(error),
+                }));
+            }
+
+            tracing::debug!("Retrying unusable structured output ({}): {}", attempts, error);
+
+            messages.push(LlmMessage {
+                role: "assistant".to_string(),
+                content: response,
+            });
+            messages.push(LlmMessage {
+                role: "user".to_string(),
+                content: format!(
+                    "Jawaban sebelumnya tidak dapat dibaca ({}). Balas HANYA dengan JSON yang valid sesuai struktur yang diminta, tanpa teks lain.",
+                    error
+                ),
+            });
+        }
     }
 
     pub async fn extract_report_info(
         &self,
+        pool: &PgPool,
         conversation: &str,
-    ) -> Result<serde_json::Value, Error> {
-        let prompt = format!(
-            r#"Ekstrak informasi laporan dari percakapan berikut. Return ONLY valid JSON dengan struktur:
-{{
-  "title": "judul singkat laporan (max 100 char)",
-  "description": "deskripsi lengkap",
-  "location_text": "lokasi yang disebutkan",
-  "category": "kategori yang sesuai",
-  "incident_date": "tanggal kejadian ISO format jika disebutkan, null jika tidak",
-  "urgency": "low|medium|high"
-}}
-
-Percakapan:
-{}
-"#,
-            conversation
-        );
-
-        let response = self
-            .chat(
-                vec![LlmMessage {
-                    role: "user".to_string(),
-                    content: prompt,
-                }],
-                None,
-            )
-            .await?;
-
-        let json_str = response
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim();
-
-        serde_json::from_str(json_str)
-            .map_err(|e| Error::new(LlmError(format!("Failed to parse JSON: {}", e))))
+    ) -> Result<(serde_json::Value, PromptRef), Error> {
+        let prompt = prompts::render_active(pool, "report_extraction", &[("conversation", conversation)])
+            .await
+            .map_err(Error::new)?;
+
+        let report: ExtractedReport = self.ask_structured(pool, &prompt).await?;
+        let report = serde_json::to_value(report)
+            .map_err(|e| Error::new(LlmError::new(format!("Failed to serialize: {}", e))))?;
+
+        Ok((report, prompt.source))
     }
 
     pub async fn check_completeness(
         &self,
+        pool: &PgPool,
         report_data: &serde_json::Value,
-    ) -> Result<CompletenessResult, Error> {
-        let prompt = format!(
-            r#"Periksa kelengkapan laporan berikut dan beri skor 0-1. Return ONLY valid JSON:
-{{
-  "is_complete": true/false,
-  "completeness_score": 0.0-1.0,
-  "missing_fields": ["field1", "field2"],
-  "suggestions": ["saran perbaikan"]
-}}
-
-Required fields untuk laporan lengkap:
-- Deskripsi jelas masalah (minimal 20 karakter)
-- Lokasi spesifik (nama jalan, kelurahan, atau koordinat)
-- Waktu/tanggal kejadian (minimal perkiraan)
-- Kategori masalah
-
-Laporan:
-{}
-"#,
-            serde_json::to_string_pretty(report_data)
-                .map_err(|e| Error::new(LlmError(format!("Failed to serialize: {}", e))))?
-        );
-
-        let response = self
-            .chat(
-                vec![LlmMessage {
-                    role: "user".to_string(),
-                    content: prompt,
-                }],
-                None,
-            )
-            .await?;
-
-        let json_str = response
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim();
-
-        serde_json::from_str(json_str)
-            .map_err(|e| Error::new(LlmError(format!("Failed to parse JSON: {}", e))))
+    ) -> Result<(CompletenessResult, PromptRef), Error> {
+        let report = serde_json::to_string_pretty(report_data)
+            .map_err(|e| Error::new(LlmError::new(format!("Failed to serialize: {}", e))))?;
+
+        let prompt = prompts::render_active(pool, "completeness_check", &[("report", &report)])
+            .await
+            .map_err(Error::new)?;
+
+        Ok((self.ask_structured(pool, &prompt).await?, prompt.source))
     }
 
-    pub async fn extract_entities(&self, text: &str) -> Result<ExtractedEntities, Error> {
-        let prompt = format!(
-            r#"Ekstrak entitas dari teks laporan berikut. Return ONLY valid JSON:
-{{
-  "locations": ["lokasi1", "lokasi2"],
-  "dates": ["tanggal1"],
-  "organizations": ["instansi terkait"],
-  "persons": ["nama orang jika ada"],
-  "facilities": ["fasilitas yang disebutkan"]
-}}
-
-Teks: {}
-"#,
-            text
-        );
-
-        let response = self
-            .chat(
-                vec![LlmMessage {
-                    role: "user".to_string(),
-                    content: prompt,
-                }],
-                None,
-            )
-            .await?;
-
-        let json_str = response
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim();
-
-        serde_json::from_str(json_str)
-            .map_err(|e| Error::new(LlmError(format!("Failed to parse JSON: {}", e))))
+    pub async fn extract_entities(
+        &self,
+        pool: &PgPool,
+        text: &str,
+    ) -> Result<(ExtractedEntities, PromptRef), Error> {
+        let prompt = prompts::render_active(pool, "ner_extraction", &[("text", text)])
+            .await
+            .map_err(Error::new)?;
+
+        Ok((self.ask_structured(pool, &prompt).await?, prompt.source))
     }
 
-    pub async fn check_user_completion_intent(&self, message: &str) -> Result<bool, Error> {
-        let prompt = format!(
-            r#"Tentukan apakah pesan pengguna berikut mengindikasikan bahwa mereka ingin menyelesaikan/submit laporan mereka.
-Return ONLY "true" atau "false" (tanpa quotes).
-
-Contoh pesan yang menandakan selesai:
-- "cukup sekian"
-- "sudah selesai"
-- "kirim saja"
-- "simpan laporan"
-- "itu saja"
-- "selesai"
-
-Pesan: {}
-"#,
-            message
-        );
-
-        let response = self
-            .chat(
-                vec![LlmMessage {
-                    role: "user".to_string(),
-                    content: prompt,
-                }],
-                None,
-            )
-            .await?;
-
-        Ok(response.trim().to_lowercase() == "true")
+    pub async fn classify_content(
+        &self,
+        pool: &PgPool,
+        text: &str,
+    ) -> Result<(ModerationVerdict, PromptRef), Error> {
+        let prompt = prompts::render_active(pool, "content_moderation", &[("text", text)])
+            .await
+            .map_err(Error::new)?;
+
+        Ok((self.ask_structured(pool, &prompt).await?, prompt.source))
+    }
+}
+
+/// Shape the report extraction prompt asks for. An object with none of
+/// these fields set is not an answer, so parsing moves on to the next one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "ExtractedReportFields")]
+pub struct ExtractedReport {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub location_text: Option<String>,
+    pub category: Option<String>,
+    pub incident_date: Option<String>,
+    pub urgency: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExtractedReportFields {
+    title: Option<String>,
+    description: Option<String>,
+    location_text: Option<String>,
+    category: Option<String>,
+    incident_date: Option<String>,
+    urgency: Option<String>,
+}
+
+impl TryFrom<ExtractedReportFields> for ExtractedReport {
+    type Error = String;
+
+    fn try_from(fields: ExtractedReportFields) -> Result<Self, Self::Error> {
+        let report = ExtractedReport {
+            title: fields.title,
+            description: fields.description,
+            location_text: fields.location_text,
+            category: fields.category,
+            incident_date: fields.incident_date,
+            urgency: fields.urgency,
+        };
+
+        let fields = [
+            &report.title,
+            &report.description,
+            &report.location_text,
+            &report.category,
+            &report.incident_date,
+            &report.urgency,
+        ];
+        if fields.iter().all(|field| field.is_none()) {
+            return Err("no report field is set".to_string());
+        }
+
+        Ok(report)
     }
 }
 
@@ -284,4 +257,11 @@ pub struct ExtractedEntities {
     pub organizations: Vec<String>,
     pub persons: Vec<String>,
     pub facilities: Vec<String>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModerationVerdict {
+    pub flagged: bool,
+    pub category: Option<String>,
+    pub reason: Option<String>,
+}
@@ -1,3 +1,17 @@
 pub mod llm;
+pub mod providers;
+pub mod facts;
+pub mod moderation;
+pub mod duplicates;
+pub mod resolution;
+pub mod regions;
+pub mod extraction;
+pub mod prompts;
+pub mod evals;
+pub mod structured;
+pub mod usage;
+pub mod permissions;
+pub mod pseudonyms;
+pub mod redaction;
 
 //pub use llm::LlmService;
\ No newline at end of file
@@ -0,0 +1,539 @@
+//! Moderation of citizen content. New reports, comments and facts are
+//! pre-screened against the keyword lists and, when enabled, the
+//! `content_moderation` classifier prompt. Flagged content is held back from
+//! publishing and queued for a moderator; facts are always queued, as they
+//! were moderated by hand before.
+
+use regex::Regex;
+use rwf::job::Job;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::*;
+use super::facts;
+use super::llm::{LlmService, ModerationVerdict};
+use super::providers;
+
+/// The text of an item's content, for `i.content_type` and `i.content_id`.
+/// A report's text is its title and description, screened and audited together.
+const CONTENT_SQL: &str = "CASE i.content_type
+    WHEN 'report' THEN (SELECT c.title || E'\\n\\n' || c.description FROM reports c WHERE c.id = i.content_id)
+    WHEN 'comment' THEN (SELECT c.comment FROM ticket_comments c WHERE c.id = i.content_id)
+    WHEN 'fact' THEN (SELECT c.content FROM citizen_facts c WHERE c.id = i.content_id)
+END";
+
+/// The title of a report item, edited apart from its body
+pub const TITLE_SQL: &str = "CASE i.content_type
+    WHEN 'report' THEN (SELECT c.title FROM reports c WHERE c.id = i.content_id)
+END";
+
+/// The text an edit replaces: a report's description, a comment or a fact
+pub const BODY_SQL: &str = "CASE i.content_type
+    WHEN 'report' THEN (SELECT c.description FROM reports c WHERE c.id = i.content_id)
+    WHEN 'comment' THEN (SELECT c.comment FROM ticket_comments c WHERE c.id = i.content_id)
+    WHEN 'fact' THEN (SELECT c.content FROM citizen_facts c WHERE c.id = i.content_id)
+END";
+
+#[derive(Debug)]
+pub struct ModerationError(pub String);
+
+impl std::fmt::Display for ModerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ModerationError {}
+
+impl From<sqlx::Error> for ModerationError {
+    fn from(e: sqlx::Error) -> Self {
+        ModerationError(format!("Database error: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Report,
+    Comment,
+    Fact,
+}
+
+impl ContentType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentType::Report => "report",
+            ContentType::Comment => "comment",
+            ContentType::Fact => "fact",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "report" => Some(ContentType::Report),
+            "comment" => Some(ContentType::Comment),
+            "fact" => Some(ContentType::Fact),
+            _ => None,
+        }
+    }
+
+    /// Table holding `moderation_status`; facts keep their own `status`
+    fn table(self) -> Option<&'static str> {
+        match self {
+            ContentType::Report => Some("reports"),
+            ContentType::Comment => Some("ticket_comments"),
+            ContentType::Fact => None,
+        }
+    }
+}
+
+fn flag(source: &str, category: &str, detail: Option<String>) -> serde_json::Value {
+    serde_json::json!({ "source": source, "category": category, "detail": detail })
+}
+
+async fn content_text(
+    executor: impl sqlx::PgExecutor<'_>,
+    content_type: ContentType,
+    content_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<String>>(&format!(
+        "SELECT {} FROM (SELECT $1::varchar AS content_type, $2::uuid AS content_id) i",
+        CONTENT_SQL
+    ))
+    .bind(content_type.as_str())
+    .bind(content_id)
+    .fetch_one(executor)
+    .await
+}
+
+/// The report and cluster the content was posted on
+async fn posted_on(pool: &PgPool, content_type: ContentType, content_id: Uuid) -> Result<(Option<Uuid>, Option<Uuid>), sqlx::Error> {
+    match content_type {
+        ContentType::Report => Ok((Some(content_id), None)),
+        ContentType::Comment => {
+            let report_id: Option<Uuid> = sqlx::query_scalar(
+                "SELECT t.report_id FROM ticket_comments c JOIN tickets t ON t.id = c.ticket_id WHERE c.id = $1"
+            )
+            .bind(content_id)
+            .fetch_optional(pool)
+            .await?;
+
+            Ok((report_id, None))
+        }
+        ContentType::Fact => Ok(sqlx::query_as("SELECT report_id, cluster_id FROM citizen_facts WHERE id = $1")
+            .bind(content_id)
+            .fetch_optional(pool)
+            .await?
+            .unwrap_or((None, None))),
+    }
+}
+
+async fn set_content_status(pool: &PgPool, content_type: ContentType, content_id: Uuid, status: &str) -> Result<(), sqlx::Error> {
+    let Some(table) = content_type.table() else {
+        return Ok(());
+    };
+
+    sqlx::query(&format!("UPDATE {} SET moderation_status = $1 WHERE id = $2", table))
+        .bind(status)
+        .bind(content_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether `text` contains the keyword, case-insensitively and not as part
+/// of a longer word
+fn mentions(text: &str, term: &str) -> bool {
+    let term = term.trim();
+    if term.is_empty() {
+        return false;
+    }
+
+    // `\b` only holds next to a word character, e.g. not after "c++"
+    let edge = |c: Option<char>| if c.is_some_and(|c| c.is_alphanumeric() || c == '_') { r"\b" } else { "" };
+    let pattern = format!(
+        "(?i){}{}{}",
+        edge(term.chars().next()),
+        regex::escape(term),
+        edge(term.chars().last())
+    );
+
+    Regex::new(&pattern).is_ok_and(|pattern| pattern.is_match(text))
+}
+
+/// Keywords found in the text, matched case-insensitively as whole words
+pub async fn keyword_flags(pool: &PgPool, text: &str) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    let keywords: Vec<(String, String)> = sqlx::query_as("SELECT term, category FROM moderation_keywords")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(keywords
+        .into_iter()
+        .filter(|(term, _)| mentions(text, term))
+        .map(|(term, category)| flag("keyword", &category, Some(term)))
+        .collect())
+}
+
+/// Pre-screens new or changed content. Returns the content's new
+/// `moderation_status` when it is held back, `None` when it was let through.
+/// Content already waiting in the queue stays there, and content a moderator
+/// or the classifier decided on is left alone until its text changes.
+pub async fn screen(pool: &PgPool, content_type: ContentType, content_id: Uuid) -> Result<Option<&'static str>, ModerationError> {
+    let config = crate::config::Config::load()
+        .map_err(|e| ModerationError(format!("Config error: {}", e)))?;
+
+    let Some(text) = content_text(pool, content_type, content_id).await? else {
+        return Ok(None);
+    };
+
+    let decided: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM moderation_items
+         WHERE content_type = $1 AND content_id = $2
+         AND status IN ('approved', 'rejected') AND screened_content = $3"
+    )
+    .bind(content_type.as_str())
+    .bind(content_id)
+    .bind(&text)
+    .fetch_optional(pool)
+    .await?;
+
+    if decided.is_some() {
+        return Ok(None);
+    }
+
+    let flags = keyword_flags(pool, &text).await?;
+    let status = if !flags.is_empty() || content_type == ContentType::Fact {
+        "pending"
+    } else if config.moderation_classifier_enabled {
+        "screening"
+    } else {
+        return Ok(None);
+    };
+
+    let (report_id, cluster_id) = posted_on(pool, content_type, content_id).await?;
+
+    let item_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO moderation_items (content_type, content_id, report_id, cluster_id, flags, status, screened_content)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (content_type, content_id) DO UPDATE SET
+            flags = EXCLUDED.flags,
+            status = EXCLUDED.status,
+            screened_content = EXCLUDED.screened_content,
+            resolved_at = NULL
+         RETURNING id"
+    )
+    .bind(content_type.as_str())
+    .bind(content_id)
+    .bind(report_id)
+    .bind(cluster_id)
+    .bind(serde_json::Value::from(flags))
+    .bind(status)
+    .bind(&text)
+    .fetch_one(pool)
+    .await?;
+
+    set_content_status(pool, content_type, content_id, "pending").await?;
+
+    if status == "screening" {
+        crate::background::jobs::ModerationScreenJob
+            .execute_async(serde_json::json!({ "item_id": item_id }))
+            .await
+            .map_err(|e| ModerationError(format!("Cannot queue screening: {}", e)))?;
+    }
+
+    Ok(Some("pending"))
+}
+
+async fn run_classifier(pool: &PgPool, text: &str) -> Result<Option<ModerationVerdict>, Box<dyn std::error::Error + Send + Sync>> {
+    let config = crate::config::Config::load()
+        .map_err(|e| format!("Config error: {}", e))?;
+
+    let Some(provider) = providers::active(pool, &config.llm).await? else {
+        return Ok(None);
+    };
+
+    let llm = LlmService::new(provider).with_retries(config.llm.structured_retries);
+    let (verdict, _) = llm.classify_content(pool, text).await?;
+
+    Ok(Some(verdict))
+}
+
+/// Runs the classifier on an item waiting for it. Clean content is
+/// published; anything else, including a failed call, goes to a moderator.
+pub async fn classify(pool: &PgPool, item_id: Uuid) -> Result<(), ModerationError> {
+    let item = sqlx::query_as::<_, ModerationItem>(
+        "SELECT * FROM moderation_items WHERE id = $1 AND status = 'screening'"
+    )
+    .bind(item_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(item) = item else {
+        return Ok(());
+    };
+    let content_type = ContentType::parse(&item.content_type)
+        .ok_or_else(|| ModerationError(format!("Unknown content type {}", item.content_type)))?;
+    let text = content_text(pool, content_type, item.content_id).await?.unwrap_or_default();
+
+    let flags = match run_classifier(pool, &text).await {
+        Ok(Some(verdict)) if verdict.flagged => vec![flag(
+            "classifier",
+            verdict.category.as_deref().unwrap_or("unknown"),
+            verdict.reason,
+        )],
+        Ok(Some(_)) => {
+            sqlx::query("UPDATE moderation_items SET status = 'approved', resolved_at = NOW() WHERE id = $1 AND status = 'screening'")
+                .bind(item.id)
+                .execute(pool)
+                .await?;
+            set_content_status(pool, content_type, item.content_id, "approved").await?;
+
+            return Ok(());
+        }
+        Ok(None) => vec![flag("classifier", "unavailable", Some("No active API key configured".to_string()))],
+        Err(e) => vec![flag("classifier", "error", Some(e.to_string()))],
+    };
+
+    sqlx::query("UPDATE moderation_items SET status = 'pending', flags = flags || $2 WHERE id = $1 AND status = 'screening'")
+        .bind(item.id)
+        .bind(serde_json::Value::from(flags))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Why a moderator's decision cannot be taken as sent, if it cannot
+pub fn action_refusal(req: &ModerationActionRequest) -> Option<&'static str> {
+    let filled = |text: &Option<String>| text.as_deref().is_some_and(|t| !t.trim().is_empty());
+
+    match req.action.as_str() {
+        "approve" => None,
+        "reject" if !filled(&req.note) => Some("A rejection needs a note with the reason"),
+        "reject" => None,
+        "edit" if !filled(&req.note) || !filled(&req.content) => Some("An edit needs the edited content and a note"),
+        "edit" => None,
+        _ => Some("Invalid moderation action"),
+    }
+}
+
+/// Applies a moderator's decision to an item that is still open and records
+/// it. Returns `None` if there is no such item. `req` is expected to be
+/// validated: a reject carries a note, an edit its content.
+pub async fn decide(
+    pool: &PgPool,
+    item_id: Uuid,
+    moderator_id: Uuid,
+    req: &ModerationActionRequest,
+) -> Result<Option<ModerationItem>, ModerationError> {
+    let mut tx = pool.begin().await?;
+
+    let status = if req.action == "reject" { "rejected" } else { "approved" };
+    let item = sqlx::query_as::<_, ModerationItem>(
+        "UPDATE moderation_items SET status = $2, resolved_at = NOW()
+         WHERE id = $1 AND status IN ('screening', 'pending')
+         RETURNING *"
+    )
+    .bind(item_id)
+    .bind(status)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // Never queued, or decided by someone else
+    let Some(item) = item else {
+        return Ok(None);
+    };
+    let content_type = ContentType::parse(&item.content_type)
+        .ok_or_else(|| ModerationError(format!("Unknown content type {}", item.content_type)))?;
+    let previous = content_text(&mut *tx, content_type, item.content_id).await?;
+
+    let mut left_cluster: Option<Uuid> = None;
+    match (content_type, req.action.as_str()) {
+        (ContentType::Report, "reject") => {
+            // Out of its cluster, so it no longer feeds the problem shop
+            left_cluster = sqlx::query_scalar(
+                "UPDATE reports r SET status = 'rejected', moderation_status = 'rejected',
+                    rejection_reason = $2, cluster_id = NULL, updated_at = NOW()
+                 FROM (SELECT id, cluster_id FROM reports WHERE id = $1) old
+                 WHERE r.id = old.id
+                 RETURNING old.cluster_id"
+            )
+            .bind(item.content_id)
+            .bind(&req.note)
+            .fetch_optional(&mut *tx)
+            .await?
+            .flatten();
+        }
+        (ContentType::Report, "edit") => {
+            sqlx::query(
+                "UPDATE reports SET title = COALESCE($2, title), description = $3, moderation_status = 'approved', updated_at = NOW()
+                 WHERE id = $1"
+            )
+            .bind(item.content_id)
+            .bind(&req.title)
+            .bind(&req.content)
+            .execute(&mut *tx)
+            .await?;
+        }
+        (ContentType::Comment, "edit") => {
+            sqlx::query("UPDATE ticket_comments SET comment = $2, moderation_status = 'approved' WHERE id = $1")
+                .bind(item.content_id)
+                .bind(&req.content)
+                .execute(&mut *tx)
+                .await?;
+        }
+        (ContentType::Fact, "edit") => {
+            sqlx::query("UPDATE citizen_facts SET content = $2, updated_at = NOW() WHERE id = $1")
+                .bind(item.content_id)
+                .bind(&req.content)
+                .execute(&mut *tx)
+                .await?;
+        }
+        (ContentType::Fact, _) => {}
+        (_, _) => {
+            if let Some(table) = content_type.table() {
+                sqlx::query(&format!("UPDATE {} SET moderation_status = $1 WHERE id = $2", table))
+                    .bind(status)
+                    .bind(item.content_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+
+    let edited = match req.action.as_str() {
+        "edit" => content_text(&mut *tx, content_type, item.content_id).await?,
+        _ => None,
+    };
+
+    if edited.is_some() {
+        // The edited text counts as decided, screening it again keeps it published
+        sqlx::query("UPDATE moderation_items SET screened_content = $2 WHERE id = $1")
+            .bind(item.id)
+            .bind(&edited)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO moderation_decisions (item_id, moderator_id, action, note, previous_content, new_content)
+         VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(item.id)
+    .bind(moderator_id)
+    .bind(&req.action)
+    .bind(&req.note)
+    .bind(previous)
+    .bind(edited)
+    .execute(&mut *tx)
+    .await?;
+
+    // Facts are decided through their own moderation, which applies an
+    // approved fact to its report
+    let fact = match content_type {
+        ContentType::Fact => facts::set_status(&mut tx, item.content_id, moderator_id, status, req.note.clone()).await?,
+        _ => None,
+    };
+
+    tx.commit().await?;
+
+    if let Some(cluster_id) = left_cluster {
+        crate::background::jobs::update_cluster_metadata(pool, cluster_id).await?;
+    }
+    if let Some(fact) = &fact {
+        facts::refresh_cluster(pool, fact).await?;
+    }
+
+    Ok(Some(item))
+}
+
+/// Closes the queue item of a fact moderated through `/panel/facts`, keeping
+/// the audit trail complete
+pub async fn record_fact_decision(
+    pool: &PgPool,
+    fact: &CitizenFact,
+    moderator_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let action = match fact.status.as_str() {
+        "approved" => "approve",
+        "rejected" => "reject",
+        _ => return Ok(()),
+    };
+
+    let item_id: Option<Uuid> = sqlx::query_scalar(
+        "UPDATE moderation_items SET status = $2, resolved_at = NOW()
+         WHERE content_type = 'fact' AND content_id = $1
+         RETURNING id"
+    )
+    .bind(fact.id)
+    .bind(&fact.status)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(item_id) = item_id {
+        sqlx::query(
+            "INSERT INTO moderation_decisions (item_id, moderator_id, action, note)
+             VALUES ($1, $2, $3, $4)"
+        )
+        .bind(item_id)
+        .bind(moderator_id)
+        .bind(action)
+        .bind(&fact.moderation_note)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(action: &str, note: Option<&str>, content: Option<&str>) -> ModerationActionRequest {
+        ModerationActionRequest {
+            action: action.to_string(),
+            note: note.map(String::from),
+            content: content.map(String::from),
+            title: None,
+        }
+    }
+
+    #[test]
+    fn keywords_match_whole_words_in_any_case() {
+        assert!(mentions("Dasar BODOH semua", "bodoh"));
+        assert!(mentions("dasar bodoh.", " bodoh "));
+        assert!(!mentions("kebodohan birokrasi", "bodoh"));
+        assert!(!mentions("apa saja", ""));
+    }
+
+    #[test]
+    fn keywords_are_literal_text() {
+        assert!(mentions("pakai c++ saja", "c++"));
+        assert!(mentions("harga (naik) lagi", "(naik)"));
+        assert!(!mentions("harga naik lagi", "na.k"));
+    }
+
+    #[test]
+    fn approvals_need_nothing_else() {
+        assert_eq!(action_refusal(&action("approve", None, None)), None);
+    }
+
+    #[test]
+    fn rejections_need_a_reason() {
+        assert!(action_refusal(&action("reject", None, None)).is_some());
+        assert!(action_refusal(&action("reject", Some("  "), None)).is_some());
+        assert_eq!(action_refusal(&action("reject", Some("Spam"), None)), None);
+    }
+
+    #[test]
+    fn edits_need_content_and_a_note() {
+        assert!(action_refusal(&action("edit", Some("Kata kasar"), None)).is_some());
+        assert!(action_refusal(&action("edit", None, Some("Teks baru"))).is_some());
+        assert_eq!(action_refusal(&action("edit", Some("Kata kasar"), Some("Teks baru"))), None);
+    }
+
+    #[test]
+    fn unknown_actions_are_refused() {
+        assert!(action_refusal(&action("delete", Some("x"), Some("y"))).is_some());
+    }
+}
@@ -0,0 +1,43 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::{Permission, PermissionGrant};
+
+/// Roles that can hold permissions: the platform roles of `users.role` and
+/// the `institution_members.member_role`s, prefixed with `institution:`.
+pub const ROLES: [&str; 5] = ["user", "moderator", "admin", "institution:member", "institution:admin"];
+
+/// Every permission the user holds: those of their role, those of their
+/// memberships in active institutions (limited to that institution) and
+/// their direct grants.
+pub async fn grants_for(pool: &PgPool, user_id: Uuid, role: &str) -> Result<Vec<PermissionGrant>, sqlx::Error> {
+    sqlx::query_as::<_, PermissionGrant>(
+        "SELECT permission, NULL::uuid AS institution_id, NULL::varchar AS region_code
+         FROM role_permissions WHERE role = $2
+         UNION
+         SELECT rp.permission, m.institution_id, NULL::varchar
+         FROM institution_members m
+         JOIN institutions i ON i.id = m.institution_id AND i.is_active = true
+         JOIN role_permissions rp ON rp.role = 'institution:' || m.member_role
+         WHERE m.user_id = $1
+         UNION
+         SELECT permission, institution_id, region_code
+         FROM user_permissions WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .bind(role)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn list(pool: &PgPool) -> Result<Vec<Permission>, sqlx::Error> {
+    sqlx::query_as::<_, Permission>(
+        "SELECT p.key, p.description,
+                ARRAY_REMOVE(ARRAY_AGG(rp.role ORDER BY rp.role), NULL) AS roles
+         FROM permissions p
+         LEFT JOIN role_permissions rp ON rp.permission = p.key
+         GROUP BY p.key, p.description
+         ORDER BY p.key"
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,325 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::{PromptDiffLine, SystemPrompt, SystemPromptVersion};
+
+#[derive(Debug)]
+pub struct PromptError(pub String);
+
+impl std::fmt::Display for PromptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PromptError {}
+
+/// Identifies the prompt that produced an LLM output; stored in the
+/// `metadata` of the row holding that output.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptRef {
+    pub prompt_id: Uuid,
+    pub prompt_type: String,
+    pub version: i32,
+}
+
+pub struct RenderedPrompt {
+    pub text: String,
+    pub source: PromptRef,
+}
+
+pub async fn load_active(pool: &PgPool, prompt_type: &str) -> Result<Option<SystemPrompt>, sqlx::Error> {
+    sqlx::query_as::<_, SystemPrompt>(
+        "SELECT * FROM system_prompts
+         WHERE prompt_type = $1
+         AND is_active = true
+         ORDER BY version DESC, updated_at DESC
+         LIMIT 1"
+    )
+    .bind(prompt_type)
+    .fetch_optional(pool)
+    .await
+}
+
+// Values are inserted verbatim apart from this, so user text cannot smuggle
+// in placeholders or control characters.
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect::<String>()
+        .replace("{{", "{ {")
+        .replace("}}", "} }")
+}
+
+/// Substitutes `{{name}}` placeholders. Every variable declared in the
+/// prompt's `variables` must be supplied, and the text may only use declared
+/// variables. Rendering is a single pass; substituted values are not rescanned.
+pub fn render(prompt: &SystemPrompt, values: &[(&str, &str)]) -> Result<RenderedPrompt, PromptError> {
+    let declared: Vec<&str> = prompt
+        .variables
+        .as_object()
+        .map(|vars| vars.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+
+    if let Some(missing) = declared.iter().find(|name| !values.iter().any(|(key, _)| key == *name)) {
+        return Err(PromptError(format!(
+            "Prompt {} (v{}) requires variable '{}'",
+            prompt.name, prompt.version, missing
+        )));
+    }
+
+    let mut text = String::with_capacity(prompt.prompt_text.len());
+    let mut rest = prompt.prompt_text.as_str();
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .ok_or_else(|| PromptError(format!("Unclosed placeholder in prompt {}", prompt.name)))?;
+
+        let name = rest[start + 2..end].trim();
+        if !declared.contains(&name) {
+            return Err(PromptError(format!(
+                "Prompt {} (v{}) uses undeclared variable '{}'",
+                prompt.name, prompt.version, name
+            )));
+        }
+
+        let value = values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| escape(value))
+            .unwrap_or_default();
+
+        text.push_str(&rest[..start]);
+        text.push_str(&value);
+        rest = &rest[end + 2..];
+    }
+
+    text.push_str(rest);
+
+    Ok(RenderedPrompt {
+        text,
+        source: PromptRef {
+            prompt_id: prompt.id,
+            prompt_type: prompt.prompt_type.clone(),
+            version: prompt.version,
+        },
+    })
+}
+
+/// Loads the active prompt of a type and renders it.
+pub async fn render_active(
+    pool: &PgPool,
+    prompt_type: &str,
+    values: &[(&str, &str)],
+) -> Result<RenderedPrompt, PromptError> {
+    let prompt = load_active(pool, prompt_type)
+        .await
+        .map_err(|e| PromptError(format!("Failed to load prompt: {}", e)))?
+        .ok_or_else(|| PromptError(format!("No active prompt of type {}", prompt_type)))?;
+
+    render(&prompt, values)
+}
+
+/// Renders a stored version instead of the prompt's active text, e.g. to try
+/// out a draft.
+pub fn render_version(
+    prompt: &SystemPrompt,
+    version: &SystemPromptVersion,
+    values: &[(&str, &str)],
+) -> Result<RenderedPrompt, PromptError> {
+    let candidate = SystemPrompt {
+        prompt_text: version.prompt_text.clone(),
+        variables: version.variables.clone(),
+        version: version.version,
+        ..prompt.clone()
+    };
+
+    render(&candidate, values)
+}
+
+/// Appends a version to a prompt's history, numbered after the latest one.
+pub async fn record_version(
+    tx: &mut sqlx::PgConnection,
+    prompt_id: Uuid,
+    prompt_text: &str,
+    variables: &serde_json::Value,
+    status: &str,
+    note: Option<String>,
+    created_by: Uuid,
+) -> Result<SystemPromptVersion, sqlx::Error> {
+    // Serialise concurrent edits of the same prompt
+    sqlx::query("SELECT id FROM system_prompts WHERE id = $1 FOR UPDATE")
+        .bind(prompt_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query_as::<_, SystemPromptVersion>(
+        "INSERT INTO system_prompt_versions (prompt_id, version, prompt_text, variables, status, note, created_by, published_at)
+         SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4, $5, $6,
+                CASE WHEN $4 = 'published' THEN NOW() END
+         FROM system_prompt_versions WHERE prompt_id = $1
+         RETURNING *"
+    )
+    .bind(prompt_id)
+    .bind(prompt_text)
+    .bind(variables)
+    .bind(status)
+    .bind(note)
+    .bind(created_by)
+    .fetch_one(&mut *tx)
+    .await
+}
+
+/// Makes a stored version the prompt's active text. Promoting an older
+/// version is how a prompt is rolled back.
+pub async fn promote(pool: &PgPool, prompt_id: Uuid, version: i32) -> Result<Option<SystemPrompt>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let promoted = sqlx::query_as::<_, SystemPromptVersion>(
+        "UPDATE system_prompt_versions
+         SET status = 'published', published_at = COALESCE(published_at, NOW())
+         WHERE prompt_id = $1 AND version = $2
+         RETURNING *"
+    )
+    .bind(prompt_id)
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(promoted) = promoted else {
+        return Ok(None);
+    };
+
+    let prompt = sqlx::query_as::<_, SystemPrompt>(
+        "UPDATE system_prompts SET
+            prompt_text = $1,
+            variables = $2,
+            version = $3,
+            updated_at = NOW()
+         WHERE id = $4
+         RETURNING *"
+    )
+    .bind(&promoted.prompt_text)
+    .bind(&promoted.variables)
+    .bind(promoted.version)
+    .bind(prompt_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(prompt))
+}
+
+/// Line-based diff (longest common subsequence) from `old` to `new`.
+pub fn diff_lines(old: &str, new: &str) -> Vec<PromptDiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] = common lines between old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |op, text: &str| PromptDiffLine { op, text: text.to_string() };
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(line("equal", old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push(line("delete", old[i]));
+            i += 1;
+        } else {
+            diff.push(line("insert", new[j]));
+            j += 1;
+        }
+    }
+
+    diff.extend(old[i..].iter().map(|text| line("delete", text)));
+    diff.extend(new[j..].iter().map(|text| line("insert", text)));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(text: &str, variables: serde_json::Value) -> SystemPrompt {
+        SystemPrompt {
+            id: Uuid::new_v4(),
+            name: "Test".to_string(),
+            prompt_type: "summarization".to_string(),
+            prompt_text: text.to_string(),
+            variables,
+            is_active: true,
+            version: 1,
+            created_by: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn ops(diff: &[PromptDiffLine]) -> Vec<(&str, &str)> {
+        diff.iter().map(|line| (line.op, line.text.as_str())).collect()
+    }
+
+    #[test]
+    fn renders_declared_variables() {
+        let prompt = prompt("Ringkas: {{ text }} ({{lang}})", serde_json::json!({"text": "", "lang": ""}));
+        let rendered = render(&prompt, &[("text", "jalan rusak"), ("lang", "id")]).unwrap();
+
+        assert_eq!(rendered.text, "Ringkas: jalan rusak (id)");
+        assert_eq!(rendered.source.version, 1);
+    }
+
+    #[test]
+    fn rejects_missing_and_undeclared_variables() {
+        let declared = prompt("Ringkas: {{text}}", serde_json::json!({"text": ""}));
+        let missing = render(&declared, &[]).err().unwrap();
+        assert!(missing.0.contains("requires variable 'text'"));
+
+        let undeclared = prompt("Ringkas: {{text}}", serde_json::json!({}));
+        let error = render(&undeclared, &[("text", "x")]).err().unwrap();
+        assert!(error.0.contains("undeclared variable 'text'"));
+
+        let unclosed = prompt("Ringkas: {{text", serde_json::json!({"text": ""}));
+        assert!(render(&unclosed, &[("text", "x")]).is_err());
+    }
+
+    #[test]
+    fn values_cannot_inject_placeholders() {
+        let prompt = prompt("A: {{a}} B: {{b}}", serde_json::json!({"a": "", "b": ""}));
+        let rendered = render(&prompt, &[("a", "{{b}}\u{7}"), ("b", "ok")]).unwrap();
+
+        assert_eq!(rendered.text, "A: { {b} } B: ok");
+        assert_eq!(escape("baris\n\ttab\u{0}"), "baris\n\ttab");
+    }
+
+    #[test]
+    fn diffs_against_empty_text() {
+        assert!(diff_lines("", "").is_empty());
+        assert_eq!(ops(&diff_lines("", "a\nb")), vec![("insert", "a"), ("insert", "b")]);
+        assert_eq!(ops(&diff_lines("a\nb", "")), vec![("delete", "a"), ("delete", "b")]);
+    }
+
+    #[test]
+    fn diffs_reordered_lines() {
+        let diff = diff_lines("a\nb\nc", "c\na\nb");
+
+        assert_eq!(ops(&diff), vec![("insert", "c"), ("equal", "a"), ("equal", "b"), ("delete", "c")]);
+    }
+}
@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use super::{Completion, CompletionRequest, DeltaSink, LlmError, LlmMessage, LlmProvider, SseBuffer};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const API_VERSION: &str = "2023-06-01";
+// The Messages API requires max_tokens on every request
+const DEFAULT_MAX_TOKENS: u32 = 2000;
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    messages: Vec<LlmMessage>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    model: Option<String>,
+    content: Vec<ContentBlock>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+}
+
+// Only the stream events that carry text or usage are decoded
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: StreamDelta },
+    MessageDelta { usage: Option<Usage> },
+    MessageStop,
+    Error { error: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    model: Option<String>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+pub struct AnthropicProvider {
+    client: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(api_key: String, base_url: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            model,
+        }
+    }
+
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, LlmError> {
+        // System prompts go in their own field; the API rejects a "system" role.
+        let messages = request
+            .messages
+            .iter()
+            .filter(|m| m.role != "system")
+            .cloned()
+            .collect();
+
+        let response = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&MessagesRequest {
+                model: &self.model,
+                system: request.system.as_deref(),
+                messages,
+                max_tokens: request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+                temperature: request.temperature,
+                stream,
+            })
+            .send()
+            .await
+            .map_err(|e| LlmError::unavailable(format!("HTTP request failed: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text: String = response.text().await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(LlmError::from_status(status, format!("Anthropic API error: {}", error_text)));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError> {
+        let response = self.send(request, false).await?;
+
+        let result: MessagesResponse = response.json().await
+            .map_err(|e| LlmError::new(format!("Failed to parse response: {}", e)))?;
+
+        let content = result
+            .content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect::<Vec<_>>()
+            .join("");
+
+        Ok(Completion {
+            content,
+            model: result.model.unwrap_or_else(|| self.model.clone()),
+            prompt_tokens: result.usage.as_ref().and_then(|u| u.input_tokens),
+            completion_tokens: result.usage.as_ref().and_then(|u| u.output_tokens),
+            provider: None,
+            api_key_id: None,
+            failovers: vec![],
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        let mut response = self.send(request, true).await?;
+        let mut sse = SseBuffer::default();
+        let mut completion = Completion {
+            content: String::new(),
+            model: self.model.clone(),
+            prompt_tokens: None,
+            completion_tokens: None,
+            provider: None,
+            api_key_id: None,
+            failovers: vec![],
+        };
+
+        'read: while let Some(chunk) = response.chunk().await
+            .map_err(|e| LlmError::unavailable(format!("Stream interrupted: {}", e)))?
+        {
+            for data in sse.push(&chunk) {
+                let event: StreamEvent = serde_json::from_str(&data)
+                    .map_err(|e| LlmError::new(format!("Failed to parse stream event: {}", e)))?;
+
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        if let Some(model) = message.model {
+                            completion.model = model;
+                        }
+                        completion.prompt_tokens = message.usage.and_then(|u| u.input_tokens);
+                    }
+                    StreamEvent::ContentBlockDelta { delta } => {
+                        let Some(text) = delta.text.filter(|t| !t.is_empty()) else {
+                            continue;
+                        };
+                        completion.content.push_str(&text);
+                        if !on_delta(&text) {
+                            break 'read;
+                        }
+                    }
+                    StreamEvent::MessageDelta { usage } => {
+                        completion.completion_tokens = usage.and_then(|u| u.output_tokens);
+                    }
+                    StreamEvent::MessageStop => break 'read,
+                    StreamEvent::Error { error } => {
+                        // Mid-stream errors are overloads and other server-side failures
+                        return Err(LlmError::unavailable(format!("Anthropic stream error: {}", error)));
+                    }
+                    StreamEvent::Other => {}
+                }
+            }
+        }
+
+        Ok(completion)
+    }
+}
@@ -0,0 +1,341 @@
+//! Envelope encryption for `api_keys` and other secrets stored in the
+//! database: every secret is sealed with its own random data key, and the
+//! data key is sealed with the master key from config. Rotating the master
+//! key only re-seals the data keys.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::ApiKey;
+
+const KEY_LEN: usize = 32;
+const HINT_CHARS: usize = 4;
+
+#[derive(Debug)]
+pub struct CryptoError(pub String);
+
+impl std::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+pub struct MasterKey {
+    key: [u8; KEY_LEN],
+    /// Fingerprint stored next to what the key sealed
+    id: String,
+}
+
+impl MasterKey {
+    /// Parses a base64-encoded 256-bit key.
+    pub fn parse(encoded: &str) -> Result<Self, CryptoError> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| CryptoError(format!("Encryption key is not valid base64: {}", e)))?;
+        let key: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| CryptoError(format!("Encryption key must be {} bytes", KEY_LEN)))?;
+
+        let id = digest(&SHA256, &key).as_ref()[..4]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Ok(Self { key, id })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// A secret as stored in the database
+pub struct Sealed {
+    pub ciphertext: String,
+    pub wrapped_data_key: String,
+    pub kek_id: String,
+}
+
+/// An API key as stored in the database
+pub struct SealedKey {
+    pub ciphertext: String,
+    pub wrapped_data_key: String,
+    pub kek_id: String,
+    pub hint: String,
+}
+
+fn seal(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<String, CryptoError> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| CryptoError("Invalid key".to_string()))?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| CryptoError("No randomness available".to_string()))?;
+
+    let mut sealed = plaintext.to_vec();
+    LessSafeKey::new(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut sealed)
+        .map_err(|_| CryptoError("Encryption failed".to_string()))?;
+
+    Ok(STANDARD.encode([nonce.as_slice(), &sealed].concat()))
+}
+
+fn open(key: &[u8], encoded: &str, aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let data = STANDARD
+        .decode(encoded)
+        .map_err(|_| CryptoError("Ciphertext is not valid base64".to_string()))?;
+    if data.len() < NONCE_LEN {
+        return Err(CryptoError("Ciphertext is too short".to_string()));
+    }
+
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| CryptoError("Invalid nonce".to_string()))?;
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| CryptoError("Invalid key".to_string()))?;
+
+    let mut sealed = sealed.to_vec();
+    let plaintext = LessSafeKey::new(key)
+        .open_in_place(nonce, Aad::from(aad), &mut sealed)
+        .map_err(|_| CryptoError("Decryption failed".to_string()))?;
+
+    Ok(plaintext.to_vec())
+}
+
+fn unwrap_data_key(master: &MasterKey, wrapped: &str, kek_id: &str) -> Result<Vec<u8>, CryptoError> {
+    if kek_id != master.id {
+        return Err(CryptoError(format!(
+            "Sealed with master key {}, configured key is {}",
+            kek_id, master.id
+        )));
+    }
+
+    open(&master.key, wrapped, kek_id.as_bytes())
+}
+
+/// Re-seals a data key sealed under `current` with `new`. A data key already
+/// under `new` is returned as it is.
+pub fn rewrap(current: &MasterKey, new: &MasterKey, wrapped: &str, kek_id: &str) -> Result<String, CryptoError> {
+    if kek_id == new.id {
+        return Ok(wrapped.to_string());
+    }
+
+    let data_key = unwrap_data_key(current, wrapped, kek_id)?;
+
+    seal(&new.key, &data_key, new.id.as_bytes())
+}
+
+/// Seals a secret for the row `row_id`; the ciphertext only opens for that row.
+pub fn seal_secret(master: &MasterKey, row_id: Uuid, secret: &str) -> Result<Sealed, CryptoError> {
+    let mut data_key = [0u8; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut data_key)
+        .map_err(|_| CryptoError("No randomness available".to_string()))?;
+
+    Ok(Sealed {
+        ciphertext: seal(&data_key, secret.as_bytes(), row_id.as_bytes())?,
+        wrapped_data_key: seal(&master.key, &data_key, master.id.as_bytes())?,
+        kek_id: master.id.clone(),
+    })
+}
+
+pub fn open_secret(master: &MasterKey, row_id: Uuid, sealed: &Sealed) -> Result<String, CryptoError> {
+    let data_key = unwrap_data_key(master, &sealed.wrapped_data_key, &sealed.kek_id)?;
+    let plaintext = open(&data_key, &sealed.ciphertext, row_id.as_bytes())?;
+
+    String::from_utf8(plaintext).map_err(|_| CryptoError("Secret is not valid UTF-8".to_string()))
+}
+
+/// Deterministic keyed hash of `data`, hex-encoded. The key is derived from
+/// the master key and `purpose`, so hashes change when the master key does.
+pub fn keyed_hash(master: &MasterKey, purpose: &str, data: &[u8]) -> String {
+    let derived = digest(&SHA256, &[purpose.as_bytes(), &master.key].concat());
+    let key = hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref());
+
+    hmac::sign(&key, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Seals a new API key for the row `key_id`; the ciphertext only opens for
+/// that row.
+pub fn encrypt(master: &MasterKey, key_id: Uuid, api_key: &str) -> Result<SealedKey, CryptoError> {
+    let chars: Vec<char> = api_key.chars().collect();
+    let suffix: String = chars[chars.len().saturating_sub(HINT_CHARS)..].iter().collect();
+
+    let sealed = seal_secret(master, key_id, api_key)?;
+
+    Ok(SealedKey {
+        ciphertext: sealed.ciphertext,
+        wrapped_data_key: sealed.wrapped_data_key,
+        kek_id: sealed.kek_id,
+        hint: format!("****{}", suffix),
+    })
+}
+
+pub(super) fn decrypt(master: &MasterKey, key: &ApiKey) -> Result<String, CryptoError> {
+    let (Some(ciphertext), Some(wrapped), Some(kek_id)) = (&key.api_key_ciphertext, &key.wrapped_data_key, &key.kek_id) else {
+        return Err(CryptoError(format!("API key {} is not encrypted", key.name)));
+    };
+
+    let data_key = unwrap_data_key(master, wrapped, kek_id)?;
+    let plaintext = open(&data_key, ciphertext, key.id.as_bytes())?;
+
+    String::from_utf8(plaintext).map_err(|_| CryptoError("API key is not valid UTF-8".to_string()))
+}
+
+/// Encrypts rows still holding a plaintext `api_key` from before encryption
+/// was introduced, clearing the plaintext. Run at startup.
+pub async fn encrypt_plaintext_keys(pool: &PgPool, master: &MasterKey) -> Result<u64, Box<dyn std::error::Error>> {
+    let rows: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, api_key FROM api_keys WHERE api_key IS NOT NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (id, api_key) in &rows {
+        let sealed = encrypt(master, *id, api_key)?;
+
+        sqlx::query(
+            "UPDATE api_keys SET
+                api_key = NULL,
+                api_key_ciphertext = $2,
+                wrapped_data_key = $3,
+                kek_id = $4,
+                api_key_hint = $5
+             WHERE id = $1"
+        )
+        .bind(id)
+        .bind(&sealed.ciphertext)
+        .bind(&sealed.wrapped_data_key)
+        .bind(&sealed.kek_id)
+        .bind(&sealed.hint)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(rows.len() as u64)
+}
+
+/// Re-seals every data key under `new`. Rows already under `new` are left
+/// alone, so an interrupted rotation can simply be run again. All rows are
+/// rotated in one transaction.
+pub async fn rotate(pool: &PgPool, current: &MasterKey, new: &MasterKey) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(Uuid, String, String)> = sqlx::query_as(
+        "SELECT id, wrapped_data_key, kek_id FROM api_keys
+         WHERE wrapped_data_key IS NOT NULL AND kek_id <> $1
+         FOR UPDATE"
+    )
+    .bind(&new.id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (id, wrapped, kek_id) in &rows {
+        sqlx::query("UPDATE api_keys SET wrapped_data_key = $2, kek_id = $3 WHERE id = $1")
+            .bind(id)
+            .bind(rewrap(current, new, wrapped, kek_id)?)
+            .bind(&new.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(rows.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn master(byte: u8) -> MasterKey {
+        MasterKey::parse(&STANDARD.encode([byte; KEY_LEN])).unwrap()
+    }
+
+    fn api_key(id: Uuid, sealed: SealedKey) -> ApiKey {
+        let now = chrono::Utc::now();
+        ApiKey {
+            id,
+            name: "test".to_string(),
+            provider: "openai".to_string(),
+            base_url: None,
+            is_active: true,
+            usage_count: 0,
+            last_used_at: None,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+            priority: 0,
+            daily_token_quota: None,
+            daily_request_quota: None,
+            consecutive_failures: 0,
+            cooldown_until: None,
+            last_error: None,
+            last_error_at: None,
+            api_key_ciphertext: Some(sealed.ciphertext),
+            wrapped_data_key: Some(sealed.wrapped_data_key),
+            kek_id: Some(sealed.kek_id),
+            api_key_hint: Some(sealed.hint),
+        }
+    }
+
+    #[test]
+    fn parse_accepts_only_32_byte_keys() {
+        assert!(MasterKey::parse(&STANDARD.encode([7u8; KEY_LEN])).is_ok());
+        assert!(MasterKey::parse(&STANDARD.encode([7u8; 16])).is_err());
+        assert!(MasterKey::parse(&STANDARD.encode([7u8; 33])).is_err());
+        assert!(MasterKey::parse("not base64!").is_err());
+        assert_ne!(master(1).id(), master(2).id());
+    }
+
+    #[test]
+    fn secrets_open_only_for_their_row_and_master_key() {
+        let row = Uuid::new_v4();
+        let sealed = seal_secret(&master(1), row, "Budi Santoso").unwrap();
+
+        assert_eq!(open_secret(&master(1), row, &sealed).unwrap(), "Budi Santoso");
+        assert!(open_secret(&master(2), row, &sealed).is_err());
+        assert!(open_secret(&master(1), Uuid::new_v4(), &sealed).is_err());
+
+        // A key with a forged id still cannot unwrap the data key
+        let forged = MasterKey { key: [2u8; KEY_LEN], id: master(1).id };
+        assert!(open_secret(&forged, row, &sealed).is_err());
+    }
+
+    #[test]
+    fn api_keys_round_trip_and_keep_a_hint() {
+        let id = Uuid::new_v4();
+        let sealed = encrypt(&master(1), id, "sk-test-a1b2").unwrap();
+        assert_eq!(sealed.hint, "****a1b2");
+
+        let key = api_key(id, sealed);
+        assert_eq!(decrypt(&master(1), &key).unwrap(), "sk-test-a1b2");
+        assert!(decrypt(&master(2), &key).is_err());
+
+        let moved = ApiKey { id: Uuid::new_v4(), ..key };
+        assert!(decrypt(&master(1), &moved).is_err());
+    }
+
+    #[test]
+    fn rewrap_moves_secrets_to_the_new_key_and_can_run_again() {
+        let (current, new) = (master(1), master(2));
+        let row = Uuid::new_v4();
+        let sealed = seal_secret(&current, row, "rahasia").unwrap();
+
+        let wrapped = rewrap(&current, &new, &sealed.wrapped_data_key, &sealed.kek_id).unwrap();
+        let rotated = Sealed { ciphertext: sealed.ciphertext, wrapped_data_key: wrapped, kek_id: new.id.clone() };
+        assert_eq!(open_secret(&new, row, &rotated).unwrap(), "rahasia");
+        assert!(open_secret(&current, row, &rotated).is_err());
+
+        let again = rewrap(&current, &new, &rotated.wrapped_data_key, &rotated.kek_id).unwrap();
+        assert_eq!(again, rotated.wrapped_data_key);
+    }
+}
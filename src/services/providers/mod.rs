@@ -0,0 +1,271 @@
+mod anthropic;
+pub mod crypto;
+mod fake;
+mod openai;
+mod openrouter;
+mod redacting;
+mod router;
+
+pub use anthropic::AnthropicProvider;
+pub use fake::FakeProvider;
+pub use openai::OpenAiProvider;
+pub use openrouter::OpenRouterProvider;
+pub use redacting::RedactingProvider;
+pub use router::KeyRouter;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::config::LlmConfig;
+use crate::models::ApiKey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmErrorKind {
+    /// The request or the answer is at fault; another key will not help
+    Invalid,
+    /// The API answered 429
+    RateLimited,
+    /// The API refused the key: revoked, not allowed or out of credit
+    KeyRejected,
+    /// Network failure, timeout or server error
+    Unavailable,
+}
+
+#[derive(Debug)]
+pub struct LlmError {
+    pub message: String,
+    pub kind: LlmErrorKind,
+    /// Provider and key of the last call attempted, set by [`KeyRouter`]
+    pub provider: Option<&'static str>,
+    pub api_key_id: Option<Uuid>,
+    /// Keys [`KeyRouter`] failed over from before this error
+    pub failovers: Vec<FailedAttempt>,
+}
+
+impl LlmError {
+    fn with_kind(message: impl Into<String>, kind: LlmErrorKind) -> Self {
+        Self {
+            message: message.into(),
+            kind,
+            provider: None,
+            api_key_id: None,
+            failovers: vec![],
+        }
+    }
+
+    pub fn new(message: impl Into<String>) -> Self {
+        Self::with_kind(message, LlmErrorKind::Invalid)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::with_kind(message, LlmErrorKind::Unavailable)
+    }
+
+    pub fn from_status(status: reqwest::StatusCode, message: impl Into<String>) -> Self {
+        let kind = match status.as_u16() {
+            429 => LlmErrorKind::RateLimited,
+            // OpenRouter answers 402 once the key's credits run out
+            401..=403 => LlmErrorKind::KeyRejected,
+            408 | 500..=599 => LlmErrorKind::Unavailable,
+            _ => LlmErrorKind::Invalid,
+        };
+
+        Self::with_kind(message, kind)
+    }
+
+    /// Names the provider and key the failed call went to
+    pub fn served_by(self, provider: &'static str, api_key_id: Uuid) -> Self {
+        Self {
+            provider: Some(provider),
+            api_key_id: Some(api_key_id),
+            ..self
+        }
+    }
+
+    /// Whether the same request may succeed on another key
+    pub fn is_retryable(&self) -> bool {
+        self.kind != LlmErrorKind::Invalid
+    }
+}
+
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompletionRequest {
+    pub system: Option<String>,
+    pub messages: Vec<LlmMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Names to redact besides the patterns, e.g. persons found by NER
+    pub sensitive_terms: Vec<String>,
+    /// The prompt the request was rendered from, e.g. `report_extraction`
+    pub prompt_type: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub content: String,
+    pub model: String,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    /// Provider and key that served the call, set by [`KeyRouter`]
+    pub provider: Option<&'static str>,
+    pub api_key_id: Option<Uuid>,
+    /// Keys [`KeyRouter`] failed over from before one served the call
+    pub failovers: Vec<FailedAttempt>,
+}
+
+/// A call to one key that failed before [`KeyRouter`] moved on to the next;
+/// it gets its own row in the `llm_calls` ledger.
+#[derive(Debug, Clone)]
+pub struct FailedAttempt {
+    pub provider: &'static str,
+    pub api_key_id: Uuid,
+    pub model: String,
+    pub latency_ms: i32,
+    pub error: String,
+}
+
+/// Receives streamed text as it arrives; returning `false` stops the stream.
+pub type DeltaSink<'a> = &'a mut (dyn FnMut(&str) -> bool + Send);
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Matches `api_keys.provider`
+    fn name(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError>;
+
+    /// Streams the completion through `on_delta`. The returned completion holds
+    /// whatever text was produced, which is partial if the sink stopped early.
+    /// Providers without streaming support deliver the whole answer at once.
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        let completion = self.complete(request).await?;
+        on_delta(&completion.content);
+        Ok(completion)
+    }
+}
+
+/// Splits a server-sent events body into `data:` payloads. Chunks may end
+/// mid-line (or mid-character), so incomplete lines are kept until the next push.
+#[derive(Default)]
+struct SseBuffer {
+    pending: Vec<u8>,
+}
+
+impl SseBuffer {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut payloads = vec![];
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                payloads.push(data.trim_start().to_string());
+            }
+        }
+
+        payloads
+    }
+}
+
+/// Builds the provider for an `api_keys` row, using the model configured for
+/// that provider. This is the only place a stored key is decrypted.
+pub fn from_api_key(key: &ApiKey, config: &LlmConfig) -> Result<Arc<dyn LlmProvider>, LlmError> {
+    let base_url = key.base_url.clone();
+    let api_key = crypto::MasterKey::parse(&config.api_key_encryption_key)
+        .and_then(|master| crypto::decrypt(&master, key))
+        .map_err(|e| LlmError::new(format!("Cannot decrypt API key {}: {}", key.name, e)))?;
+
+    let provider: Arc<dyn LlmProvider> = match key.provider.as_str() {
+        "openrouter" => Arc::new(OpenRouterProvider::new(
+            api_key,
+            base_url,
+            config.openrouter_model.clone(),
+        )),
+        "openai" => Arc::new(OpenAiProvider::new(
+            api_key,
+            base_url.unwrap_or_else(|| openai::DEFAULT_BASE_URL.to_string()),
+            config.openai_model.clone(),
+        )),
+        "anthropic" => Arc::new(AnthropicProvider::new(
+            api_key,
+            base_url,
+            config.anthropic_model.clone(),
+        )),
+        // Any OpenAI-compatible endpoint, e.g. a self-hosted model server
+        "custom" => Arc::new(OpenAiProvider::new(
+            api_key,
+            base_url.ok_or_else(|| LlmError::new(format!("API key {} has no base_url", key.name)))?,
+            config.custom_model.clone(),
+        )),
+        other => return Err(LlmError::new(format!("Unknown LLM provider: {}", other))),
+    };
+
+    Ok(provider)
+}
+
+/// Provider for the app's own calls: the key router over all active API
+/// keys, or the fake provider when enabled, redacting personal data per
+/// `config.redaction`. Returns `None` when no key is configured at all.
+pub async fn active(pool: &PgPool, config: &LlmConfig) -> Result<Option<Arc<dyn LlmProvider>>, LlmError> {
+    let provider: Arc<dyn LlmProvider> = if config.fake_provider {
+        Arc::new(FakeProvider)
+    } else {
+        match routed(pool, config).await? {
+            Some(router) => router,
+            None => return Ok(None),
+        }
+    };
+
+    if config.redaction.is_empty() {
+        return Ok(Some(provider));
+    }
+
+    Ok(Some(Arc::new(RedactingProvider::new(provider, config.redaction.clone()))))
+}
+
+/// The key router, or `None` when no key is configured
+async fn routed(pool: &PgPool, config: &LlmConfig) -> Result<Option<Arc<dyn LlmProvider>>, LlmError> {
+
+    let keys = router::candidates(pool)
+        .await
+        .map_err(|e| LlmError::new(format!("Failed to load API keys: {}", e)))?;
+
+    // Keys that exist but are all cooling down still get a router, whose
+    // calls then fail with a clear error.
+    if keys.is_empty() {
+        let configured: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM api_keys WHERE is_active = true)")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| LlmError::new(format!("Failed to load API keys: {}", e)))?;
+
+        if !configured {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(Arc::new(KeyRouter::new(pool.clone(), config.clone(), keys))))
+}
@@ -0,0 +1,237 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use super::{Completion, CompletionRequest, DeltaSink, LlmError, LlmMessage, LlmProvider, SseBuffer};
+
+pub(super) const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<LlmMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    model: Option<String>,
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: LlmMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    delta: Delta,
+}
+
+#[derive(Debug, Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+}
+
+fn messages_for(request: &CompletionRequest) -> Vec<LlmMessage> {
+    let mut messages = vec![];
+
+    if let Some(system) = &request.system {
+        messages.push(LlmMessage {
+            role: "system".to_string(),
+            content: system.clone(),
+        });
+    }
+
+    messages.extend(request.messages.iter().cloned());
+    messages
+}
+
+async fn send(
+    builder: reqwest::RequestBuilder,
+    body: &ChatCompletionRequest<'_>,
+) -> Result<reqwest::Response, LlmError> {
+    let response = builder
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| LlmError::unavailable(format!("HTTP request failed: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text: String = response.text().await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(LlmError::from_status(status, format!("Chat completion API error: {}", error_text)));
+    }
+
+    Ok(response)
+}
+
+/// Calls an OpenAI-compatible `/chat/completions` endpoint. Shared with the
+/// OpenRouter provider, which only adds its attribution headers.
+pub(super) async fn chat_completions(
+    builder: reqwest::RequestBuilder,
+    model: &str,
+    request: &CompletionRequest,
+) -> Result<Completion, LlmError> {
+    let body = ChatCompletionRequest {
+        model,
+        messages: messages_for(request),
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        stream: false,
+        stream_options: None,
+    };
+
+    let result: ChatCompletionResponse = send(builder, &body).await?.json().await
+        .map_err(|e| LlmError::new(format!("Failed to parse response: {}", e)))?;
+
+    let content = result
+        .choices
+        .first()
+        .map(|c| c.message.content.clone())
+        .ok_or_else(|| LlmError::new("Response contained no choices"))?;
+
+    Ok(Completion {
+        content,
+        model: result.model.unwrap_or_else(|| model.to_string()),
+        prompt_tokens: result.usage.as_ref().and_then(|u| u.prompt_tokens),
+        completion_tokens: result.usage.as_ref().and_then(|u| u.completion_tokens),
+        provider: None,
+        api_key_id: None,
+        failovers: vec![],
+    })
+}
+
+/// Streaming variant of [`chat_completions`]; usage arrives in the final chunk.
+pub(super) async fn chat_completions_stream(
+    builder: reqwest::RequestBuilder,
+    model: &str,
+    request: &CompletionRequest,
+    on_delta: DeltaSink<'_>,
+) -> Result<Completion, LlmError> {
+    let body = ChatCompletionRequest {
+        model,
+        messages: messages_for(request),
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        stream: true,
+        stream_options: Some(serde_json::json!({ "include_usage": true })),
+    };
+
+    let mut response = send(builder, &body).await?;
+    let mut sse = SseBuffer::default();
+    let mut completion = Completion {
+        content: String::new(),
+        model: model.to_string(),
+        prompt_tokens: None,
+        completion_tokens: None,
+        provider: None,
+        api_key_id: None,
+        failovers: vec![],
+    };
+
+    'read: while let Some(chunk) = response.chunk().await
+        .map_err(|e| LlmError::unavailable(format!("Stream interrupted: {}", e)))?
+    {
+        for data in sse.push(&chunk) {
+            if data == "[DONE]" {
+                break 'read;
+            }
+
+            let chunk: ChatCompletionChunk = serde_json::from_str(&data)
+                .map_err(|e| LlmError::new(format!("Failed to parse stream chunk: {}", e)))?;
+
+            if let Some(model) = chunk.model {
+                completion.model = model;
+            }
+            if let Some(usage) = chunk.usage {
+                completion.prompt_tokens = usage.prompt_tokens;
+                completion.completion_tokens = usage.completion_tokens;
+            }
+
+            let delta = chunk
+                .choices
+                .into_iter()
+                .filter_map(|c| c.delta.content)
+                .collect::<String>();
+
+            if !delta.is_empty() {
+                completion.content.push_str(&delta);
+                if !on_delta(&delta) {
+                    break 'read;
+                }
+            }
+        }
+    }
+
+    Ok(completion)
+}
+
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: String, base_url: String, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            base_url,
+            model,
+        }
+    }
+
+    fn request(&self) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError> {
+        chat_completions(self.request(), &self.model, request).await
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        chat_completions_stream(self.request(), &self.model, request, on_delta).await
+    }
+}
@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use super::openai::{chat_completions, chat_completions_stream};
+use super::{Completion, CompletionRequest, DeltaSink, LlmError, LlmProvider};
+
+const DEFAULT_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+pub struct OpenRouterProvider {
+    client: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+impl OpenRouterProvider {
+    pub fn new(api_key: String, base_url: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            model,
+        }
+    }
+
+    fn request(&self) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("HTTP-Referer", "https://balungpisah.app")
+            .header("X-Title", "BalungPisah")
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenRouterProvider {
+    fn name(&self) -> &'static str {
+        "openrouter"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError> {
+        chat_completions(self.request(), &self.model, request).await
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        chat_completions_stream(self.request(), &self.model, request, on_delta).await
+    }
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use super::{Completion, CompletionRequest, DeltaSink, LlmError, LlmMessage, LlmProvider};
+use crate::services::redaction::{RedactionRules, Redactor, StreamRestorer};
+
+/// Wraps a provider so that personal data never leaves in a request. Each
+/// call gets its own placeholders; the answer comes back restored.
+pub struct RedactingProvider {
+    inner: Arc<dyn LlmProvider>,
+    rules: RedactionRules,
+}
+
+impl RedactingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, rules: RedactionRules) -> Self {
+        Self { inner, rules }
+    }
+
+    fn redact(&self, request: &CompletionRequest) -> (CompletionRequest, Redactor) {
+        let mut redactor = Redactor::new(self.rules.clone()).with_names(&request.sensitive_terms);
+
+        let redacted = CompletionRequest {
+            system: request.system.as_deref().map(|system| redactor.redact(system)),
+            messages: request
+                .messages
+                .iter()
+                .map(|m| LlmMessage {
+                    role: m.role.clone(),
+                    content: redactor.redact(&m.content),
+                })
+                .collect(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            sensitive_terms: vec![],
+            prompt_type: request.prompt_type.clone(),
+        };
+
+        (redacted, redactor)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RedactingProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError> {
+        let (redacted, redactor) = self.redact(request);
+        let mut completion = self.inner.complete(&redacted).await?;
+        completion.content = redactor.restore(&completion.content);
+
+        Ok(completion)
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        let (redacted, redactor) = self.redact(request);
+        let mut restorer = StreamRestorer::new(&redactor);
+        let mut stopped = false;
+
+        let result = self
+            .inner
+            .stream(&redacted, &mut |delta: &str| {
+                let restored = restorer.push(delta);
+                stopped = !restored.is_empty() && !on_delta(&restored);
+                !stopped
+            })
+            .await;
+
+        // Text held back in case it was the start of a placeholder
+        let rest = restorer.finish();
+        if !stopped && !rest.is_empty() {
+            on_delta(&rest);
+        }
+
+        let mut completion = result?;
+        completion.content = redactor.restore(&completion.content);
+
+        Ok(completion)
+    }
+}
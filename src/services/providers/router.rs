@@ -0,0 +1,464 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::config::LlmConfig;
+use crate::models::ApiKey;
+use super::{from_api_key, Completion, CompletionRequest, DeltaSink, FailedAttempt, LlmError, LlmErrorKind, LlmProvider};
+
+// Cooldown doubles with every consecutive failure, up to the cap
+const RATE_LIMIT_COOLDOWN_SECS: f64 = 60.0;
+const UNAVAILABLE_COOLDOWN_SECS: f64 = 15.0;
+// A refused key stays refused until someone tops it up or replaces it
+const KEY_REJECTED_COOLDOWN_SECS: f64 = 300.0;
+const MAX_COOLDOWN_SECS: f64 = 900.0;
+
+// Keys with today's (WIB) request and token counts from the ledger
+const KEYS_WITH_USAGE: &str = "
+    SELECT k.*, today.requests, today.tokens FROM api_keys k
+    LEFT JOIN LATERAL (
+        SELECT COUNT(*) AS requests,
+               COALESCE(SUM(COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0)), 0)::BIGINT AS tokens
+        FROM llm_calls c
+        WHERE c.api_key_id = k.id
+        AND c.created_at >= DATE_TRUNC('day', NOW() AT TIME ZONE 'Asia/Jakarta') AT TIME ZONE 'Asia/Jakarta'
+    ) today ON true";
+
+#[derive(sqlx::FromRow)]
+struct KeyWithUsage {
+    #[sqlx(flatten)]
+    key: ApiKey,
+    requests: i64,
+    tokens: i64,
+}
+
+impl KeyWithUsage {
+    /// Active, not cooling down and under both daily quotas
+    fn usable(&self, now: DateTime<Utc>) -> bool {
+        self.key.is_active
+            && self.key.cooldown_until.is_none_or(|until| until <= now)
+            && self.key.daily_request_quota.is_none_or(|quota| self.requests < i64::from(quota))
+            && self.key.daily_token_quota.is_none_or(|quota| self.tokens < quota)
+    }
+}
+
+/// Usable keys in the order to try them: by priority, healthiest first, then
+/// least recently used so keys of equal priority take turns.
+fn in_turn(keys: Vec<KeyWithUsage>, now: DateTime<Utc>) -> Vec<ApiKey> {
+    let mut keys: Vec<ApiKey> = keys
+        .into_iter()
+        .filter(|key| key.usable(now))
+        .map(|key| key.key)
+        .collect();
+
+    // Never-used keys (None) sort first
+    keys.sort_by_key(|key| (key.priority, key.consecutive_failures, key.last_used_at));
+    keys
+}
+
+/// Active keys that may take a call right now, in the order to try them.
+/// Keys cooling down or over today's quota are left out.
+pub async fn candidates(pool: &PgPool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let keys = sqlx::query_as::<_, KeyWithUsage>(&format!("{} WHERE k.is_active = true", KEYS_WITH_USAGE))
+        .fetch_all(pool)
+        .await?;
+
+    Ok(in_turn(keys, Utc::now()))
+}
+
+type Connect = fn(&ApiKey, &LlmConfig) -> Result<Arc<dyn LlmProvider>, LlmError>;
+
+/// Spreads calls over several API keys and fails over to the next key when
+/// one is rate limited, refused or unavailable. Each completion names the
+/// provider and key that served it.
+pub struct KeyRouter {
+    pool: PgPool,
+    config: LlmConfig,
+    keys: Vec<ApiKey>,
+    connect: Connect,
+}
+
+impl KeyRouter {
+    pub fn new(pool: PgPool, config: LlmConfig, keys: Vec<ApiKey>) -> Self {
+        Self { pool, config, keys, connect: from_api_key }
+    }
+
+    /// Checks the key again right before a call, as other calls may have
+    /// used up its quota or put it in cooldown since the router was built.
+    /// A failed check lets the call go ahead.
+    async fn still_usable(&self, key: &ApiKey) -> bool {
+        let current = sqlx::query_as::<_, KeyWithUsage>(&format!("{} WHERE k.id = $1", KEYS_WITH_USAGE))
+            .bind(key.id)
+            .fetch_optional(&self.pool)
+            .await;
+
+        match current {
+            Ok(current) => current.is_some_and(|current| current.usable(Utc::now())),
+            Err(e) => {
+                tracing::warn!("Failed to check quota of API key {}: {}", key.name, e);
+                true
+            }
+        }
+    }
+
+    async fn mark_failed(&self, key: &ApiKey, error: &LlmError) {
+        let base = match error.kind {
+            LlmErrorKind::RateLimited => RATE_LIMIT_COOLDOWN_SECS,
+            LlmErrorKind::KeyRejected => KEY_REJECTED_COOLDOWN_SECS,
+            _ => UNAVAILABLE_COOLDOWN_SECS,
+        };
+
+        tracing::warn!("API key {} failed, failing over: {}", key.name, error);
+
+        let updated = sqlx::query(
+            "UPDATE api_keys SET
+                consecutive_failures = consecutive_failures + 1,
+                cooldown_until = NOW() + make_interval(secs => LEAST($2 * POWER(2, consecutive_failures), $3)),
+                last_error = $4,
+                last_error_at = NOW()
+             WHERE id = $1"
+        )
+        .bind(key.id)
+        .bind(base)
+        .bind(MAX_COOLDOWN_SECS)
+        .bind(&error.message)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = updated {
+            tracing::warn!("Failed to update health of API key {}: {}", key.name, e);
+        }
+    }
+
+    async fn mark_healthy(&self, key: &ApiKey) {
+        if key.consecutive_failures == 0 {
+            return;
+        }
+
+        let updated = sqlx::query(
+            "UPDATE api_keys SET consecutive_failures = 0, cooldown_until = NULL WHERE id = $1"
+        )
+        .bind(key.id)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = updated {
+            tracing::warn!("Failed to update health of API key {}: {}", key.name, e);
+        }
+    }
+
+    fn exhausted(mut failovers: Vec<FailedAttempt>, last_error: Option<LlmError>) -> LlmError {
+        match last_error {
+            Some(e) => {
+                // The last failure is the call's own row in the ledger
+                failovers.pop();
+                LlmError {
+                    message: format!("All API keys failed, last error: {}", e.message),
+                    failovers,
+                    ..e
+                }
+            }
+            None => LlmError::unavailable("No API key available: all are cooling down or over quota"),
+        }
+    }
+}
+
+fn failed_attempt(provider: &dyn LlmProvider, key: &ApiKey, started: Instant, error: &LlmError) -> FailedAttempt {
+    FailedAttempt {
+        provider: provider.name(),
+        api_key_id: key.id,
+        model: provider.model().to_string(),
+        latency_ms: started.elapsed().as_millis() as i32,
+        error: error.message.clone(),
+    }
+}
+
+#[async_trait]
+impl LlmProvider for KeyRouter {
+    fn name(&self) -> &'static str {
+        "router"
+    }
+
+    /// Model of the first key in line
+    fn model(&self) -> &str {
+        match self.keys.first().map(|key| key.provider.as_str()) {
+            Some("openrouter") => &self.config.openrouter_model,
+            Some("openai") => &self.config.openai_model,
+            Some("anthropic") => &self.config.anthropic_model,
+            Some("custom") => &self.config.custom_model,
+            _ => "none",
+        }
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, LlmError> {
+        let mut last_error = None;
+        let mut failovers = vec![];
+
+        for key in &self.keys {
+            if !self.still_usable(key).await {
+                continue;
+            }
+
+            let provider = match (self.connect)(key, &self.config) {
+                Ok(provider) => provider,
+                Err(e) => {
+                    tracing::warn!("Skipping API key {}: {}", key.name, e);
+                    continue;
+                }
+            };
+
+            let started = Instant::now();
+            match provider.complete(request).await.map_err(|e| e.served_by(provider.name(), key.id)) {
+                Ok(mut completion) => {
+                    self.mark_healthy(key).await;
+                    completion.provider = Some(provider.name());
+                    completion.api_key_id = Some(key.id);
+                    completion.failovers = failovers;
+                    return Ok(completion);
+                }
+                Err(e) if e.is_retryable() => {
+                    self.mark_failed(key, &e).await;
+                    failovers.push(failed_attempt(provider.as_ref(), key, started, &e));
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(LlmError { failovers, ..e }),
+            }
+        }
+
+        Err(Self::exhausted(failovers, last_error))
+    }
+
+    /// Fails over only while nothing has been streamed yet; after that the
+    /// partial answer is returned with the error.
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<Completion, LlmError> {
+        let mut last_error = None;
+        let mut failovers = vec![];
+
+        for key in &self.keys {
+            if !self.still_usable(key).await {
+                continue;
+            }
+
+            let provider = match (self.connect)(key, &self.config) {
+                Ok(provider) => provider,
+                Err(e) => {
+                    tracing::warn!("Skipping API key {}: {}", key.name, e);
+                    continue;
+                }
+            };
+
+            let started = Instant::now();
+            let mut delivered = false;
+            let result = provider.stream(request, &mut |delta: &str| {
+                delivered = true;
+                on_delta(delta)
+            }).await.map_err(|e| e.served_by(provider.name(), key.id));
+
+            match result {
+                Ok(mut completion) => {
+                    self.mark_healthy(key).await;
+                    completion.provider = Some(provider.name());
+                    completion.api_key_id = Some(key.id);
+                    completion.failovers = failovers;
+                    return Ok(completion);
+                }
+                Err(e) if e.is_retryable() => {
+                    self.mark_failed(key, &e).await;
+                    if delivered {
+                        return Err(LlmError { failovers, ..e });
+                    }
+                    failovers.push(failed_attempt(provider.as_ref(), key, started, &e));
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(LlmError { failovers, ..e }),
+            }
+        }
+
+        Err(Self::exhausted(failovers, last_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::redaction::RedactionRules;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    fn key(name: &str, priority: i32) -> ApiKey {
+        let now = Utc::now();
+        ApiKey {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            provider: "openai".to_string(),
+            base_url: None,
+            is_active: true,
+            usage_count: 0,
+            last_used_at: None,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+            priority,
+            daily_token_quota: None,
+            daily_request_quota: None,
+            consecutive_failures: 0,
+            cooldown_until: None,
+            last_error: None,
+            last_error_at: None,
+            api_key_ciphertext: None,
+            wrapped_data_key: None,
+            kek_id: None,
+            api_key_hint: None,
+        }
+    }
+
+    fn unused(key: ApiKey) -> KeyWithUsage {
+        KeyWithUsage { key, requests: 0, tokens: 0 }
+    }
+
+    fn names(keys: &[ApiKey]) -> Vec<&str> {
+        keys.iter().map(|key| key.name.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_by_priority_health_then_least_recently_used() {
+        let now = Utc::now();
+        let recent = ApiKey { last_used_at: Some(now), ..key("recent", 1) };
+        let older = ApiKey { last_used_at: Some(now - Duration::hours(1)), ..key("older", 1) };
+        let failing = ApiKey { consecutive_failures: 2, ..key("failing", 1) };
+        let keys = [recent, failing, key("backup", 2), older, key("fresh", 1)];
+
+        let ordered = in_turn(keys.into_iter().map(unused).collect(), now);
+
+        assert_eq!(names(&ordered), vec!["fresh", "older", "recent", "failing", "backup"]);
+    }
+
+    #[test]
+    fn skips_keys_cooling_down_inactive_or_over_quota() {
+        let now = Utc::now();
+        let cooling = ApiKey { cooldown_until: Some(now + Duration::seconds(30)), ..key("cooling", 0) };
+        let cooled = ApiKey { cooldown_until: Some(now - Duration::seconds(1)), ..key("cooled", 0) };
+        let inactive = ApiKey { is_active: false, ..key("inactive", 0) };
+        let requests = KeyWithUsage {
+            requests: 10,
+            ..unused(ApiKey { daily_request_quota: Some(10), ..key("requests", 0) })
+        };
+        let tokens = KeyWithUsage {
+            tokens: 5_000,
+            ..unused(ApiKey { daily_token_quota: Some(5_000), ..key("tokens", 0) })
+        };
+        let under = KeyWithUsage {
+            requests: 9,
+            tokens: 4_999,
+            ..unused(ApiKey { daily_request_quota: Some(10), daily_token_quota: Some(5_000), ..key("under", 1) })
+        };
+
+        let keys = vec![unused(cooling), unused(cooled), unused(inactive), requests, tokens, under];
+
+        assert_eq!(names(&in_turn(keys, now)), vec!["cooled", "under"]);
+    }
+
+    /// Answers per key name: "limited" is rate limited, "invalid" refuses the
+    /// request, anything else answers with its own name.
+    struct Stub(String);
+
+    #[async_trait]
+    impl LlmProvider for Stub {
+        fn name(&self) -> &'static str {
+            "openai"
+        }
+
+        fn model(&self) -> &str {
+            "stub-model"
+        }
+
+        async fn complete(&self, _request: &CompletionRequest) -> Result<Completion, LlmError> {
+            match self.0.as_str() {
+                "limited" => Err(LlmError::from_status(reqwest::StatusCode::TOO_MANY_REQUESTS, "slow down")),
+                "invalid" => Err(LlmError::new("bad request")),
+                name => Ok(Completion {
+                    content: name.to_string(),
+                    model: "stub-model".to_string(),
+                    prompt_tokens: None,
+                    completion_tokens: None,
+                    provider: None,
+                    api_key_id: None,
+                    failovers: vec![],
+                }),
+            }
+        }
+    }
+
+    fn stub(key: &ApiKey, _config: &LlmConfig) -> Result<Arc<dyn LlmProvider>, LlmError> {
+        Ok(Arc::new(Stub(key.name.clone())))
+    }
+
+    // Nothing listens on the pool, so health and quota bookkeeping fail fast
+    // and, as in production, never block the call.
+    fn router(keys: Vec<ApiKey>) -> KeyRouter {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(50))
+            .connect_lazy("postgres://localhost:1/none")
+            .unwrap();
+        let config = LlmConfig {
+            openrouter_model: String::new(),
+            openai_model: "stub-model".to_string(),
+            anthropic_model: String::new(),
+            custom_model: String::new(),
+            fake_provider: false,
+            structured_retries: 0,
+            api_key_encryption_key: String::new(),
+            redaction: RedactionRules::default(),
+        };
+
+        KeyRouter { connect: stub, ..KeyRouter::new(pool, config, keys) }
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_keeps_every_failed_attempt() {
+        let (limited, served) = (key("limited", 0), key("served", 1));
+        let completion = router(vec![limited.clone(), served.clone()])
+            .complete(&CompletionRequest::default())
+            .await
+            .unwrap();
+
+        assert_eq!(completion.content, "served");
+        assert_eq!(completion.api_key_id, Some(served.id));
+        assert_eq!(completion.failovers.len(), 1);
+        assert_eq!(completion.failovers[0].api_key_id, limited.id);
+        assert_eq!(completion.failovers[0].model, "stub-model");
+    }
+
+    #[tokio::test]
+    async fn exhausted_keys_report_the_last_error_and_earlier_attempts() {
+        let (first, last) = (key("limited", 0), key("limited", 1));
+        let error = router(vec![first.clone(), last.clone()])
+            .complete(&CompletionRequest::default())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind, LlmErrorKind::RateLimited);
+        assert_eq!(error.api_key_id, Some(last.id));
+        assert_eq!(error.failovers.iter().map(|f| f.api_key_id).collect::<Vec<_>>(), vec![first.id]);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_do_not_fail_over() {
+        let invalid = key("invalid", 0);
+        let error = router(vec![invalid.clone(), key("served", 1)])
+            .complete(&CompletionRequest::default())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind, LlmErrorKind::Invalid);
+        assert_eq!(error.api_key_id, Some(invalid.id));
+        assert!(error.failovers.is_empty());
+    }
+}
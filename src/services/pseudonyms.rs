@@ -0,0 +1,209 @@
+//! Pseudonymous reporting. A citizen reporting pseudonymously files the
+//! report as a stand-in `users` row (role `pseudonym`) without personal data.
+//! The link back to the citizen is never stored in the clear: a keyed hash
+//! of their user id lets them find their own reports, and an escrowed copy
+//! sealed with the master key is only opened for legal requests.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+use super::providers::crypto::{self, CryptoError, MasterKey, Sealed};
+
+const OWNER_HASH_PURPOSE: &str = "pseudonym-owner";
+
+#[derive(Debug)]
+pub struct PseudonymError(pub String);
+
+impl std::fmt::Display for PseudonymError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PseudonymError {}
+
+impl From<sqlx::Error> for PseudonymError {
+    fn from(e: sqlx::Error) -> Self {
+        PseudonymError(format!("Database error: {}", e))
+    }
+}
+
+impl From<CryptoError> for PseudonymError {
+    fn from(e: CryptoError) -> Self {
+        PseudonymError(e.0)
+    }
+}
+
+/// Whether a requested reporting mode is pseudonymous; absent means identified
+pub fn is_pseudonymous(anonymity: Option<&str>) -> Result<bool, PseudonymError> {
+    match anonymity.unwrap_or("identified") {
+        "identified" => Ok(false),
+        "pseudonymous" => Ok(true),
+        other => Err(PseudonymError(format!("Unknown reporting mode {}", other))),
+    }
+}
+
+/// The master key the escrow is sealed with
+pub fn master_key() -> Result<MasterKey, PseudonymError> {
+    let config = crate::config::Config::load()
+        .map_err(|e| PseudonymError(format!("Config error: {}", e)))?;
+
+    Ok(MasterKey::parse(&config.llm.api_key_encryption_key)?)
+}
+
+fn owner_hash(master: &MasterKey, user_id: Uuid) -> String {
+    crypto::keyed_hash(master, OWNER_HASH_PURPOSE, user_id.as_bytes())
+}
+
+/// The citizen's pseudonym, if they ever reported pseudonymously
+pub async fn find(pool: &PgPool, master: &MasterKey, user_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar("SELECT user_id FROM pseudonyms WHERE owner_hash = $1")
+        .bind(owner_hash(master, user_id))
+        .fetch_optional(pool)
+        .await
+}
+
+/// The citizen's pseudonym, created on first use
+pub async fn find_or_create(pool: &PgPool, master: &MasterKey, user_id: Uuid) -> Result<Uuid, PseudonymError> {
+    if let Some(pseudonym_id) = find(pool, master, user_id).await? {
+        return Ok(pseudonym_id);
+    }
+
+    let pseudonym_id = Uuid::new_v4();
+    let escrow = crypto::seal_secret(master, pseudonym_id, &user_id.to_string())?;
+    let handle = format!("warga-{}", &pseudonym_id.simple().to_string()[..8]);
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "INSERT INTO users (id, logto_user_id, username, role)
+         VALUES ($1, 'pseudonym:' || $1::text, $2, 'pseudonym')"
+    )
+    .bind(pseudonym_id)
+    .bind(handle)
+    .execute(&mut *tx)
+    .await?;
+
+    let created = sqlx::query(
+        "INSERT INTO pseudonyms (user_id, owner_hash, escrow_ciphertext, wrapped_data_key, kek_id)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (owner_hash) DO NOTHING"
+    )
+    .bind(pseudonym_id)
+    .bind(owner_hash(master, user_id))
+    .bind(&escrow.ciphertext)
+    .bind(&escrow.wrapped_data_key)
+    .bind(&escrow.kek_id)
+    .execute(&mut *tx)
+    .await?;
+
+    // Another request created it first
+    if created.rows_affected() == 0 {
+        tx.rollback().await?;
+        return find(pool, master, user_id)
+            .await?
+            .ok_or_else(|| PseudonymError("Pseudonym disappeared".to_string()));
+    }
+
+    tx.commit().await?;
+
+    Ok(pseudonym_id)
+}
+
+/// Ids a citizen's reports and tickets may be filed under: their own and
+/// their pseudonym's
+pub async fn reporter_ids(pool: &PgPool, master: &MasterKey, user_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+    let mut ids = vec![user_id];
+    ids.extend(find(pool, master, user_id).await?);
+
+    Ok(ids)
+}
+
+/// Cuts a pseudonymous report loose from the chat it was drafted in, whose
+/// session belongs to the citizen, and closes that chat so it drafts no
+/// further report. Returns whether there was a link to cut.
+pub async fn detach_session(conn: &mut sqlx::PgConnection, report_id: Uuid) -> Result<bool, sqlx::Error> {
+    let session_id: Option<Uuid> = sqlx::query_scalar(
+        "SELECT session_id FROM reports WHERE id = $1 AND anonymity = 'pseudonymous' AND session_id IS NOT NULL"
+    )
+    .bind(report_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .flatten();
+
+    let Some(session_id) = session_id else {
+        return Ok(false);
+    };
+
+    sqlx::query("UPDATE chat_sessions SET status = 'completed' WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE llm_calls SET session_id = NULL WHERE report_id = $1")
+        .bind(report_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE reports SET session_id = NULL WHERE id = $1")
+        .bind(report_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(true)
+}
+
+/// Opens the escrow of a pseudonym, returning the citizen behind it
+pub async fn reveal(pool: &PgPool, master: &MasterKey, pseudonym_id: Uuid) -> Result<Option<Uuid>, PseudonymError> {
+    let escrow: Option<(String, String, String)> = sqlx::query_as(
+        "SELECT escrow_ciphertext, wrapped_data_key, kek_id FROM pseudonyms WHERE user_id = $1"
+    )
+    .bind(pseudonym_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((ciphertext, wrapped_data_key, kek_id)) = escrow else {
+        return Ok(None);
+    };
+
+    let sealed = Sealed { ciphertext, wrapped_data_key, kek_id };
+    let user_id = crypto::open_secret(master, pseudonym_id, &sealed)?;
+
+    Uuid::parse_str(&user_id)
+        .map(Some)
+        .map_err(|_| PseudonymError("Escrow does not hold a user id".to_string()))
+}
+
+/// Re-seals every escrow under `new` and re-derives the owner hashes from
+/// it. Like `crypto::rotate`, rows already under `new` are skipped.
+pub async fn rotate(pool: &PgPool, current: &MasterKey, new: &MasterKey) -> Result<u64, PseudonymError> {
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(Uuid, String, String, String)> = sqlx::query_as(
+        "SELECT user_id, escrow_ciphertext, wrapped_data_key, kek_id FROM pseudonyms
+         WHERE kek_id <> $1
+         FOR UPDATE"
+    )
+    .bind(new.id())
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (pseudonym_id, ciphertext, wrapped_data_key, kek_id) in rows.iter().cloned() {
+        let sealed = Sealed { ciphertext, wrapped_data_key, kek_id };
+        let user_id = Uuid::parse_str(&crypto::open_secret(current, pseudonym_id, &sealed)?)
+            .map_err(|_| PseudonymError("Escrow does not hold a user id".to_string()))?;
+
+        sqlx::query(
+            "UPDATE pseudonyms SET owner_hash = $2, wrapped_data_key = $3, kek_id = $4 WHERE user_id = $1"
+        )
+        .bind(pseudonym_id)
+        .bind(owner_hash(new, user_id))
+        .bind(crypto::rewrap(current, new, &sealed.wrapped_data_key, &sealed.kek_id)?)
+        .bind(new.id())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(rows.len() as u64)
+}
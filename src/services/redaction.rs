@@ -0,0 +1,405 @@
+//! Redaction of personal data from citizen text before it reaches a
+//! third-party LLM. Each value found is replaced with a placeholder such as
+//! `[PHONE_1]`; the same value always gets the same placeholder within one
+//! request, so the model can still refer to it. Placeholders in the answer
+//! are turned back into the original values, except for the kinds that
+//! should stay redacted even in stored output.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// Longest placeholder, e.g. `[PERSON_999]`; used to hold back streamed text
+// that may end in the first half of one
+const MAX_PLACEHOLDER_LEN: usize = 16;
+
+// Capitals before a number in addresses, which look like the letters of a plate
+const NOT_PLATE_PREFIXES: [&str; 6] = ["RT", "RW", "NO", "KM", "JL", "GG"];
+
+// NPWP with separators (`12.345.678.9-012.345`) or as 15 plain digits
+static NPWP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b\d{2}\.\d{3}\.\d{3}\.\d-\d{3}\.\d{3}\b|\b\d{15}\b").unwrap()
+});
+// 16 digits, starting with a province code
+static NIK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[1-9]\d{15}\b").unwrap());
+// Mobile numbers: +62 / 62 / 0 followed by 8, separated by spaces, dots or dashes
+static PHONE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:\+62|\b62|\b0)[\s.-]?8\d{1,3}(?:[\s.-]?\d{2,5}){1,3}\b").unwrap()
+});
+static EMAIL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b").unwrap()
+});
+// Regional letters, number, owner letters: `B 1234 ABC`, `AB1234CD`
+static PLATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b([A-Z]{1,2})\s?[1-9]\d{0,3}\s?[A-Z]{1,3}\b").unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiiKind {
+    Nik,
+    Npwp,
+    Phone,
+    Email,
+    Plate,
+    /// Names of people, once NER has found them in an earlier prompt
+    Person,
+}
+
+impl PiiKind {
+    pub const ALL: [PiiKind; 6] = [
+        PiiKind::Npwp,
+        PiiKind::Nik,
+        PiiKind::Phone,
+        PiiKind::Email,
+        PiiKind::Plate,
+        PiiKind::Person,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PiiKind::Nik => "nik",
+            PiiKind::Npwp => "npwp",
+            PiiKind::Phone => "phone",
+            PiiKind::Email => "email",
+            PiiKind::Plate => "plate",
+            PiiKind::Person => "person",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    fn label(self) -> String {
+        self.name().to_uppercase()
+    }
+}
+
+/// Which kinds are redacted, and which of those stay redacted in the answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedactionRules {
+    pub redact: Vec<PiiKind>,
+    pub keep_redacted: Vec<PiiKind>,
+}
+
+impl RedactionRules {
+    /// Parses comma-separated kind names, e.g. `nik,phone,email`
+    pub fn parse(redact: &str, keep_redacted: &str) -> Result<Self, String> {
+        Ok(Self {
+            redact: parse_kinds(redact)?,
+            keep_redacted: parse_kinds(keep_redacted)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.redact.is_empty()
+    }
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self {
+            redact: PiiKind::ALL.to_vec(),
+            // Identity numbers are never needed back in an answer
+            keep_redacted: vec![PiiKind::Nik, PiiKind::Npwp],
+        }
+    }
+}
+
+fn parse_kinds(names: &str) -> Result<Vec<PiiKind>, String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| PiiKind::from_name(name).ok_or_else(|| format!("Unknown PII kind: {}", name)))
+        .collect()
+}
+
+struct Replacement {
+    placeholder: String,
+    original: String,
+    kind: PiiKind,
+}
+
+/// Redacts the texts of one request and restores the answer to it
+pub struct Redactor {
+    rules: RedactionRules,
+    names: Vec<String>,
+    replacements: Vec<Replacement>,
+}
+
+impl Redactor {
+    pub fn new(rules: RedactionRules) -> Self {
+        Self {
+            rules,
+            names: Vec::new(),
+            replacements: Vec::new(),
+        }
+    }
+
+    /// Names of people to redact when `person` is among the rules
+    pub fn with_names(mut self, names: &[String]) -> Self {
+        let mut names: Vec<String> = names
+            .iter()
+            .map(|name| name.trim().to_string())
+            .filter(|name| name.chars().count() > 2)
+            .collect();
+        // Longest first, so "Budi Santoso" goes before "Budi"
+        names.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        names.dedup();
+
+        self.names = names;
+        self
+    }
+
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+
+        for kind in PiiKind::ALL {
+            if !self.rules.redact.contains(&kind) {
+                continue;
+            }
+
+            text = match kind {
+                PiiKind::Nik => self.replace_matches(&text, &NIK, kind),
+                PiiKind::Npwp => self.replace_matches(&text, &NPWP, kind),
+                PiiKind::Phone => self.replace_matches(&text, &PHONE, kind),
+                PiiKind::Email => self.replace_matches(&text, &EMAIL, kind),
+                PiiKind::Plate => self.replace_plates(&text),
+                PiiKind::Person => self.replace_names(&text),
+            };
+        }
+
+        text
+    }
+
+    /// Puts the original values back, except for kinds kept redacted
+    pub fn restore(&self, text: &str) -> String {
+        self.replacements
+            .iter()
+            .filter(|r| !self.rules.keep_redacted.contains(&r.kind))
+            .fold(text.to_string(), |text, r| text.replace(&r.placeholder, &r.original))
+    }
+
+    fn placeholder(&mut self, original: &str, kind: PiiKind) -> String {
+        if let Some(existing) = self.replacements.iter().find(|r| r.kind == kind && r.original == original) {
+            return existing.placeholder.clone();
+        }
+
+        let number = self.replacements.iter().filter(|r| r.kind == kind).count() + 1;
+        let placeholder = format!("[{}_{}]", kind.label(), number);
+        self.replacements.push(Replacement {
+            placeholder: placeholder.clone(),
+            original: original.to_string(),
+            kind,
+        });
+
+        placeholder
+    }
+
+    fn replace_matches(&mut self, text: &str, pattern: &Regex, kind: PiiKind) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for found in pattern.find_iter(text) {
+            redacted.push_str(&text[last..found.start()]);
+            redacted.push_str(&self.placeholder(found.as_str(), kind));
+            last = found.end();
+        }
+
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+
+    fn replace_plates(&mut self, text: &str) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for captures in PLATE.captures_iter(text) {
+            let (Some(found), Some(prefix)) = (captures.get(0), captures.get(1)) else {
+                continue;
+            };
+            if NOT_PLATE_PREFIXES.contains(&prefix.as_str()) {
+                continue;
+            }
+
+            redacted.push_str(&text[last..found.start()]);
+            redacted.push_str(&self.placeholder(found.as_str(), PiiKind::Plate));
+            last = found.end();
+        }
+
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+
+    fn replace_names(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+
+        for name in self.names.clone() {
+            let pattern = match Regex::new(&format!(r"(?i)\b{}\b", regex::escape(&name))) {
+                Ok(pattern) => pattern,
+                Err(_) => continue,
+            };
+            text = self.replace_matches(&text, &pattern, PiiKind::Person);
+        }
+
+        text
+    }
+}
+
+/// Restores streamed text piece by piece. A piece ending in what may be the
+/// start of a placeholder is held back until the rest arrives.
+pub struct StreamRestorer<'a> {
+    redactor: &'a Redactor,
+    pending: String,
+}
+
+impl<'a> StreamRestorer<'a> {
+    pub fn new(redactor: &'a Redactor) -> Self {
+        Self {
+            redactor,
+            pending: String::new(),
+        }
+    }
+
+    /// Restored text that is safe to pass on; may be empty
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+
+        let hold_from = self
+            .pending
+            .rfind('[')
+            .filter(|&open| !self.pending[open..].contains(']'))
+            .filter(|&open| self.pending.len() - open < MAX_PLACEHOLDER_LEN);
+
+        match hold_from {
+            Some(open) => {
+                let held = self.pending.split_off(open);
+                let ready = std::mem::replace(&mut self.pending, held);
+                self.redactor.restore(&ready)
+            }
+            None => self.redactor.restore(&std::mem::take(&mut self.pending)),
+        }
+    }
+
+    /// Whatever is still held back, restored
+    pub fn finish(self) -> String {
+        self.redactor.restore(&self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::new(RedactionRules::default())
+    }
+
+    #[test]
+    fn redacts_nik_and_keeps_it_redacted() {
+        let mut redactor = redactor();
+        let redacted = redactor.redact("NIK saya 3174012345678901, tolong dicek");
+
+        assert_eq!(redacted, "NIK saya [NIK_1], tolong dicek");
+        assert_eq!(redactor.restore("Terima kasih, [NIK_1] sudah dicatat"), "Terima kasih, [NIK_1] sudah dicatat");
+    }
+
+    #[test]
+    fn redacts_npwp_in_both_formats() {
+        let mut redactor = redactor();
+        let redacted = redactor.redact("NPWP 01.234.567.8-901.234 atau 012345678901234");
+
+        assert_eq!(redacted, "NPWP [NPWP_1] atau [NPWP_2]");
+    }
+
+    #[test]
+    fn redacts_phone_numbers_and_restores_them() {
+        let mut redactor = redactor();
+        let text = "Hubungi 0812-3456-7890 atau +62 813 1234 5678 atau 6285712345678";
+        let redacted = redactor.redact(text);
+
+        assert_eq!(redacted, "Hubungi [PHONE_1] atau [PHONE_2] atau [PHONE_3]");
+        assert_eq!(redactor.restore(&redacted), text);
+    }
+
+    #[test]
+    fn same_value_gets_same_placeholder() {
+        let mut redactor = redactor();
+        let first = redactor.redact("Email saya budi@example.com");
+        let second = redactor.redact("Sekali lagi: budi@example.com");
+
+        assert_eq!(first, "Email saya [EMAIL_1]");
+        assert_eq!(second, "Sekali lagi: [EMAIL_1]");
+    }
+
+    #[test]
+    fn redacts_plates_but_not_neighbourhood_numbers() {
+        let mut redactor = redactor();
+        let redacted = redactor.redact("Mobil B 1234 ABC parkir di RT 05 RW 03, motor AB1234CD juga");
+
+        assert_eq!(redacted, "Mobil [PLATE_1] parkir di RT 05 RW 03, motor [PLATE_2] juga");
+    }
+
+    #[test]
+    fn redacts_names_case_insensitively_longest_first() {
+        let mut redactor = redactor().with_names(&["Budi".to_string(), "Budi Santoso".to_string()]);
+        let redacted = redactor.redact("Pak budi santoso bilang Budi tidak tahu");
+
+        assert_eq!(redacted, "Pak [PERSON_1] bilang [PERSON_2] tidak tahu");
+        assert_eq!(redactor.restore(&redacted), "Pak budi santoso bilang Budi tidak tahu");
+    }
+
+    #[test]
+    fn repeated_names_are_kept_once() {
+        let names = ["Siti", "Budi", "Siti ", "Budi Santoso", "Budi"].map(String::from);
+        let redactor = redactor().with_names(&names);
+
+        assert_eq!(redactor.names, ["Budi Santoso", "Budi", "Siti"]);
+    }
+
+    #[test]
+    fn only_configured_kinds_are_redacted() {
+        let rules = RedactionRules::parse("email", "").unwrap();
+        let mut redactor = Redactor::new(rules);
+        let redacted = redactor.redact("0812-3456-7890, budi@example.com");
+
+        assert_eq!(redacted, "0812-3456-7890, [EMAIL_1]");
+    }
+
+    #[test]
+    fn parse_rejects_unknown_kinds() {
+        assert!(RedactionRules::parse("nik,passport", "").is_err());
+        assert_eq!(
+            RedactionRules::parse(" nik , phone ", "nik").unwrap(),
+            RedactionRules {
+                redact: vec![PiiKind::Nik, PiiKind::Phone],
+                keep_redacted: vec![PiiKind::Nik],
+            }
+        );
+    }
+
+    #[test]
+    fn stream_restores_placeholders_split_across_deltas() {
+        let mut redactor = redactor();
+        redactor.redact("Nomor saya 081234567890");
+
+        let mut restorer = StreamRestorer::new(&redactor);
+        let mut output = String::new();
+        for delta in ["Kami akan menghubungi [PHO", "NE_", "1] segera", " [ya]"] {
+            output.push_str(&restorer.push(delta));
+        }
+        output.push_str(&restorer.finish());
+
+        assert_eq!(output, "Kami akan menghubungi 081234567890 segera [ya]");
+    }
+
+    #[test]
+    fn stream_holds_back_nothing_without_brackets() {
+        let redactor = redactor();
+        let mut restorer = StreamRestorer::new(&redactor);
+
+        assert_eq!(restorer.push("Halo, "), "Halo, ");
+        assert_eq!(restorer.push("apa kabar?"), "apa kabar?");
+        assert_eq!(restorer.finish(), "");
+    }
+}
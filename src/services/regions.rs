@@ -0,0 +1,327 @@
+use sqlx::PgPool;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+static HAS_BOUNDARIES: OnceLock<bool> = OnceLock::new();
+
+// Minimum word similarity between a village name and free-text location
+const MIN_TEXT_SIMILARITY: f32 = 0.6;
+// Nearest-centroid lookups ignore villages further than this many degrees away
+const CENTROID_WINDOW_DEGREES: f64 = 0.1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionRow {
+    pub code: String,
+    pub parent_code: Option<String>,
+    pub name: String,
+    pub level: &'static str,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+fn level_for(code: &str) -> Option<&'static str> {
+    match code.split('.').count() {
+        1 => Some("province"),
+        2 => Some("regency"),
+        3 => Some("district"),
+        4 => Some("village"),
+        _ => None,
+    }
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Parses the Kemendagri region list: `kode,nama[,lat,lng]` per line, e.g.
+/// `33.74.01.1001,Sendangmulyo`. Header and malformed lines are skipped.
+/// Rows are returned parents first so they can be inserted in order.
+pub fn parse_kemendagri_csv(csv: &str) -> Vec<RegionRow> {
+    let mut rows: Vec<RegionRow> = csv
+        .lines()
+        .filter_map(|line| {
+            let fields = split_csv_line(line.trim_start_matches('\u{feff}'));
+            let code = fields.first()?.clone();
+            let name = fields.get(1)?.clone();
+
+            if name.is_empty() || !code.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                return None;
+            }
+
+            let level = level_for(&code)?;
+            let parent_code = code.rsplit_once('.').map(|(parent, _)| parent.to_string());
+
+            Some(RegionRow {
+                code,
+                parent_code,
+                name,
+                level,
+                latitude: fields.get(2).and_then(|v| v.parse().ok()),
+                longitude: fields.get(3).and_then(|v| v.parse().ok()),
+            })
+        })
+        .collect();
+
+    rows.sort_by_key(|r| r.code.split('.').count());
+    rows
+}
+
+pub async fn import(pool: &PgPool, rows: &[RegionRow]) -> Result<u64, sqlx::Error> {
+    let mut imported = 0;
+    let mut tx = pool.begin().await?;
+
+    for chunk in rows.chunks(1000) {
+        let result = sqlx::query(
+            "INSERT INTO regions (code, parent_code, name, level, centroid_latitude, centroid_longitude)
+             SELECT * FROM UNNEST($1::varchar[], $2::varchar[], $3::varchar[], $4::varchar[], $5::float8[], $6::float8[])
+             ON CONFLICT (code) DO UPDATE SET
+                parent_code = EXCLUDED.parent_code,
+                name = EXCLUDED.name,
+                level = EXCLUDED.level,
+                centroid_latitude = COALESCE(EXCLUDED.centroid_latitude, regions.centroid_latitude),
+                centroid_longitude = COALESCE(EXCLUDED.centroid_longitude, regions.centroid_longitude),
+                updated_at = NOW()"
+        )
+        .bind(chunk.iter().map(|r| r.code.clone()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.parent_code.clone()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.name.clone()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.level.to_string()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.latitude).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.longitude).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?;
+
+        imported += result.rows_affected();
+    }
+
+    tx.commit().await?;
+
+    Ok(imported)
+}
+
+async fn has_boundaries(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    if let Some(has) = HAS_BOUNDARIES.get() {
+        return Ok(*has);
+    }
+
+    let has: bool = sqlx::query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM information_schema.columns
+            WHERE table_name = 'regions' AND column_name = 'boundary'
+        )"
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(*HAS_BOUNDARIES.get_or_init(|| has))
+}
+
+pub async fn resolve_from_coordinates(
+    pool: &PgPool,
+    latitude: f64,
+    longitude: f64,
+) -> Result<Option<String>, sqlx::Error> {
+    if has_boundaries(pool).await? {
+        let code: Option<String> = sqlx::query_scalar(
+            "SELECT code FROM regions
+             WHERE level = 'village' AND boundary IS NOT NULL
+             AND ST_Contains(boundary, ST_SetSRID(ST_MakePoint($2, $1), 4326))
+             LIMIT 1"
+        )
+        .bind(latitude)
+        .bind(longitude)
+        .fetch_optional(pool)
+        .await?;
+
+        if code.is_some() {
+            return Ok(code);
+        }
+    }
+
+    // Equirectangular distance is plenty for picking the closest village centroid.
+    sqlx::query_scalar(
+        "SELECT code FROM regions
+         WHERE level = 'village'
+         AND centroid_latitude BETWEEN $1 - $3 AND $1 + $3
+         AND centroid_longitude BETWEEN $2 - $3 AND $2 + $3
+         ORDER BY POWER(CAST(centroid_latitude AS DOUBLE PRECISION) - $1, 2)
+                + POWER((CAST(centroid_longitude AS DOUBLE PRECISION) - $2) * COS(RADIANS($1)), 2)
+         LIMIT 1"
+    )
+    .bind(latitude)
+    .bind(longitude)
+    .bind(CENTROID_WINDOW_DEGREES)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Fuzzy-matches free-text location against village names. Matching district
+/// and regency names in the same text break ties between same-named villages.
+pub async fn resolve_from_text(pool: &PgPool, text: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT v.code FROM regions v
+         LEFT JOIN regions d ON d.code = v.parent_code
+         LEFT JOIN regions k ON k.code = d.parent_code
+         WHERE v.level = 'village'
+         AND v.name <% $1
+         AND word_similarity(v.name, $1) >= $2
+         ORDER BY word_similarity(v.name, $1)
+                + 0.5 * COALESCE(word_similarity(d.name, $1), 0)
+                + 0.5 * COALESCE(word_similarity(k.name, $1), 0) DESC
+         LIMIT 1"
+    )
+    .bind(text)
+    .bind(MIN_TEXT_SIMILARITY)
+    .fetch_optional(pool)
+    .await
+}
+
+// latitude, longitude, location_text, address
+type ReportLocation = (Option<f64>, Option<f64>, Option<String>, Option<String>);
+
+/// Assigns a report to its village, preferring coordinates over location text.
+pub async fn resolve_report(pool: &PgPool, report_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let report: Option<ReportLocation> = sqlx::query_as(
+        "SELECT CAST(latitude AS DOUBLE PRECISION), CAST(longitude AS DOUBLE PRECISION), location_text, address
+         FROM reports WHERE id = $1"
+    )
+    .bind(report_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((latitude, longitude, location_text, address)) = report else {
+        return Ok(None);
+    };
+
+    let mut code = match (latitude, longitude) {
+        (Some(lat), Some(lon)) => resolve_from_coordinates(pool, lat, lon).await?,
+        _ => None,
+    };
+
+    if code.is_none() {
+        let text = [location_text, address]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if !text.trim().is_empty() {
+            code = resolve_from_text(pool, &text).await?;
+        }
+    }
+
+    if code.is_some() {
+        sqlx::query("UPDATE reports SET region_code = $1 WHERE id = $2")
+            .bind(&code)
+            .bind(report_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(code)
+}
+
+/// SQL predicate matching `column` against a region and everything beneath it.
+/// The bound parameter may be NULL, in which case every row matches.
+pub fn sql_filter(column: &str, param: usize) -> String {
+    format!(
+        "(${p}::varchar IS NULL OR {c} = ${p} OR {c} LIKE ${p} || '.%')",
+        c = column,
+        p = param
+    )
+}
+
+pub struct Geocoded {
+    pub region_code: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// Approximate position for free-text location: the centroid of the best
+/// matching village.
+pub async fn geocode(pool: &PgPool, text: &str) -> Result<Option<Geocoded>, sqlx::Error> {
+    let Some(region_code) = resolve_from_text(pool, text).await? else {
+        return Ok(None);
+    };
+
+    let (latitude, longitude): (Option<f64>, Option<f64>) = sqlx::query_as(
+        "SELECT CAST(centroid_latitude AS DOUBLE PRECISION), CAST(centroid_longitude AS DOUBLE PRECISION)
+         FROM regions WHERE code = $1"
+    )
+    .bind(&region_code)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Some(Geocoded {
+        region_code,
+        latitude,
+        longitude,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = "\u{feff}kode,nama,lat,lng
+33.74.01.1001,Sendangmulyo,-7.0361,110.4639
+33,JAWA TENGAH
+33.74,KOTA SEMARANG,-6.9932,110.4203
+33.74.01,\"Tembalang, Kec.\"
+33.74.01.1002,\"Kramas \"\"Lama\"\"\",bukan,angka
+33.74.01.1001.5,Terlalu dalam
+3374x,Kode rusak
+33.74.02,
+";
+
+    #[test]
+    fn parses_regions_parents_first() {
+        let rows = parse_kemendagri_csv(FIXTURE);
+        let codes: Vec<&str> = rows.iter().map(|r| r.code.as_str()).collect();
+
+        assert_eq!(codes, ["33", "33.74", "33.74.01", "33.74.01.1001", "33.74.01.1002"]);
+        assert_eq!(rows.iter().map(|r| r.level).collect::<Vec<_>>(), ["province", "regency", "district", "village", "village"]);
+    }
+
+    #[test]
+    fn links_parents_and_reads_centroids() {
+        let rows = parse_kemendagri_csv(FIXTURE);
+
+        assert_eq!(rows[3], RegionRow {
+            code: "33.74.01.1001".to_string(),
+            parent_code: Some("33.74.01".to_string()),
+            name: "Sendangmulyo".to_string(),
+            level: "village",
+            latitude: Some(-7.0361),
+            longitude: Some(110.4639),
+        });
+        assert_eq!(rows[0].parent_code, None);
+    }
+
+    #[test]
+    fn reads_quoted_names_and_skips_bad_coordinates() {
+        let rows = parse_kemendagri_csv(FIXTURE);
+
+        assert_eq!(rows[2].name, "Tembalang, Kec.");
+        assert_eq!(rows[4].name, "Kramas \"Lama\"");
+        assert_eq!((rows[4].latitude, rows[4].longitude), (None, None));
+    }
+}
@@ -0,0 +1,251 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::*;
+
+// Citizens who may vote on a ticket: the reporter plus everyone whose report
+// landed in the same cluster as the ticket's report, and the supporters of
+// those reports.
+const ELIGIBLE_VOTERS: &str = "
+    SELECT t.user_id FROM tickets t WHERE t.id = $1
+    UNION
+    SELECT r2.user_id FROM tickets t
+    JOIN reports r ON r.id = t.report_id
+    JOIN reports r2 ON r2.cluster_id = r.cluster_id
+    WHERE t.id = $1 AND r.cluster_id IS NOT NULL
+    UNION
+    SELECT s.user_id FROM tickets t
+    JOIN reports r ON r.id = t.report_id
+    JOIN reports r2 ON r2.id = r.id OR r2.cluster_id = r.cluster_id
+    JOIN report_supporters s ON s.report_id = r2.id
+    WHERE t.id = $1";
+
+pub async fn open_review(
+    pool: &PgPool,
+    ticket_id: Uuid,
+    requested_by: Option<Uuid>,
+    summary: Option<String>,
+    quorum: f64,
+) -> Result<ResolutionReview, sqlx::Error> {
+    if let Some(review) = current_review(pool, ticket_id).await?.filter(|r| r.status == "open") {
+        return Ok(review);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "UPDATE tickets SET status = 'pending_review', updated_at = NOW() WHERE id = $1"
+    )
+    .bind(ticket_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let review = sqlx::query_as::<_, ResolutionReview>(
+        "INSERT INTO ticket_resolution_reviews (ticket_id, round, summary, requested_by, quorum)
+         VALUES (
+             $1,
+             (SELECT COALESCE(MAX(round), 0) + 1 FROM ticket_resolution_reviews WHERE ticket_id = $1),
+             $2, $3, $4::numeric
+         )
+         RETURNING *"
+    )
+    .bind(ticket_id)
+    .bind(summary)
+    .bind(requested_by)
+    .bind(quorum.clamp(0.0, 1.0))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(review)
+}
+
+pub async fn current_review(
+    pool: &PgPool,
+    ticket_id: Uuid,
+) -> Result<Option<ResolutionReview>, sqlx::Error> {
+    sqlx::query_as::<_, ResolutionReview>(
+        "SELECT * FROM ticket_resolution_reviews WHERE ticket_id = $1 ORDER BY round DESC LIMIT 1"
+    )
+    .bind(ticket_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The first of `user_ids` that may vote on the ticket, if any
+pub async fn eligible_voter(
+    pool: &PgPool,
+    ticket_id: Uuid,
+    user_ids: &[Uuid],
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT e.user_id FROM ({}) e WHERE e.user_id = ANY($2) LIMIT 1",
+        ELIGIBLE_VOTERS
+    ))
+    .bind(ticket_id)
+    .bind(user_ids)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn tally(executor: impl sqlx::PgExecutor<'_>, review: &ResolutionReview) -> Result<ResolutionTally, sqlx::Error> {
+    sqlx::query_as::<_, ResolutionTally>(&format!(
+        "WITH eligible AS ({}),
+              counted AS (SELECT COUNT(*)::bigint AS n FROM eligible)
+         SELECT
+             counted.n AS eligible,
+             GREATEST(1, CEIL(counted.n * $3::numeric))::bigint AS required_accepts,
+             (SELECT COUNT(*) FROM ticket_resolution_votes v
+              WHERE v.review_id = $2 AND v.vote = 'accept'
+              AND v.user_id IN (SELECT user_id FROM eligible))::bigint AS accepts,
+             (SELECT COUNT(*) FROM ticket_resolution_votes v
+              WHERE v.review_id = $2 AND v.vote = 'reject'
+              AND v.user_id IN (SELECT user_id FROM eligible))::bigint AS rejects
+         FROM counted",
+        ELIGIBLE_VOTERS
+    ))
+    .bind(review.ticket_id)
+    .bind(review.id)
+    .bind(review.quorum)
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn votes(pool: &PgPool, review_id: Uuid) -> Result<Vec<ResolutionVote>, sqlx::Error> {
+    sqlx::query_as::<_, ResolutionVote>(
+        "SELECT * FROM ticket_resolution_votes WHERE review_id = $1 ORDER BY created_at ASC"
+    )
+    .bind(review_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Records (or changes) a citizen's vote and settles the round once the
+/// outcome is decided: enough accepts close the ticket, and enough rejects
+/// that the quorum can no longer be reached reopen it. The round is locked
+/// for the vote and tally; returns `None` when it was already settled.
+pub async fn cast_vote(
+    pool: &PgPool,
+    review: &ResolutionReview,
+    user_id: Uuid,
+    vote: &str,
+    reason: Option<String>,
+) -> Result<Option<ResolutionReview>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let open = sqlx::query_as::<_, ResolutionReview>(
+        "SELECT * FROM ticket_resolution_reviews WHERE id = $1 AND status = 'open' FOR UPDATE"
+    )
+    .bind(review.id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(review) = open else {
+        return Ok(None);
+    };
+
+    sqlx::query(
+        "INSERT INTO ticket_resolution_votes (review_id, user_id, vote, reason)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (review_id, user_id)
+         DO UPDATE SET vote = EXCLUDED.vote, reason = EXCLUDED.reason, created_at = NOW()"
+    )
+    .bind(review.id)
+    .bind(user_id)
+    .bind(vote)
+    .bind(reason)
+    .execute(&mut *tx)
+    .await?;
+
+    let tally = tally(&mut *tx, &review).await?;
+
+    let review = if tally.accepts >= tally.required_accepts {
+        accept(&mut tx, &review).await?
+    } else if tally.rejects > tally.eligible - tally.required_accepts {
+        reject(&mut tx, &review).await?
+    } else {
+        review
+    };
+
+    tx.commit().await?;
+
+    Ok(Some(review))
+}
+
+async fn accept(tx: &mut sqlx::PgConnection, review: &ResolutionReview) -> Result<ResolutionReview, sqlx::Error> {
+    let review = sqlx::query_as::<_, ResolutionReview>(
+        "UPDATE ticket_resolution_reviews SET status = 'accepted', closed_at = NOW()
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(review.id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE tickets SET
+            status = 'closed',
+            resolution = COALESCE($2, resolution),
+            resolved_at = NOW(),
+            resolved_by = $3,
+            updated_at = NOW()
+         WHERE id = $1"
+    )
+    .bind(review.ticket_id)
+    .bind(&review.summary)
+    .bind(review.requested_by)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE reports SET status = 'resolved', updated_at = NOW()
+         WHERE id = (SELECT report_id FROM tickets WHERE id = $1)"
+    )
+    .bind(review.ticket_id)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(review)
+}
+
+async fn reject(tx: &mut sqlx::PgConnection, review: &ResolutionReview) -> Result<ResolutionReview, sqlx::Error> {
+    let reasons: Vec<String> = sqlx::query_scalar(
+        "SELECT reason FROM ticket_resolution_votes
+         WHERE review_id = $1 AND vote = 'reject' AND reason IS NOT NULL
+         ORDER BY created_at ASC"
+    )
+    .bind(review.id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let review = sqlx::query_as::<_, ResolutionReview>(
+        "UPDATE ticket_resolution_reviews SET status = 'rejected', closed_at = NOW()
+         WHERE id = $1 RETURNING *"
+    )
+    .bind(review.id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE tickets SET status = 'reopened', updated_at = NOW() WHERE id = $1"
+    )
+    .bind(review.ticket_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let mut comment = format!("Penyelesaian ditolak warga (putaran {}).", review.round);
+    for reason in &reasons {
+        comment.push_str(&format!("\n- {}", reason));
+    }
+
+    // Posted by the platform, not in the reporter's name
+    sqlx::query(
+        "INSERT INTO ticket_comments (ticket_id, user_id, comment, is_internal)
+         VALUES ($1, NULL, $2, false)"
+    )
+    .bind(review.ticket_id)
+    .bind(comment)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(review)
+}
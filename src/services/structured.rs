@@ -0,0 +1,234 @@
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+
+// Bracketed prose before the answer ("[catatan] ...") is skipped, within reason
+const MAX_CANDIDATES: usize = 8;
+
+#[derive(Debug)]
+pub enum StructuredError {
+    /// The response contains no JSON at all
+    NotFound,
+    /// JSON was found but is malformed or does not match the expected shape
+    Invalid(String),
+    /// Still no usable answer after the corrective follow-ups
+    Exhausted { attempts: u32, last: Box<StructuredError> },
+}
+
+impl std::fmt::Display for StructuredError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructuredError::NotFound => write!(f, "No JSON found in response"),
+            StructuredError::Invalid(e) => write!(f, "Invalid JSON response: {}", e),
+            StructuredError::Exhausted { attempts, last } => {
+                write!(f, "No valid structured output after {} attempts: {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StructuredError {}
+
+/// Byte range of the JSON value starting at `start`. Runs to the end of the
+/// text when the value is never closed, i.e. the output was truncated.
+fn value_end(text: &str, start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return start + i + c.len_utf8();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    text.len()
+}
+
+/// Objects and arrays in the order they appear in the text.
+fn candidates(text: &str) -> impl Iterator<Item = &str> {
+    text.char_indices()
+        .filter(|(_, c)| *c == '{' || *c == '[')
+        .map(move |(start, _)| &text[start..value_end(text, start)])
+}
+
+fn strip_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with(',') {
+        out.truncate(trimmed.len() - 1);
+    }
+}
+
+/// Fixes the defects models commonly produce: trailing commas, Python
+/// literals and output cut off before the closing brackets.
+pub fn repair(json: &str) -> String {
+    let mut out = String::with_capacity(json.len() + 8);
+    let mut closers = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = json.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' => {
+                closers.push('}');
+                out.push(c);
+            }
+            '[' => {
+                closers.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                strip_trailing_comma(&mut out);
+                closers.pop();
+                out.push(c);
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|n| n.is_ascii_alphanumeric() || *n == '_') {
+                    word.push(next);
+                }
+                out.push_str(match word.as_str() {
+                    "True" => "true",
+                    "False" => "false",
+                    "None" => "null",
+                    _ => &word,
+                });
+            }
+            _ => out.push(c),
+        }
+    }
+
+    // Truncated output: close the open string, drop a dangling separator and
+    // close whatever is still open.
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    out.truncate(out.trim_end().len());
+    strip_trailing_comma(&mut out);
+    if out.ends_with(':') {
+        out.push_str(" null");
+    }
+    while let Some(closer) = closers.pop() {
+        out.push(closer);
+    }
+
+    out
+}
+
+/// Reads the first JSON value in `text` that deserializes into `T`, repairing
+/// it if needed. Surrounding prose and markdown fences are ignored.
+pub fn parse<T: DeserializeOwned>(text: &str) -> Result<T, StructuredError> {
+    let mut error: Option<serde_json::Error> = None;
+
+    for candidate in candidates(text).take(MAX_CANDIDATES) {
+        for attempt in [Cow::Borrowed(candidate), Cow::Owned(repair(candidate))] {
+            match serde_json::from_str::<T>(&attempt) {
+                Ok(value) => return Ok(value),
+                // Shape mismatches say more about what to fix than syntax errors
+                Err(e) if e.is_data() || error.is_none() => error = Some(e),
+                Err(_) => {}
+            }
+        }
+    }
+
+    Err(error
+        .map(|e| StructuredError::Invalid(e.to_string()))
+        .unwrap_or(StructuredError::NotFound))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm::ExtractedReport;
+
+    fn value(text: &str) -> serde_json::Value {
+        parse(text).unwrap()
+    }
+
+    #[test]
+    fn repair_drops_trailing_commas() {
+        assert_eq!(repair(r#"{"a": [1, 2, ], }"#), r#"{"a": [1, 2]}"#);
+    }
+
+    #[test]
+    fn repair_converts_python_literals_outside_strings() {
+        assert_eq!(
+            repair(r#"{"a": True, "b": False, "c": None, "d": "None of True"}"#),
+            r#"{"a": true, "b": false, "c": null, "d": "None of True"}"#
+        );
+    }
+
+    #[test]
+    fn repair_closes_truncated_output() {
+        assert_eq!(repair(r#"{"a": [1, 2"#), r#"{"a": [1, 2]}"#);
+        assert_eq!(repair(r#"{"a": "terpot"#), r#"{"a": "terpot"}"#);
+        assert_eq!(repair(r#"{"a": 1, "b":"#), r#"{"a": 1, "b": null}"#);
+        assert_eq!(repair(r#"{"a": 1,"#), r#"{"a": 1}"#);
+    }
+
+    #[test]
+    fn braces_inside_strings_do_not_end_the_value() {
+        let text = r#"Hasil: {"title": "Lampu {mati} ]", "n": 1} selesai"#;
+
+        assert_eq!(candidates(text).next(), Some(r#"{"title": "Lampu {mati} ]", "n": 1}"#));
+        assert_eq!(value(text)["title"], "Lampu {mati} ]");
+    }
+
+    #[test]
+    fn parse_skips_prose_and_fences() {
+        let text = "[catatan] Berikut hasilnya:\n```json\n{\"flagged\": false,}\n```";
+
+        assert_eq!(value(text), serde_json::json!({"flagged": false}));
+    }
+
+    #[test]
+    fn parse_reports_missing_json() {
+        assert!(matches!(parse::<serde_json::Value>("tidak ada"), Err(StructuredError::NotFound)));
+    }
+
+    #[test]
+    fn report_needs_at_least_one_field() {
+        assert!(parse::<ExtractedReport>(r#"{"catatan": "kosong"}"#).is_err());
+        assert!(parse::<ExtractedReport>(r#"{"title": null, "urgency": null}"#).is_err());
+
+        // A stray object before the answer is passed over
+        let report: ExtractedReport = parse(r#"{"note": 1} {"title": "Banjir", "urgency": None}"#).unwrap();
+        assert_eq!(report.title.as_deref(), Some("Banjir"));
+    }
+}
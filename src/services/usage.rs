@@ -0,0 +1,236 @@
+use sqlx::PgPool;
+use std::time::Instant;
+use uuid::Uuid;
+use crate::models::{LlmModelPrice, LlmUsageBucket, LlmUsageReport, LlmUsageTotals};
+use super::prompts::PromptRef;
+use super::providers::{Completion, LlmError, LlmProvider};
+
+/// What an LLM call is made for, as recorded in the `llm_calls` ledger.
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    pub api_key_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub report_id: Option<Uuid>,
+}
+
+pub struct LlmCall<'a> {
+    pub context: &'a CallContext,
+    pub provider: &'a dyn LlmProvider,
+    pub prompt_type: &'a str,
+    /// `None` when a built-in fallback prompt was used
+    pub prompt: Option<&'a PromptRef>,
+    pub started: Instant,
+    pub result: Result<&'a Completion, &'a LlmError>,
+}
+
+/// One provider call as written to the ledger
+struct Attempt<'a> {
+    provider: &'a str,
+    api_key_id: Option<Uuid>,
+    model: &'a str,
+    prompt_tokens: Option<i32>,
+    completion_tokens: Option<i32>,
+    latency_ms: i32,
+    error: Option<&'a str>,
+}
+
+/// Adds a call to the ledger and bumps the key's usage counter, with a row
+/// of its own for every key the router failed over from. Accounting must
+/// never fail the request it accounts for, so errors are only logged.
+pub async fn record(pool: &PgPool, call: LlmCall<'_>) {
+    let latency_ms = call.started.elapsed().as_millis() as i32;
+    // Routed calls name the provider and key that served them, or failed last
+    let (served_by, routed_key, failovers) = match call.result {
+        Ok(completion) => (completion.provider, completion.api_key_id, &completion.failovers),
+        Err(e) => (e.provider, e.api_key_id, &e.failovers),
+    };
+
+    let prices = sqlx::query_as::<_, LlmModelPrice>("SELECT * FROM llm_model_prices")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to load model prices: {}", e);
+            vec![]
+        });
+
+    for failed in failovers {
+        insert(pool, &call, &prices, Attempt {
+            provider: failed.provider,
+            api_key_id: Some(failed.api_key_id),
+            model: &failed.model,
+            prompt_tokens: None,
+            completion_tokens: None,
+            latency_ms: failed.latency_ms,
+            error: Some(&failed.error),
+        })
+        .await;
+    }
+
+    let (model, prompt_tokens, completion_tokens, error) = match call.result {
+        Ok(completion) => (
+            completion.model.as_str(),
+            completion.prompt_tokens.map(|t| t as i32),
+            completion.completion_tokens.map(|t| t as i32),
+            None,
+        ),
+        Err(e) => (call.provider.model(), None, None, Some(e.to_string())),
+    };
+
+    insert(pool, &call, &prices, Attempt {
+        provider: served_by.unwrap_or(call.provider.name()),
+        api_key_id: routed_key.or(call.context.api_key_id),
+        model,
+        prompt_tokens,
+        completion_tokens,
+        latency_ms,
+        error: error.as_deref(),
+    })
+    .await;
+}
+
+/// Price of the longest model prefix matching `model`, e.g. "gpt-4o-mini"
+/// for "gpt-4o-mini-2024-07-18"
+fn price_for<'a>(prices: &'a [LlmModelPrice], model: &str) -> Option<&'a LlmModelPrice> {
+    prices
+        .iter()
+        .filter(|price| model.starts_with(&price.model))
+        .max_by_key(|price| price.model.len())
+}
+
+fn estimated_cost_usd(price: &LlmModelPrice, prompt_tokens: Option<i32>, completion_tokens: Option<i32>) -> f64 {
+    (f64::from(prompt_tokens.unwrap_or(0)) * price.prompt_usd_per_million
+        + f64::from(completion_tokens.unwrap_or(0)) * price.completion_usd_per_million)
+        / 1_000_000.0
+}
+
+async fn insert(pool: &PgPool, call: &LlmCall<'_>, prices: &[LlmModelPrice], attempt: Attempt<'_>) {
+    // NULL when the model has no price
+    let cost = price_for(prices, attempt.model)
+        .map(|price| estimated_cost_usd(price, attempt.prompt_tokens, attempt.completion_tokens));
+
+    let inserted = sqlx::query(
+        "INSERT INTO llm_calls (
+            api_key_id, provider, model, prompt_type, prompt_id, prompt_version,
+            session_id, report_id, prompt_tokens, completion_tokens, latency_ms,
+            success, error, estimated_cost_usd
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"
+    )
+    .bind(attempt.api_key_id)
+    .bind(attempt.provider)
+    .bind(attempt.model)
+    .bind(call.prompt_type)
+    .bind(call.prompt.map(|p| p.prompt_id))
+    .bind(call.prompt.map(|p| p.version))
+    .bind(call.context.session_id)
+    .bind(call.context.report_id)
+    .bind(attempt.prompt_tokens)
+    .bind(attempt.completion_tokens)
+    .bind(attempt.latency_ms)
+    .bind(attempt.error.is_none())
+    .bind(attempt.error)
+    .bind(cost)
+    .execute(pool)
+    .await;
+
+    if let Err(e) = inserted {
+        tracing::warn!("Failed to record LLM call: {}", e);
+    }
+
+    if let Some(api_key_id) = attempt.api_key_id {
+        let updated = sqlx::query(
+            "UPDATE api_keys SET usage_count = usage_count + 1, last_used_at = NOW() WHERE id = $1"
+        )
+        .bind(api_key_id)
+        .execute(pool)
+        .await;
+
+        if let Err(e) = updated {
+            tracing::warn!("Failed to update API key usage: {}", e);
+        }
+    }
+}
+
+const BUCKET_COLUMNS: &str = "
+    COUNT(*) AS calls,
+    COUNT(*) FILTER (WHERE NOT success) AS failed_calls,
+    COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens,
+    COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens,
+    COALESCE(SUM(estimated_cost_usd), 0)::DOUBLE PRECISION AS estimated_cost_usd";
+
+// From the start of the WIB day `days - 1` days ago, so the first daily
+// bucket is a whole day
+const USAGE_FILTER: &str = "
+    created_at >= (DATE_TRUNC('day', NOW() AT TIME ZONE 'Asia/Jakarta') - make_interval(days => $1::int - 1)) AT TIME ZONE 'Asia/Jakarta'
+    AND ($2::uuid IS NULL OR api_key_id = $2)";
+
+async fn buckets(
+    pool: &PgPool,
+    key: &str,
+    days: i64,
+    api_key_id: Option<Uuid>,
+) -> Result<Vec<LlmUsageBucket>, sqlx::Error> {
+    sqlx::query_as::<_, LlmUsageBucket>(&format!(
+        "SELECT {} AS key, {} FROM llm_calls WHERE {} GROUP BY 1 ORDER BY 1",
+        key, BUCKET_COLUMNS, USAGE_FILTER
+    ))
+    .bind(days as i32)
+    .bind(api_key_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Usage over the last `days` days, optionally for one API key. Days are
+/// WIB calendar days.
+pub async fn report(pool: &PgPool, days: i64, api_key_id: Option<Uuid>) -> Result<LlmUsageReport, sqlx::Error> {
+    let totals = sqlx::query_as::<_, LlmUsageTotals>(&format!(
+        "SELECT {}, AVG(latency_ms)::DOUBLE PRECISION AS avg_latency_ms FROM llm_calls WHERE {}",
+        BUCKET_COLUMNS, USAGE_FILTER
+    ))
+    .bind(days as i32)
+    .bind(api_key_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(LlmUsageReport {
+        days,
+        totals,
+        daily: buckets(pool, "TO_CHAR(created_at AT TIME ZONE 'Asia/Jakarta', 'YYYY-MM-DD')", days, api_key_id).await?,
+        by_prompt_type: buckets(pool, "COALESCE(prompt_type, 'unknown')", days, api_key_id).await?,
+        by_model: buckets(pool, "provider || '/' || model", days, api_key_id).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(model: &str, prompt: f64, completion: f64) -> LlmModelPrice {
+        LlmModelPrice {
+            model: model.to_string(),
+            prompt_usd_per_million: prompt,
+            completion_usd_per_million: completion,
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn longest_matching_prefix_wins() {
+        let prices = vec![price("gpt-4o", 2.5, 10.0), price("gpt-4o-mini", 0.15, 0.6), price("gpt", 1.0, 1.0)];
+
+        assert_eq!(price_for(&prices, "gpt-4o-mini-2024-07-18").unwrap().model, "gpt-4o-mini");
+        assert_eq!(price_for(&prices, "gpt-4o-2024-08-06").unwrap().model, "gpt-4o");
+        assert_eq!(price_for(&prices, "gpt-3.5-turbo").unwrap().model, "gpt");
+        assert!(price_for(&prices, "claude-3-5-sonnet").is_none());
+        // Prefixes are literal, not patterns
+        assert!(price_for(&[price("gpt_4o", 1.0, 1.0)], "gpt-4o").is_none());
+    }
+
+    #[test]
+    fn cost_is_priced_per_million_tokens() {
+        let mini = price("gpt-4o-mini", 0.15, 0.6);
+
+        assert!((estimated_cost_usd(&mini, Some(1_000_000), Some(500_000)) - 0.45).abs() < 1e-9);
+        assert_eq!(estimated_cost_usd(&mini, None, None), 0.0);
+    }
+}
@@ -1,11 +1,108 @@
 use rwf::prelude::*;
 use rwf::http::websocket::Message;
+use rwf::http::Stream;
 use rwf::controller::WebsocketController;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
 use uuid::Uuid;
+use crate::middleware::auth::LogtoAuthMiddleware;
+use crate::middleware::rbac::denied;
 use crate::models::*;
+use crate::services::{duplicates, extraction};
+use crate::services::llm::LlmService;
+use crate::services::{prompts, pseudonyms};
+use crate::services::providers::{self, CompletionRequest, LlmMessage};
+use crate::services::usage::{self, CallContext, LlmCall};
+use std::time::Instant;
 
-#[derive(Default, macros::WebsocketController)]
-pub struct ChatWebSocketController;
+/// Who is on the other end of a client's sockets and which chat they are in.
+/// RWF addresses sockets by client session and delivers every message to all
+/// of them, so the tabs of one browser share the user and the chat.
+#[derive(Default)]
+struct Connection {
+    /// Open sockets of the client; the entry goes with the last of them
+    sockets: usize,
+    user_id: Option<Uuid>,
+    /// Expiry of the latest token the connection was authenticated with
+    expires_at: i64,
+    chat_session_id: Option<Uuid>,
+}
+
+static CONNECTIONS: Lazy<RwLock<HashMap<SessionId, Connection>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Chat over a WebSocket. Browsers cannot set headers on the handshake, so
+/// the bearer token comes as a `token` parameter or in an `authenticate`
+/// message; nothing else is accepted before it.
+pub struct ChatWebSocketController {
+    auth: LogtoAuthMiddleware,
+}
+
+impl ChatWebSocketController {
+    pub fn new(auth: LogtoAuthMiddleware) -> Self {
+        Self { auth }
+    }
+
+    async fn authenticate(&self, client: &SessionId, token: &str) -> Result<Uuid, Error> {
+        let principal = self.auth.principal_for_token(token).await?;
+        let user_id = principal.user_id();
+        let now = chrono::Utc::now().timestamp();
+
+        let mut connections = CONNECTIONS.write().unwrap();
+        let connection = connections.entry(client.clone()).or_default();
+
+        match connection.user_id {
+            Some(bound) if bound == user_id => {
+                connection.expires_at = connection.expires_at.max(principal.expires_at);
+            }
+            // Another tab would read this user's chat
+            Some(_) if connection.sockets > 0 && connection.expires_at > now => {
+                return Err(Error::new(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "Another user is signed in on this browser session",
+                )));
+            }
+            _ => {
+                connection.user_id = Some(user_id);
+                connection.expires_at = principal.expires_at;
+                connection.chat_session_id = None;
+            }
+        }
+
+        Ok(user_id)
+    }
+}
+
+#[async_trait]
+impl Controller for ChatWebSocketController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        if let Some(token) = request.query().get::<String>("token") {
+            let client = request.session().session_id.clone();
+            if let Err(e) = self.authenticate(&client, &token).await {
+                return Ok(denied(401, &e.to_string()));
+            }
+        }
+
+        WebsocketController::handle(self, request).await
+    }
+
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        let client = request.session().session_id.clone();
+        CONNECTIONS.write().unwrap().entry(client.clone()).or_default().sockets += 1;
+
+        let result = WebsocketController::handle_stream(self, request, stream).await;
+
+        let mut connections = CONNECTIONS.write().unwrap();
+        if let Some(connection) = connections.get_mut(&client) {
+            connection.sockets = connection.sockets.saturating_sub(1);
+            if connection.sockets == 0 {
+                connections.remove(&client);
+            }
+        }
+
+        result
+    }
+}
 
 #[async_trait]
 impl WebsocketController for ChatWebSocketController {
@@ -22,66 +119,177 @@ impl WebsocketController for ChatWebSocketController {
             _ => return Ok(()),
         };
         
-        let chat_session_id = match get_or_create_session(pool, session_id).await {
-            Ok(id) => id,
-            Err(e) => {
-                tracing::error!("Failed to get/create session: {:?}", e);
-                return Ok(());
+        let Ok(ws_msg) = serde_json::from_str::<WsMessage>(&message_text) else {
+            send_error(session_id, "Invalid message");
+            return Ok(());
+        };
+        
+        if let WsMessage::Authenticate { token } = ws_msg {
+            match self.authenticate(session_id, &token).await {
+                Ok(user_id) => send(session_id, &WsMessage::Authenticated { user_id }),
+                Err(e) => {
+                    tracing::warn!("WebSocket authentication failed: {}", e);
+                    send_error(session_id, "Authentication failed");
+                }
             }
+            return Ok(());
+        }
+        
+        let Some((user_id, chat_session_id)) = connection(session_id) else {
+            send_error(session_id, "Not authenticated");
+            return Ok(());
         };
         
-        if let Ok(ws_msg) = serde_json::from_str::<WsMessage>(&message_text) {
-            match ws_msg {
-                WsMessage::UserMessage { content } => {
-                    if let Err(e) = handle_user_message(pool, chat_session_id, session_id, &content).await {
-                        tracing::error!("Error handling user message: {:?}", e);
-                        let error_msg = WsMessage::Error {
-                            message: "Failed to process message".to_string(),
-                        };
-                        if let Ok(json) = serde_json::to_string(&error_msg) {
-                            let _ = Comms::websocket(session_id).send(json);
-                        }
+        match ws_msg {
+            WsMessage::StartSession { anonymity } => match start_session(pool, user_id, anonymity.as_deref()).await {
+                Ok(id) => {
+                    join_session(session_id, id);
+                    send(session_id, &WsMessage::SessionReady { session_id: id, messages: Vec::new() });
+                }
+                Err(e) => {
+                    tracing::error!("Failed to start session: {:?}", e);
+                    send_error(session_id, "Failed to start session");
+                }
+            },
+            WsMessage::ResumeSession { session_id: id } => match resume_session(pool, user_id, id).await {
+                Ok(Some(messages)) => {
+                    join_session(session_id, id);
+                    send(session_id, &WsMessage::SessionReady { session_id: id, messages });
+                }
+                Ok(None) => send_error(session_id, "Chat session not found"),
+                Err(e) => {
+                    tracing::error!("Failed to resume session: {:?}", e);
+                    send_error(session_id, "Failed to resume session");
+                }
+            },
+            WsMessage::UserMessage { content } => {
+                let Some(chat_session_id) = chat_session_id else {
+                    send_error(session_id, "Start or resume a session first");
+                    return Ok(());
+                };
+                
+                if let Err(e) = handle_user_message(pool, chat_session_id, session_id, &content).await {
+                    tracing::error!("Error handling user message: {:?}", e);
+                    send_error(session_id, "Failed to process message");
+                }
+            }
+            WsMessage::ConfirmDuplicate { report_id, candidate_id, same } => {
+                match confirm_duplicate(pool, user_id, report_id, candidate_id, same).await {
+                    // The candidates still open, so the client can ask about the next one
+                    Ok(Some(candidates)) => send(session_id, &WsMessage::DuplicateCandidates { report_id, candidates }),
+                    Ok(None) => send_error(session_id, "Duplicate candidate not found"),
+                    Err(e) => {
+                        tracing::error!("Failed to confirm duplicate: {:?}", e);
+                        send_error(session_id, "Failed to confirm duplicate");
                     }
                 }
-                _ => {}
             }
+            _ => {}
         }
         
         Ok(())
     }
 }
 
-async fn get_or_create_session(
+/// The authenticated user of a client and their current chat, while the
+/// token is still valid
+fn connection(client: &SessionId) -> Option<(Uuid, Option<Uuid>)> {
+    let now = chrono::Utc::now().timestamp();
+
+    CONNECTIONS
+        .read()
+        .unwrap()
+        .get(client)
+        .filter(|connection| connection.expires_at > now)
+        .and_then(|connection| Some((connection.user_id?, connection.chat_session_id)))
+}
+
+fn join_session(client: &SessionId, chat_session_id: Uuid) {
+    if let Some(connection) = CONNECTIONS.write().unwrap().get_mut(client) {
+        connection.chat_session_id = Some(chat_session_id);
+    }
+}
+
+fn send(client: &SessionId, message: &WsMessage) {
+    if let Ok(json) = serde_json::to_string(message) {
+        let _ = Comms::websocket(client).send(json);
+    }
+}
+
+fn send_error(client: &SessionId, message: &str) {
+    send(client, &WsMessage::Error { message: message.to_string() });
+}
+
+async fn start_session(
     pool: &sqlx::PgPool,
-    client: &SessionId,
-) -> Result<Uuid, sqlx::Error> {
-    let client_str = format!("{:?}", client);
-    
-    let existing: Option<(Uuid,)> = sqlx::query_as(
-        "SELECT id FROM chat_sessions 
-         WHERE metadata->>'client_id' = $1 
-         AND status = 'active'
-         ORDER BY created_at DESC
-         LIMIT 1"
+    user_id: Uuid,
+    anonymity: Option<&str>,
+) -> Result<Uuid, Box<dyn std::error::Error>> {
+    let anonymity = if pseudonyms::is_pseudonymous(anonymity)? { "pseudonymous" } else { "identified" };
+    
+    let session_id = sqlx::query_scalar(
+        "INSERT INTO chat_sessions (user_id, status, anonymity)
+         VALUES ($1, 'active', $2)
+         RETURNING id"
     )
-    .bind(&client_str)
+    .bind(user_id)
+    .bind(anonymity)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(session_id)
+}
+
+/// History of one of the user's active chats, or `None` if they have no such chat
+async fn resume_session(
+    pool: &sqlx::PgPool,
+    user_id: Uuid,
+    chat_session_id: Uuid,
+) -> Result<Option<Vec<ChatMessage>>, sqlx::Error> {
+    let owned: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM chat_sessions WHERE id = $1 AND user_id = $2 AND status = 'active'"
+    )
+    .bind(chat_session_id)
+    .bind(user_id)
     .fetch_optional(pool)
     .await?;
     
-    if let Some((session_id,)) = existing {
-        return Ok(session_id);
+    if owned.is_none() {
+        return Ok(None);
     }
     
-    let session_id: Uuid = sqlx::query_scalar(
-        "INSERT INTO chat_sessions (user_id, status, metadata)
-         VALUES ('00000000-0000-0000-0000-000000000000', 'active', $1)
-         RETURNING id"
+    let messages = sqlx::query_as::<_, ChatMessage>(
+        "SELECT * FROM chat_messages WHERE session_id = $1 ORDER BY created_at ASC"
     )
-    .bind(serde_json::json!({ "client_id": client_str }))
-    .fetch_one(pool)
+    .bind(chat_session_id)
+    .fetch_all(pool)
     .await?;
     
-    Ok(session_id)
+    Ok(Some(messages))
+}
+
+/// Records the citizen's answer on a candidate duplicate of their report and
+/// returns the candidates left open, or `None` if there is no such candidate
+async fn confirm_duplicate(
+    pool: &sqlx::PgPool,
+    user_id: Uuid,
+    report_id: Uuid,
+    candidate_id: Uuid,
+    same: bool,
+) -> Result<Option<Vec<DuplicateCandidate>>, Box<dyn std::error::Error>> {
+    let master = pseudonyms::master_key()?;
+    let reporters = pseudonyms::reporter_ids(pool, &master, user_id).await?;
+    
+    if !duplicates::answer(pool, report_id, candidate_id, &reporters, same).await? {
+        return Ok(None);
+    }
+    
+    let candidates = duplicates::candidates(pool, report_id, &reporters).await?
+        .into_iter()
+        .filter(|c| c.citizen_confirmed.is_none())
+        .collect();
+    
+    Ok(Some(candidates))
 }
 
 async fn handle_user_message(
@@ -117,30 +325,28 @@ async fn handle_user_message(
     .fetch_all(pool)
     .await?;
     
-    let system_prompt = sqlx::query_as::<_, SystemPrompt>(
-        "SELECT * FROM system_prompts 
-         WHERE prompt_type = 'chat_assistant' 
-         AND is_active = true 
-         ORDER BY version DESC 
-         LIMIT 1"
-    )
-    .fetch_optional(pool)
-    .await?;
+    let system_prompt = match prompts::load_active(pool, "chat_assistant").await? {
+        Some(prompt) => match prompts::render(&prompt, &[]) {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                tracing::warn!("Chat assistant prompt unusable: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
     
+    let prompt_source = system_prompt.as_ref().map(|p| p.source.clone());
     let prompt_text = system_prompt
-        .map(|p| p.prompt_text)
+        .map(|p| p.text)
         .unwrap_or_else(|| "Anda adalah asisten yang membantu.".to_string());
     
-    let api_key = sqlx::query_as::<_, ApiKey>(
-        "SELECT * FROM api_keys 
-         WHERE is_active = true 
-         ORDER BY created_at DESC 
-         LIMIT 1"
-    )
-    .fetch_optional(pool)
-    .await?;
+    let config = crate::config::Config::load()
+        .map_err(|e| std::io::Error::other(format!("Config error: {}", e)))?;
+    
+    let provider = providers::active(pool, &config.llm).await?;
     
-    if api_key.is_none() {
+    let Some(provider) = provider else {
         let error_msg = WsMessage::Error {
             message: "No active API key configured".to_string(),
         };
@@ -148,95 +354,107 @@ async fn handle_user_message(
             Comms::websocket(client).send(json)?;
         }
         return Ok(());
-    }
+    };
     
-    let api_key = api_key.unwrap();
+    // Names the citizen mentioned earlier are redacted along with the patterns
+    let persons = extraction::known_persons(pool, session_id).await?;
     
-    let response = call_llm_api(&api_key, &prompt_text, &messages, content).await?;
+    // The history already ends with the message stored above.
+    let request = CompletionRequest {
+        system: Some(prompt_text),
+        messages: messages
+            .iter()
+            .map(|m| LlmMessage {
+                role: m.role.clone(),
+                content: m.content.clone(),
+            })
+            .collect(),
+        temperature: Some(0.7),
+        max_tokens: None,
+        sensitive_terms: persons.clone(),
+        prompt_type: Some("chat_assistant".to_string()),
+    };
     
-    sqlx::query(
-        "INSERT INTO chat_messages (session_id, role, content)
-         VALUES ($1, 'assistant', $2)"
+    let context = CallContext {
+        session_id: Some(session_id),
+        ..Default::default()
+    };
+    
+    // Deltas are pushed as they arrive; a failed send means the socket is gone.
+    let started = Instant::now();
+    let mut streamed = String::new();
+    let mut connected = true;
+    let result = provider.stream(&request, &mut |delta: &str| {
+        streamed.push_str(delta);
+        let delta_msg = WsMessage::AssistantDelta {
+            content: delta.to_string(),
+        };
+        if let Ok(json) = serde_json::to_string(&delta_msg) {
+            connected = Comms::websocket(client).send(json).is_ok();
+        }
+        connected
+    }).await;
+    
+    usage::record(pool, LlmCall {
+        context: &context,
+        provider: provider.as_ref(),
+        prompt_type: "chat_assistant",
+        prompt: prompt_source.as_ref(),
+        started,
+        result: result.as_ref(),
+    })
+    .await;
+    
+    let (response, error) = match result {
+        Ok(completion) => (completion.content, None),
+        Err(e) if streamed.is_empty() => return Err(e.into()),
+        Err(e) => (streamed, Some(e)),
+    };
+    
+    // Keep whatever the citizen already saw, even if the answer was cut off.
+    let partial = error.is_some() || !connected;
+    let message_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO chat_messages (session_id, role, content, metadata)
+         VALUES ($1, 'assistant', $2, $3)
+         RETURNING id"
     )
     .bind(session_id)
     .bind(&response)
-    .execute(pool)
+    .bind(serde_json::json!({ "partial": partial, "prompt": prompt_source }))
+    .fetch_one(pool)
     .await?;
     
-    sqlx::query(
-        "UPDATE api_keys SET usage_count = usage_count + 1, last_used_at = NOW() WHERE id = $1"
-    )
-    .bind(api_key.id)
-    .execute(pool)
-    .await?;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    
+    if !connected {
+        return Ok(());
+    }
     
-    let assistant_msg = WsMessage::AssistantMessage {
+    let done_msg = WsMessage::AssistantDone {
+        message_id,
         content: response,
     };
-    if let Ok(json) = serde_json::to_string(&assistant_msg) {
+    if let Ok(json) = serde_json::to_string(&done_msg) {
         Comms::websocket(client).send(json)?;
     }
     
-    check_completeness(pool, session_id, client).await?;
+    let llm = LlmService::new(provider.clone())
+        .with_retries(config.llm.structured_retries)
+        .with_context(context)
+        .with_sensitive_terms(persons);
+    check_completeness(pool, session_id, client, &llm, config.ner_processing_enabled).await?;
     
     Ok(())
 }
 
-async fn call_llm_api(
-    api_key: &ApiKey,
-    system_prompt: &str,
-    history: &[ChatMessage],
-    user_message: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    
-    let base_url = api_key.base_url.as_deref().unwrap_or("https://openrouter.ai/api/v1");
-    
-    let mut messages_payload = vec![
-        serde_json::json!({
-            "role": "system",
-            "content": system_prompt
-        })
-    ];
-    
-    for msg in history {
-        messages_payload.push(serde_json::json!({
-            "role": msg.role,
-            "content": msg.content
-        }));
-    }
-    
-    messages_payload.push(serde_json::json!({
-        "role": "user",
-        "content": user_message
-    }));
-    
-    let response = client
-        .post(format!("{}/chat/completions", base_url))
-        .header("Authorization", format!("Bearer {}", api_key.api_key))
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({
-            "model": "openai/gpt-3.5-turbo",
-            "messages": messages_payload,
-            "temperature": 0.7
-        }))
-        .send()
-        .await?;
-    
-    let result: serde_json::Value = response.json().await?;
-    
-    let content = result["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("Error processing response")
-        .to_string();
-    
-    Ok(content)
-}
-
 async fn check_completeness(
     pool: &sqlx::PgPool,
     session_id: Uuid,
     client: &SessionId,
+    llm: &LlmService,
+    ner_enabled: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let messages: Vec<ChatMessage> = sqlx::query_as(
         "SELECT * FROM chat_messages WHERE session_id = $1 ORDER BY created_at ASC"
@@ -251,52 +469,59 @@ async fn check_completeness(
         .collect::<Vec<_>>()
         .join("\n");
     
-    let report_count: i64 = sqlx::query_scalar(
-        "SELECT COUNT(*) FROM reports WHERE session_id = $1"
-    )
-    .bind(session_id)
-    .fetch_one(pool)
-    .await?;
+    // Too early to extract anything useful from a greeting.
+    if conversation.len() <= 100 {
+        return Ok(());
+    }
     
-    if report_count == 0 && conversation.len() > 100 {
-        if conversation.contains("jalan") || conversation.contains("rusak") {
-            let title = "Laporan dari percakapan".to_string();
-            let description = conversation.lines().take(5).collect::<Vec<_>>().join(" ");
-            
-            let report_id: Uuid = sqlx::query_scalar(
-                "INSERT INTO reports (
-                    session_id, user_id, title, description, 
-                    status, is_complete, completeness_score
-                ) VALUES ($1, '00000000-0000-0000-0000-000000000000', $2, $3, 'draft', false, 0.5)
-                RETURNING id"
-            )
-            .bind(session_id)
-            .bind(title)
-            .bind(description)
-            .fetch_one(pool)
-            .await?;
-            
-            let ticket_number = format!("TKT-{}", &report_id.to_string()[..8].to_uppercase());
-            
-            let completeness_msg = WsMessage::CompletenessCheck {
-                is_complete: false,
-                score: 0.5,
-                missing_fields: vec!["location".to_string(), "incident_date".to_string()],
-                suggestions: vec!["Mohon sebutkan lokasi spesifik".to_string()],
-            };
-            if let Ok(json) = serde_json::to_string(&completeness_msg) {
-                Comms::websocket(client).send(json)?;
-            }
-            
-            let created_msg = WsMessage::ReportCreated {
-                report_id,
-                ticket_number,
-            };
-            if let Ok(json) = serde_json::to_string(&created_msg) {
-                Comms::websocket(client).send(json)?;
-            }
+    let sync = match extraction::sync_draft_report(pool, llm, session_id, &conversation).await {
+        Ok(Some(sync)) => sync,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            // The answer was already delivered; a bad extraction only delays the draft.
+            tracing::warn!("Report extraction failed for session {}: {:?}", session_id, e);
+            return Ok(());
+        }
+    };
+    
+    if ner_enabled
+        && let Err(e) = extraction::sync_entities(pool, &llm.for_report(sync.report_id), session_id, sync.report_id, &conversation).await
+    {
+        tracing::warn!("Entity extraction failed for session {}: {:?}", session_id, e);
+    }
+    
+    let completeness_msg = WsMessage::CompletenessCheck {
+        is_complete: sync.completeness.is_complete,
+        score: sync.completeness.completeness_score,
+        missing_fields: sync.completeness.missing_fields,
+        suggestions: sync.completeness.suggestions,
+    };
+    if let Ok(json) = serde_json::to_string(&completeness_msg) {
+        Comms::websocket(client).send(json)?;
+    }
+    
+    if sync.created {
+        let ticket_number = format!("TKT-{}", &sync.report_id.to_string()[..8].to_uppercase());
+        let created_msg = WsMessage::ReportCreated {
+            report_id: sync.report_id,
+            ticket_number,
+        };
+        if let Ok(json) = serde_json::to_string(&created_msg) {
+            Comms::websocket(client).send(json)?;
+        }
+    }
+    
+    // Asked once per candidate, as the draft gains a location, category or date
+    let candidates = duplicates::detect(pool, sync.report_id).await?;
+    if !candidates.is_empty() {
+        let duplicates_msg = WsMessage::DuplicateCandidates {
+            report_id: sync.report_id,
+            candidates,
+        };
+        if let Ok(json) = serde_json::to_string(&duplicates_msg) {
+            Comms::websocket(client).send(json)?;
         }
     }
     
     Ok(())
-}
\ No newline at end of file
+}